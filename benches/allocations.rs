@@ -0,0 +1,296 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{
+    criterion_group, criterion_main,
+    measurement::{Measurement, ValueFormatter},
+    Criterion, Throughput,
+};
+use mca::{CompressionLevel, CompressionType, RegionReader, RegionWriter};
+
+const REGION: &[u8] = include_bytes!("r.0.0.mca");
+
+/// Counts every allocation & reallocation and tracks the live & peak heap size,
+/// the global allocator has to live in its own bench binary
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn grow(by: usize) {
+    let live = LIVE.fetch_add(by, Ordering::Relaxed) + by;
+    PEAK.fetch_max(live, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        grow(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        grow(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Measures benchmarks in allocations instead of time
+struct Allocations;
+
+impl Measurement for Allocations {
+    type Intermediate = usize;
+    type Value = usize;
+
+    fn start(&self) -> usize {
+        ALLOCATIONS.load(Ordering::Relaxed)
+    }
+
+    fn end(&self, start: usize) -> usize {
+        ALLOCATIONS.load(Ordering::Relaxed) - start
+    }
+
+    fn add(&self, v1: &usize, v2: &usize) -> usize {
+        v1 + v2
+    }
+
+    fn zero(&self) -> usize {
+        0
+    }
+
+    fn to_f64(&self, value: &usize) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &AllocationFormatter
+    }
+}
+
+/// Measures benchmarks in the most heap bytes held on top of what was live before
+///
+/// A peak doesn't add up over iterations like time does, so benchmarks measure every
+/// iteration on its own with [`peak_during`] in `iter_custom`.
+struct PeakHeap;
+
+impl Measurement for PeakHeap {
+    type Intermediate = usize;
+    type Value = usize;
+
+    fn start(&self) -> usize {
+        let base = LIVE.load(Ordering::Relaxed);
+        PEAK.store(base, Ordering::Relaxed);
+        base
+    }
+
+    fn end(&self, base: usize) -> usize {
+        PEAK.load(Ordering::Relaxed) - base
+    }
+
+    fn add(&self, v1: &usize, v2: &usize) -> usize {
+        v1 + v2
+    }
+
+    fn zero(&self) -> usize {
+        0
+    }
+
+    fn to_f64(&self, value: &usize) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &BytesFormatter
+    }
+}
+
+/// Heap bytes allocated on top of what was live before `f` ran, at its peak
+fn peak_during(f: impl FnOnce()) -> usize {
+    let base = PeakHeap.start();
+    f();
+    PeakHeap.end(base)
+}
+
+struct AllocationFormatter;
+
+impl ValueFormatter for AllocationFormatter {
+    fn scale_values(&self, _: f64, _: &mut [f64]) -> &'static str {
+        "allocs"
+    }
+
+    fn scale_throughputs(
+        &self,
+        _: f64,
+        throughput: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        match throughput {
+            Throughput::Elements(n) => {
+                values.iter_mut().for_each(|v| *v /= *n as f64);
+                "allocs/chunk"
+            }
+            _ => "allocs",
+        }
+    }
+
+    fn scale_for_machines(&self, _: &mut [f64]) -> &'static str {
+        "allocs"
+    }
+}
+
+struct BytesFormatter;
+
+impl ValueFormatter for BytesFormatter {
+    fn scale_values(&self, typical_value: f64, values: &mut [f64]) -> &'static str {
+        let (factor, unit) = match typical_value {
+            v if v < 1024.0 => (1.0, "B"),
+            v if v < 1024.0 * 1024.0 => (1024.0, "KiB"),
+            _ => (1024.0 * 1024.0, "MiB"),
+        };
+        values.iter_mut().for_each(|v| *v /= factor);
+        unit
+    }
+
+    fn scale_throughputs(
+        &self,
+        typical_value: f64,
+        _: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        self.scale_values(typical_value, values)
+    }
+
+    fn scale_for_machines(&self, _: &mut [f64]) -> &'static str {
+        "B"
+    }
+}
+
+fn decompressed() -> Vec<(Vec<u8>, (u8, u8))> {
+    let region = RegionReader::new(REGION).unwrap();
+    let mut raw = vec![];
+
+    for (idx, chunk) in region.iter().enumerate() {
+        if let Some(chunk) = chunk.unwrap() {
+            raw.push((
+                chunk.decompress().unwrap(),
+                ((idx % 32) as u8, (idx / 32) as u8),
+            ));
+        }
+    }
+
+    raw
+}
+
+fn convert_allocations(c: &mut Criterion<Allocations>) {
+    let raw = decompressed();
+    // what converting one region of a world costs, pushing every chunk & writing it out
+    let convert = |writer: &mut RegionWriter, buf: &mut Vec<u8>| {
+        for (data, coordinate) in raw.iter() {
+            writer
+                .push_chunk_with_timestamp(data, *coordinate, CompressionType::Zlib, 0)
+                .unwrap();
+        }
+        buf.clear();
+        writer.write(buf).unwrap();
+    };
+    let mut buf = Vec::with_capacity(REGION.len());
+
+    let mut group = c.benchmark_group("convert_region");
+    group.throughput(Throughput::Elements(raw.len() as u64));
+    group.sample_size(10);
+    // every chunk compressed on its own, the allocations the scratch buffers save
+    group.bench_function("without_scratch", |b| {
+        b.iter(|| {
+            raw.iter()
+                .map(|(data, _)| {
+                    CompressionType::Zlib
+                        .compress_with_level(data, CompressionLevel::default())
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("new_writer", |b| {
+        b.iter(|| convert(&mut RegionWriter::with_capacity(raw.len()), &mut buf))
+    });
+    let mut writer = RegionWriter::with_capacity(raw.len());
+    group.bench_function("reused_writer", |b| {
+        b.iter(|| {
+            writer.clear_and_reuse();
+            convert(&mut writer, &mut buf);
+        })
+    });
+    group.finish();
+}
+
+fn write_peak_heap(c: &mut Criterion<PeakHeap>) {
+    let region = RegionReader::new(REGION).unwrap();
+    let writer = RegionWriter::from_region(&region).unwrap();
+    let mut buf = Vec::with_capacity(REGION.len());
+
+    // payloads stream straight into the sink, nothing near the region size should be held
+    let mut group = c.benchmark_group("write_peak_heap");
+    group.sample_size(10);
+    group.bench_function("sink", |b| {
+        b.iter_custom(|iters| {
+            (0..iters)
+                .map(|_| {
+                    peak_during(|| {
+                        writer.write(&mut std::io::sink()).unwrap();
+                    })
+                })
+                .sum()
+        })
+    });
+    group.bench_function("presized_vec", |b| {
+        b.iter_custom(|iters| {
+            (0..iters)
+                .map(|_| {
+                    buf.clear();
+                    peak_during(|| {
+                        writer.write(&mut buf).unwrap();
+                    })
+                })
+                .sum()
+        })
+    });
+    group.bench_function("into_bytes", |b| {
+        b.iter_custom(|iters| {
+            (0..iters)
+                .map(|_| {
+                    let writer = writer.clone();
+                    peak_during(|| {
+                        writer.into_bytes().unwrap();
+                    })
+                })
+                .sum()
+        })
+    });
+    group.finish();
+}
+
+// every sample of a deterministic measurement is the same, which the plots can't handle
+criterion_group! {
+    name = allocations;
+    config = Criterion::default().with_measurement(Allocations).without_plots();
+    targets = convert_allocations
+}
+criterion_group! {
+    name = peak_heap;
+    config = Criterion::default().with_measurement(PeakHeap).without_plots();
+    targets = write_peak_heap
+}
+criterion_main!(allocations, peak_heap);
@@ -0,0 +1,116 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use mca::{RegionReader, RegionWriter};
+
+const REGION: &[u8] = include_bytes!("r.0.0.mca");
+
+fn recompress() {
+    let region = RegionReader::new(REGION).unwrap();
+    let mut writer = RegionWriter::new();
+
+    for (idx, chunk) in region.iter().enumerate() {
+        if let Some(chunk) = chunk.unwrap() {
+            let data = chunk.decompress().unwrap();
+            writer
+                .push_chunk_with_compression(
+                    &data,
+                    ((idx % 32) as u8, (idx / 32) as u8),
+                    chunk.get_compression_type(),
+                )
+                .unwrap();
+        }
+    }
+}
+
+fn precompressed() {
+    let region = RegionReader::new(REGION).unwrap();
+    let mut writer = RegionWriter::new();
+
+    for (idx, chunk) in region.iter().enumerate() {
+        if let Some(chunk) = chunk.unwrap() {
+            writer
+                .push_precompressed_chunk(
+                    chunk.raw_data.to_vec(),
+                    chunk.get_compression_type(),
+                    ((idx % 32) as u8, (idx / 32) as u8),
+                    0,
+                )
+                .unwrap();
+        }
+    }
+}
+
+fn decompressed() -> Vec<(Vec<u8>, (u8, u8))> {
+    let region = RegionReader::new(REGION).unwrap();
+    let mut raw = vec![];
+
+    for (idx, chunk) in region.iter().enumerate() {
+        if let Some(chunk) = chunk.unwrap() {
+            raw.push((
+                chunk.decompress().unwrap(),
+                ((idx % 32) as u8, (idx / 32) as u8),
+            ));
+        }
+    }
+
+    raw
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("copy_region");
+    group.sample_size(10);
+    group.bench_function("recompress", |b| b.iter(recompress));
+    group.bench_function("precompressed", |b| b.iter(precompressed));
+    group.finish();
+
+    let region = RegionReader::new(REGION).unwrap();
+    let mut writer = RegionWriter::new();
+    for (idx, chunk) in region.iter().enumerate() {
+        if let Some(chunk) = chunk.unwrap() {
+            let coordinate = ((idx % 32) as u8, (idx / 32) as u8);
+            writer
+                .push_precompressed_chunk(
+                    chunk.raw_data.to_vec(),
+                    chunk.get_compression_type(),
+                    coordinate,
+                    0,
+                )
+                .unwrap();
+        }
+    }
+    let mut buf = Vec::with_capacity(REGION.len());
+    c.bench_function("write_region", |b| {
+        b.iter(|| {
+            buf.clear();
+            writer.write(&mut buf).unwrap();
+        })
+    });
+
+    // run with `--features rayon` to compare against the parallel batch push
+    let raw = decompressed();
+    let batch = raw
+        .iter()
+        .map(|(d, c)| (d.as_slice(), *c))
+        .collect::<Vec<_>>();
+    let mut group = c.benchmark_group("compress_region");
+    group.sample_size(10);
+    group.bench_function("serial", |b| {
+        b.iter(|| {
+            let mut writer = RegionWriter::new();
+            for (data, coordinate) in raw.iter() {
+                writer.push_chunk(data, *coordinate).unwrap();
+            }
+            writer.write(&mut vec![]).unwrap();
+        })
+    });
+    group.bench_function("batch", |b| {
+        b.iter(|| {
+            let mut writer = RegionWriter::new();
+            writer.push_chunks(&batch).unwrap();
+            writer.write(&mut vec![]).unwrap();
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
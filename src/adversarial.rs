@@ -0,0 +1,285 @@
+//! Feeds hostile regions through every entry point taking untrusted bytes, none of them may panic.
+
+use std::io::Cursor;
+
+use crate::{
+    backup, compact_region, compat::conformance_issue, dict, extract_area, layout, merge3,
+    merge_regions, retain, CacheLimit, CachedRegion, CompressionType, ConflictPolicy, Lz4Framing,
+    McaError, MccFile, MergePolicy, RegionEditor, RegionFileEditor, RegionReader, RegionWriter,
+};
+
+const REGION: &[u8] = include_bytes!("../benches/r.0.0.mca");
+
+/// xorshift, the inputs only have to be reproducible, not good
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+/// A small valid region with every compression byte a payload can carry
+fn small_region() -> Vec<u8> {
+    let mut writer = RegionWriter::with_fixed_timestamp(1);
+    for (i, compression) in [
+        CompressionType::Zlib,
+        CompressionType::LZ4,
+        CompressionType::Uncompressed,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        writer
+            .push_chunk_with_compression(&[10, 0, 0, i as u8], (i as u8, 0), compression)
+            .unwrap();
+    }
+
+    let mut buf = vec![];
+    writer.write(&mut buf).unwrap();
+    buf
+}
+
+/// Regions ranging from cut off & random to valid ones with a few corrupted bytes
+fn hostile_regions(rng: &mut Rng) -> Vec<Vec<u8>> {
+    let small = small_region();
+    let mut regions = vec![
+        vec![],
+        vec![0; layout::HEADER_SIZE - 1],
+        vec![0xFF; layout::HEADER_SIZE],
+        vec![0xFF; layout::HEADER_SIZE + layout::SECTOR_SIZE],
+        small[..small.len() - 1].to_vec(),
+        small[..layout::HEADER_SIZE + 3].to_vec(),
+    ];
+
+    for _ in 0..16 {
+        let len = layout::HEADER_SIZE + rng.below(4 * layout::SECTOR_SIZE);
+        regions.push(rng.bytes(len));
+    }
+
+    // the vanilla region is large & slow to rewrite, a few of it go a long way
+    for (base, count) in [(&small[..], 32), (REGION, 4)] {
+        for _ in 0..count {
+            let mut region = base.to_vec();
+            // mostly the header & payload heads, where the offsets & lengths are
+            for _ in 0..1 + rng.below(8) {
+                let at = match rng.below(3) {
+                    0 => rng.below(layout::HEADER_SIZE),
+                    1 => layout::HEADER_SIZE + rng.below(8),
+                    _ => rng.below(region.len()),
+                };
+                region[at] = rng.next() as u8;
+            }
+            if rng.below(4) == 0 {
+                region.truncate(rng.below(region.len()));
+            }
+            regions.push(region);
+        }
+    }
+
+    // every payload length & compression byte pointing at edge cases
+    for (length, compression) in [
+        ([0, 0, 0, 0], 2),
+        ([0, 0, 0, 1], 2),
+        ([0xFF, 0xFF, 0xFF, 0xFF], 2),
+        ([0, 0, 0x10, 0], 2),
+        ([0, 0, 0, 5], 0),
+        ([0, 0, 0, 5], 0xFF),
+        ([0, 0, 0, 5], 1),
+        ([0, 0, 0, 5], 127),
+        ([0, 0, 0, 5], 0x82),
+    ] {
+        let mut region = small.clone();
+        region[layout::HEADER_SIZE..layout::HEADER_SIZE + 4].copy_from_slice(&length);
+        region[layout::HEADER_SIZE + 4] = compression;
+        regions.push(region);
+    }
+
+    // locations pointing into the header, past the end & at the largest offset
+    for location in [
+        [0, 0, 0, 1],
+        [0, 0, 1, 0xFF],
+        [0, 0, 0xFF, 1],
+        [0xFF, 0xFF, 0xFF, 0xFF],
+        [0, 0, 2, 0],
+    ] {
+        let mut region = small.clone();
+        region[..4].copy_from_slice(&location);
+        regions.push(region);
+    }
+
+    regions
+}
+
+/// Every read only entry point, results are ignored since only panics matter
+fn read_everything(region: &RegionReader) {
+    for x in 0..34 {
+        for z in [0, 1, 31, 32, usize::MAX] {
+            let _ = region.get_chunk(x, z).map(|c| c.map(|c| c.decompress()));
+            let _ = region.padding_bytes(x, z);
+            let _ = region.get_chunk_timestamp(x, z);
+            let _ = region.occupancy().get(x, z);
+        }
+    }
+    // the `unsafe` feature skips the bounds checks on raw header offsets
+    #[cfg(not(feature = "unsafe"))]
+    for offset in [0, layout::SECTOR_SIZE - 4, layout::HEADER_SIZE, usize::MAX] {
+        let _ = region.get_location(offset);
+        let _ = region.get_timestamp(offset);
+    }
+
+    let _ = region.occupancy().to_string();
+    let _ = region.vanilla_compatibility();
+    let _ = region.gap_extents();
+    let _ = region.expected_len();
+    let _ = region.iter().count();
+    let _ = region.iter_from(40, usize::MAX).count();
+    let _ = region.iter_from_index(usize::MAX).count();
+    let _ = conformance_issue(region.inner());
+
+    let mut cached = CachedRegion::new(region.clone(), CacheLimit::Entries(4));
+    for x in 0..33 {
+        let _ = cached.get_decompressed(x, 0);
+    }
+}
+
+/// Every entry point building something new out of a region
+fn rewrite_everything(region: &RegionReader, other: &RegionReader) {
+    let data = region.inner();
+    let _ = compact_region(data);
+    let _ = extract_area(region, 0..40, 3..9);
+    let _ = retain(region, |x, _, _, _| x % 2 == 0);
+    let _ = merge_regions(region, other, MergePolicy::NewestWins);
+    let _ = merge3(region, other, region, ConflictPolicy::NewestWins);
+    let _ = backup::delta_region(region, 0);
+    let _ = backup::apply_delta(other, region);
+    let _ = dict::collect_samples([region.clone()].into_iter(), 8, 1 << 20);
+    #[cfg(feature = "fastnbt")]
+    let _ = crate::relocate_region(
+        data,
+        crate::RegionPos::new(0, 0),
+        crate::RegionPos::new(1, -1),
+    );
+
+    if let Ok(mut writer) = RegionWriter::from_region(region) {
+        writer.preserve_layout(region);
+        let _ = writer.push_chunk(&[10, 0, 0, 0], (5, 5));
+        let _ = writer.write(&mut vec![]);
+        let _ = writer.write_into(&mut [0; layout::HEADER_SIZE]);
+    }
+
+    if let Ok(mut editor) = RegionEditor::new(data.to_vec()) {
+        let _ = editor.set_timestamp(40, 0, 1);
+        let _ = editor.remove_chunk(0, 40);
+        let _ = editor.into_bytes();
+    }
+
+    if let Ok(mut editor) = RegionFileEditor::open(Cursor::new(data.to_vec())) {
+        let _ = editor.set_chunk(1, 0, &[10, 0, 0, 0], CompressionType::Zlib);
+        let _ = editor.set_chunk(32, 0, &[10, 0, 0, 0], CompressionType::Zlib);
+        let _ = editor.remove_chunk(2, 0);
+        let _ = editor.touch_timestamps(&[((3, 0), 7), ((0, 99), 7)]);
+        let _ = editor.defragment_in_place();
+    }
+}
+
+#[test]
+fn hostile_regions_never_panic() {
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    let small = small_region();
+    let valid = RegionReader::new(&small).unwrap();
+    assert!(matches!(
+        RegionReader::chunk_offset(32, 0),
+        Err(McaError::InvalidCoordinate { x: 32, z: 0 })
+    ));
+    assert!(matches!(
+        valid.get_chunk(0, usize::MAX),
+        Err(McaError::InvalidCoordinate { .. })
+    ));
+    assert_eq!(valid.get_chunk_timestamp(0, 32), 0);
+    assert!(!valid.occupancy().get(32, 0));
+
+    let mut unknown = small.clone();
+    unknown[layout::HEADER_SIZE + 4] = 9;
+    assert!(matches!(
+        RegionReader::new(&unknown).unwrap().get_chunk(0, 0),
+        Err(McaError::UnknownCompression(9))
+    ));
+
+    for data in hostile_regions(&mut rng) {
+        for region in [RegionReader::new(&data), RegionReader::new_strict(&data)]
+            .into_iter()
+            .flatten()
+        {
+            read_everything(&region);
+            rewrite_everything(&region, &valid);
+            rewrite_everything(&valid, &region);
+        }
+    }
+}
+
+#[test]
+fn hostile_payloads_never_panic() {
+    let mut rng = Rng(0xD1B5_4A32_D192_ED03);
+
+    for id in 0..=u8::MAX {
+        match CompressionType::try_from(id) {
+            Ok(compression) => assert_eq!(compression.to_u8(), id),
+            Err(e) => assert!(matches!(e, McaError::UnknownCompression(i) if i == id)),
+        }
+    }
+    for compression in [CompressionType::GZip, CompressionType::Custom] {
+        assert!(matches!(
+            compression.compress(&[1, 2, 3]),
+            Err(McaError::UnimplementedCompression(c)) if c == compression
+        ));
+        assert!(compression.decompress(&[1, 2, 3]).is_err());
+    }
+
+    let lz4 = CompressionType::LZ4.compress(&[7; 300]).unwrap();
+    let zlib = CompressionType::Zlib.compress(&[7; 300]).unwrap();
+    for _ in 0..200 {
+        let len = rng.below(64);
+        let mut inputs = vec![rng.bytes(len)];
+        for valid in [&lz4, &zlib] {
+            let mut corrupt = valid.clone();
+            let at = rng.below(corrupt.len());
+            corrupt[at] = rng.next() as u8;
+            corrupt.truncate(at.max(rng.below(corrupt.len() + 1)));
+            inputs.push(corrupt);
+        }
+
+        for data in inputs {
+            let _ = Lz4Framing::detect(&data);
+            for compression in [
+                CompressionType::Zlib,
+                CompressionType::LZ4,
+                CompressionType::Uncompressed,
+            ] {
+                let _ = compression.decompress(&data);
+            }
+        }
+    }
+
+    for name in [
+        "c.",
+        "c..mcc",
+        "c.9999999999.0.mcc",
+        "c.-.-.mcc",
+        "c.0.0.mcc.mcc",
+        "ä.1.2.mcc",
+    ] {
+        let _ = MccFile::from_bytes(name, CompressionType::Zlib, vec![]);
+    }
+}
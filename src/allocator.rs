@@ -0,0 +1,183 @@
+use std::{fmt::Debug, ops::Range};
+
+/// Picks the sectors a [`RegionFileEditor`](crate::RegionFileEditor) writes a replaced chunk to,
+/// see [`set_allocator`](crate::RegionFileEditor::set_allocator)
+///
+/// The old sectors of the chunk being replaced are still in use while allocating,
+/// so a crash before the header is patched never loses the old payload.
+///
+/// ## Example
+/// ```ignore
+/// /// Reuses a gap only if it fits exactly, appends otherwise
+/// #[derive(Debug)]
+/// struct ExactFit;
+///
+/// impl SectorAllocator for ExactFit {
+///     fn allocate(&mut self, count: usize, free: &[Range<usize>]) -> Option<usize> {
+///         free.iter().find(|r| r.len() == count).map(|r| r.start)
+///     }
+/// }
+///
+/// editor.set_allocator(ExactFit);
+/// ```
+pub trait SectorAllocator: Debug + Send + Sync {
+    /// Picks where `count` sectors go out of the `free` sector ranges, in file order  
+    /// Returns the first sector to use, which has to start `count` free sectors inside one of the ranges,
+    /// or `None` to append after the last sector in use.
+    fn allocate(&mut self, count: usize, free: &[Range<usize>]) -> Option<usize>;
+}
+
+/// The first free range big enough, what vanilla does  
+/// Keeps files compact, the default of [`RegionFileEditor`](crate::RegionFileEditor)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirstFit;
+
+impl SectorAllocator for FirstFit {
+    fn allocate(&mut self, count: usize, free: &[Range<usize>]) -> Option<usize> {
+        free.iter().find(|r| r.len() >= count).map(|r| r.start)
+    }
+}
+
+/// The smallest free range big enough, the earliest of those on ties  
+/// Leaves the fewest unusably small gaps behind
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BestFit;
+
+impl SectorAllocator for BestFit {
+    fn allocate(&mut self, count: usize, free: &[Range<usize>]) -> Option<usize> {
+        free.iter()
+            .filter(|r| r.len() >= count)
+            .min_by_key(|r| r.len())
+            .map(|r| r.start)
+    }
+}
+
+/// Always appends, freed sectors are never written again  
+/// The fastest and a crash never touches an old payload, the file only grows until it's defragmented
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AppendOnly;
+
+impl SectorAllocator for AppendOnly {
+    fn allocate(&mut self, _count: usize, _free: &[Range<usize>]) -> Option<usize> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        layout::{self, SECTOR_SIZE},
+        CompressionType, McaError, RegionFileEditor, RegionReader, RegionWriter,
+    };
+    use std::io::Cursor;
+
+    // uncompressed data taking exactly `sectors` sectors
+    fn data(sectors: usize, fill: u8) -> Vec<u8> {
+        vec![fill; sectors * SECTOR_SIZE - 5]
+    }
+
+    fn location(buf: &[u8], x: usize) -> (usize, usize) {
+        let offset = RegionReader::chunk_offset(x, 0).unwrap();
+        layout::decode_location(buf[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn strategies() {
+        let mut writer = RegionWriter::with_fixed_timestamp(1);
+        for (x, sectors) in [3, 1, 1, 1, 2, 1].into_iter().enumerate() {
+            writer
+                .push_chunk_with_compression(
+                    &data(sectors, x as u8),
+                    (x as u8, 0),
+                    CompressionType::Uncompressed,
+                )
+                .unwrap();
+        }
+        let mut base = vec![];
+        writer.write(&mut base).unwrap();
+
+        fn edit(base: &[u8], allocator: impl SectorAllocator + 'static) -> Vec<u8> {
+            let mut editor = RegionFileEditor::open(Cursor::new(base.to_vec())).unwrap();
+            editor.set_allocator(allocator);
+            // leaves gaps of 3 sectors at 2 & 1 sector at 6
+            editor.remove_chunk(0, 0).unwrap();
+            editor.remove_chunk(2, 0).unwrap();
+            editor
+                .set_chunk(6, 0, &data(1, 6), CompressionType::Uncompressed)
+                .unwrap();
+            editor
+                .set_chunk(7, 0, &data(2, 7), CompressionType::Uncompressed)
+                .unwrap();
+            editor.into_inner().into_inner()
+        }
+
+        let first = edit(&base, FirstFit);
+        assert_eq!(location(&first, 6), (2, 1));
+        assert_eq!(location(&first, 7), (3, 2));
+        assert_eq!(first.len(), base.len());
+
+        let best = edit(&base, BestFit);
+        assert_eq!(location(&best, 6), (6, 1));
+        assert_eq!(location(&best, 7), (2, 2));
+        assert_eq!(best.len(), base.len());
+
+        let append = edit(&base, AppendOnly);
+        assert_eq!(location(&append, 6), (11, 1));
+        assert_eq!(location(&append, 7), (12, 2));
+        assert_eq!(append.len(), base.len() + 3 * SECTOR_SIZE);
+
+        // the default is first fit
+        let mut editor = RegionFileEditor::open(Cursor::new(base.clone())).unwrap();
+        editor.remove_chunk(0, 0).unwrap();
+        editor
+            .set_chunk(6, 0, &data(1, 6), CompressionType::Uncompressed)
+            .unwrap();
+        assert_eq!(location(&editor.into_inner().into_inner(), 6), (2, 1));
+
+        for buf in [&first, &best, &append] {
+            let region = RegionReader::new(buf).unwrap();
+            let mut sectors = layout::SectorMap::new(0);
+            for x in 0..8 {
+                let chunk = region.get_chunk(x, 0).unwrap();
+                match x {
+                    0 | 2 => assert!(chunk.is_none()),
+                    _ => {
+                        let sectors_used = [3, 1, 1, 1, 2, 1, 1, 2][x];
+                        assert_eq!(chunk.unwrap().raw_data, data(sectors_used, x as u8));
+                        let (offset, count) = location(buf, x);
+                        assert!(sectors.is_free(offset, count));
+                        sectors.mark(offset, count, true);
+                    }
+                }
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct Broken;
+
+    impl SectorAllocator for Broken {
+        fn allocate(&mut self, _count: usize, _free: &[Range<usize>]) -> Option<usize> {
+            Some(3)
+        }
+    }
+
+    #[test]
+    fn rejects_used_sectors() {
+        let mut writer = RegionWriter::new();
+        writer
+            .push_chunk_with_compression(&data(2, 0), (0, 0), CompressionType::Uncompressed)
+            .unwrap();
+        let mut base = vec![];
+        writer.write(&mut base).unwrap();
+
+        let mut editor = RegionFileEditor::open(Cursor::new(base.clone())).unwrap();
+        editor.set_allocator(Broken);
+        assert!(matches!(
+            editor.set_chunk(1, 0, &data(1, 1), CompressionType::Uncompressed),
+            Err(McaError::InvalidAllocation { start: 3, count: 1 })
+        ));
+        assert_eq!(editor.into_inner().into_inner(), base);
+    }
+}
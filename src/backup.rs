@@ -0,0 +1,120 @@
+//! Incremental backups made of sparse delta regions.
+
+use crate::{merge_regions, McaError, MergePolicy, RegionIter, RegionReader, RegionWriter};
+
+/// Builds a sparse region holding only the chunks of `src` with a timestamp after `since`.
+/// Chunks are copied verbatim with their timestamps, `None` is returned when no chunk changed.
+///
+/// Fails on externally stored chunks that changed, their data lives in a separate `.mcc` file.
+///
+/// ## Example
+/// ```ignore
+/// use mca::{backup, RegionReader};
+///
+/// let region = RegionReader::new(&data)?;
+/// if let Some(delta) = backup::delta_region(&region, last_backup)? {
+///     std::fs::write("backup/r.0.0.mca", &delta)?;
+/// }
+/// ```
+pub fn delta_region(src: &RegionReader, since: u32) -> Result<Option<Vec<u8>>, McaError> {
+    let mut writer = RegionWriter::new();
+
+    for idx in 0..RegionIter::MAX {
+        let (x, z) = RegionIter::get_chunk_coordinate(idx);
+        if src.get_chunk_timestamp(x, z) <= since {
+            continue;
+        }
+
+        writer.copy_chunk_from(src, (x, z), (x as u8, z as u8))?;
+    }
+
+    if writer.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(writer.into_bytes()?))
+}
+
+/// Applies a delta from [`delta_region`] on top of `base`, every chunk in `delta` replaces the one in `base`  
+/// Fails on externally stored chunks in either region, like [`merge_regions`] does.
+pub fn apply_delta(base: &RegionReader, delta: &RegionReader) -> Result<Vec<u8>, McaError> {
+    merge_regions(delta, base, MergePolicy::PreferFirst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{layout, CompressionType};
+
+    const REGION: &[u8] = include_bytes!("../benches/r.0.0.mca");
+
+    #[test]
+    fn delta_round_trip() {
+        let base = RegionReader::new(REGION).unwrap();
+        let newest = (0..RegionIter::MAX)
+            .map(RegionIter::get_chunk_coordinate)
+            .map(|(x, z)| base.get_chunk_timestamp(x, z))
+            .max()
+            .unwrap();
+        assert!(delta_region(&base, newest).unwrap().is_none());
+
+        let mut writer = RegionWriter::from_region(&base).unwrap();
+        for (i, coordinate) in [(0, 0), (5, 9), (31, 31)].into_iter().enumerate() {
+            writer
+                .push_chunk_with_timestamp(
+                    &[10, i as u8, 0, 0],
+                    coordinate,
+                    CompressionType::Zlib,
+                    newest + 1 + i as u32,
+                )
+                .unwrap();
+        }
+        let mut modified = vec![];
+        writer.write(&mut modified).unwrap();
+        let modified = RegionReader::new(&modified).unwrap();
+
+        let delta = delta_region(&modified, newest).unwrap().unwrap();
+        let delta_reader = RegionReader::new(&delta).unwrap();
+        assert_eq!(delta_reader.occupancy().count(), 3);
+
+        let applied = apply_delta(&base, &delta_reader).unwrap();
+        let applied = RegionReader::new(&applied).unwrap();
+        for idx in 0..RegionIter::MAX {
+            let (x, z) = RegionIter::get_chunk_coordinate(idx);
+            assert_eq!(
+                applied.get_chunk(x, z).unwrap(),
+                modified.get_chunk(x, z).unwrap()
+            );
+            assert_eq!(
+                applied.get_chunk_timestamp(x, z),
+                modified.get_chunk_timestamp(x, z)
+            );
+        }
+
+        assert_eq!(delta_region(&applied, newest).unwrap().unwrap(), delta);
+    }
+
+    #[test]
+    fn apply_delta_external() {
+        let base = RegionReader::new(REGION).unwrap();
+        let oversized = (0..256 * layout::SECTOR_SIZE)
+            .map(|i| (i * 31 % 256) as u8)
+            .collect::<Vec<_>>();
+        let mut writer = RegionWriter::for_region(crate::RegionPos::new(0, 0));
+        writer
+            .push_precompressed_chunk(oversized, CompressionType::Uncompressed, (0, 0), 1)
+            .unwrap();
+        let mut delta = vec![];
+        writer.write_with_external(&mut delta).unwrap();
+        let delta = RegionReader::new(&delta).unwrap();
+        assert!(delta.get_chunk(0, 0).unwrap().unwrap().is_external());
+
+        // the base's copy would silently win over the external chunk otherwise
+        for (a, b) in [(&base, &delta), (&delta, &base)] {
+            assert!(matches!(
+                apply_delta(a, b),
+                Err(McaError::InvalidChunkPayload(_))
+            ));
+        }
+    }
+}
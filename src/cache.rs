@@ -0,0 +1,228 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use crate::{McaError, RegionReader};
+
+/// How many decompressed chunks a [`CachedRegion`] may hold at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CacheLimit {
+    /// At most this many chunks
+    Entries(usize),
+    /// At most this many decompressed bytes in total.  
+    /// A chunk bigger than the whole budget is returned but never cached.
+    Bytes(usize),
+}
+
+/// A [`RegionReader`] with an LRU cache of decompressed chunk data, keyed by chunk coordinate.
+///
+/// Useful when the same chunks are requested over and over, cache hits never run the codec again.
+///
+/// ## Example
+/// ```ignore
+/// use mca::{CacheLimit, CachedRegion, RegionReader};
+///
+/// let region = RegionReader::new(&data)?;
+/// let mut cached = CachedRegion::new(region, CacheLimit::Entries(64));
+///
+/// let data = cached.get_decompressed(0, 0)?.unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct CachedRegion<'a> {
+    region: RegionReader<'a>,
+    limit: CacheLimit,
+    entries: HashMap<(usize, usize), Arc<Vec<u8>>>,
+    // least recently used at the front
+    order: VecDeque<(usize, usize)>,
+    bytes: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl<'a> CachedRegion<'a> {
+    /// Wraps a region with an empty cache
+    pub fn new(region: RegionReader<'a>, limit: CacheLimit) -> CachedRegion<'a> {
+        CachedRegion {
+            region,
+            limit,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            bytes: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Get the decompressed data of a chunk, from the cache if possible.  
+    /// Will return [`None`] if chunk hasn't been generated yet.
+    pub fn get_decompressed(
+        &mut self,
+        x: usize,
+        z: usize,
+    ) -> Result<Option<Arc<Vec<u8>>>, McaError> {
+        if let Some(data) = self.entries.get(&(x, z)) {
+            let data = Arc::clone(data);
+            self.hits += 1;
+            self.touch((x, z));
+
+            return Ok(Some(data));
+        }
+
+        let chunk = match self.region.get_chunk(x, z)? {
+            Some(chunk) => chunk,
+            None => return Ok(None),
+        };
+
+        self.misses += 1;
+        let data = Arc::new(chunk.decompress()?);
+        self.insert((x, z), Arc::clone(&data));
+
+        Ok(Some(data))
+    }
+
+    fn touch(&mut self, key: (usize, usize)) {
+        if let Some(pos) = self.order.iter().position(|k| *k == key) {
+            self.order.remove(pos);
+        }
+        self.order.push_back(key);
+    }
+
+    fn insert(&mut self, key: (usize, usize), data: Arc<Vec<u8>>) {
+        let len = data.len();
+
+        match self.limit {
+            CacheLimit::Entries(max) => {
+                if max == 0 {
+                    return;
+                }
+                while self.entries.len() >= max {
+                    self.evict();
+                }
+            }
+            CacheLimit::Bytes(max) => {
+                if len > max {
+                    return;
+                }
+                while self.bytes + len > max {
+                    self.evict();
+                }
+            }
+        }
+
+        self.bytes += len;
+        self.entries.insert(key, data);
+        self.order.push_back(key);
+    }
+
+    fn evict(&mut self) {
+        if let Some(key) = self.order.pop_front() {
+            if let Some(data) = self.entries.remove(&key) {
+                self.bytes -= data.len();
+            }
+        }
+    }
+
+    /// The amount of requests served from the cache
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// The amount of requests that had to decompress the chunk
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// The amount of chunks currently cached
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no chunks are cached
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The total size of all cached decompressed chunks
+    pub fn cached_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Drops every cached chunk, the hit & miss counters are kept
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.bytes = 0;
+    }
+
+    /// Get the wrapped region
+    pub fn inner(&self) -> &RegionReader<'a> {
+        &self.region
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGION: &[u8] = include_bytes!("../benches/r.0.0.mca");
+
+    #[test]
+    fn hits_and_misses() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut cached = CachedRegion::new(region.clone(), CacheLimit::Entries(2));
+
+        let first = cached.get_decompressed(0, 0).unwrap().unwrap();
+        let again = cached.get_decompressed(0, 0).unwrap().unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+        assert_eq!(
+            *first,
+            region
+                .get_chunk(0, 0)
+                .unwrap()
+                .unwrap()
+                .decompress()
+                .unwrap()
+        );
+        assert_eq!((cached.hits(), cached.misses()), (1, 1));
+
+        cached.get_decompressed(1, 0).unwrap();
+        // (0, 0) was used more recently than (1, 0), so (1, 0) gets evicted
+        cached.get_decompressed(0, 0).unwrap();
+        cached.get_decompressed(2, 0).unwrap();
+        assert_eq!(cached.len(), 2);
+        assert_eq!((cached.hits(), cached.misses()), (2, 3));
+
+        cached.get_decompressed(0, 0).unwrap();
+        cached.get_decompressed(1, 0).unwrap();
+        assert_eq!((cached.hits(), cached.misses()), (3, 4));
+    }
+
+    #[test]
+    fn byte_budget_of_one_chunk() {
+        let region = RegionReader::new(REGION).unwrap();
+        let size = region
+            .get_chunk(0, 0)
+            .unwrap()
+            .unwrap()
+            .decompress()
+            .unwrap()
+            .len();
+        let mut cached = CachedRegion::new(region, CacheLimit::Bytes(size));
+
+        cached.get_decompressed(0, 0).unwrap().unwrap();
+        cached.get_decompressed(0, 0).unwrap().unwrap();
+        assert_eq!((cached.hits(), cached.misses()), (1, 1));
+        assert_eq!(cached.cached_bytes(), size);
+
+        for x in 1..4 {
+            cached.get_decompressed(x, 0).unwrap().unwrap();
+            assert!(cached.cached_bytes() <= size);
+            assert!(cached.len() <= 1);
+        }
+
+        cached.get_decompressed(0, 0).unwrap().unwrap();
+        assert_eq!(cached.misses(), 5);
+        assert!(cached.cached_bytes() <= size);
+    }
+}
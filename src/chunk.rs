@@ -1,4 +1,9 @@
-use crate::{compression::CompressionType, McaError};
+use std::{cmp::Ordering, sync::OnceLock};
+
+use crate::{
+    compression::{CompressScratch, CompressionLevel, CompressionType, Lz4Framing},
+    layout, McaError, RegionWriter,
+};
 
 /// A raw compressed chunk, holds the compression type used.  
 /// And the specific chunk byte slice from the region data
@@ -8,6 +13,7 @@ use crate::{compression::CompressionType, McaError};
 pub struct RawChunk<'a> {
     pub raw_data: &'a [u8],
     compression_type: CompressionType,
+    external: bool,
 }
 
 impl RawChunk<'_> {
@@ -30,24 +36,127 @@ impl RawChunk<'_> {
         self.compression_type.clone()
     }
 
+    /// Returns true if the chunk data is stored in an external `c.x.z.mcc` file.  
+    /// The raw data is then empty, the compression type still applies to the external file.
+    pub fn is_external(&self) -> bool {
+        self.external
+    }
+
     /// Creates a new raw chunk from its bytes and compression type
-    pub fn new(data: &[u8], compression: CompressionType) -> RawChunk {
+    pub fn new(data: &[u8], compression: CompressionType) -> RawChunk<'_> {
         RawChunk {
             raw_data: data,
             compression_type: compression,
+            external: false,
         }
     }
+
+    pub(crate) fn with_external(mut self, external: bool) -> Self {
+        self.external = external;
+        self
+    }
+}
+
+/// A chunk too large for the region file, collected while writing before it becomes its [`MccFile`](crate::MccFile)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct ExternalChunk {
+    /// The chunk coordinate relative to the region
+    pub coordinate: (u8, u8),
+    pub compression: CompressionType,
+    pub data: Vec<u8>,
+}
+
+/// A raw chunk handed to [`RegionWriter::write_with`](crate::RegionWriter::write_with) by its generator
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChunkSource {
+    /// The uncompressed chunk NBT
+    pub data: Vec<u8>,
+    /// What `data` gets compressed with
+    pub compression: CompressionType,
+    pub timestamp: u32,
+}
+
+impl ChunkSource {
+    /// A chunk compressed with `Zlib`, timestamp will be current time since `UNIX_EPOCH`
+    pub fn new(data: Vec<u8>) -> Result<ChunkSource, McaError> {
+        Ok(ChunkSource {
+            data,
+            compression: CompressionType::Zlib,
+            timestamp: crate::RegionWriter::get_current_timestamp()?,
+        })
+    }
 }
 
 /// A `pending` chunk, holds all metadata used in region chunk payloads.  
 ///
 /// This is used when **writing** region files.  
+/// A [`deferred`](PendingChunk::deferred) chunk keeps its raw data until written, it has no [`compressed_data`](PendingChunk::compressed_data) until then.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct PendingChunk {
-    pub compressed_data: Vec<u8>,
     pub compression: CompressionType,
     pub timestamp: u32,
     pub coordinate: (u8, u8),
+    data: ChunkData,
+    // size of the uncompressed data, when it passed through this crate
+    raw_len: Option<usize>,
+}
+
+/// The payload of a [`PendingChunk`], the only copy of its data
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum ChunkData {
+    Compressed(Vec<u8>),
+    // raw data still waiting to be compressed with `compression`
+    Deferred(Vec<u8>, CompressedCache),
+}
+
+/// The compressed copy of a deferred chunk the first write made, so the next one doesn't compress again  
+/// Only reused while compressed the same way, comparisons ignore it since it mirrors the raw data.
+#[derive(Debug, Clone, Default)]
+struct CompressedCache(OnceLock<Compressed>);
+
+#[derive(Debug, Clone)]
+struct Compressed {
+    compression: CompressionType,
+    level: CompressionLevel,
+    lz4: Lz4Framing,
+    verified: bool,
+    data: Vec<u8>,
+}
+
+impl Compressed {
+    /// Whether compressing again with these settings gives the same data
+    fn matches(
+        &self,
+        compression: &CompressionType,
+        level: CompressionLevel,
+        lz4: Lz4Framing,
+        verify: bool,
+    ) -> bool {
+        self.compression == *compression
+            && self.level == level
+            && self.lz4 == lz4
+            && (self.verified || !verify)
+    }
+}
+
+impl PartialEq for CompressedCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for CompressedCache {}
+
+impl PartialOrd for CompressedCache {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CompressedCache {
+    fn cmp(&self, _: &Self) -> Ordering {
+        Ordering::Equal
+    }
 }
 
 impl PendingChunk {
@@ -67,16 +176,341 @@ impl PendingChunk {
         timestamp: u32,
         coordinate: (u8, u8),
     ) -> Result<PendingChunk, McaError> {
-        assert!(coordinate.0 < 32);
-        assert!(coordinate.1 < 32);
+        layout::check_coordinate(coordinate.0 as usize, coordinate.1 as usize)?;
 
         let compressed_data = compression.compress(raw_data)?;
 
         Ok(PendingChunk {
-            compressed_data,
             compression,
             timestamp,
             coordinate,
+            data: ChunkData::Compressed(compressed_data),
+            raw_len: Some(raw_data.len()),
+        })
+    }
+
+    /// Create a new pending chunk that's only compressed once written, or by [`compress`](PendingChunk::compress)  
+    /// A writer compresses it with its own [`CompressionLevel`], changing `compression` before that costs nothing.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let chunk = PendingChunk::deferred(nbt, CompressionType::Zlib, 1724372177, (4, 6))?;
+    /// writer.push_pending_chunk(chunk)?;
+    /// ```
+    pub fn deferred(
+        raw_data: Vec<u8>,
+        compression: CompressionType,
+        timestamp: u32,
+        coordinate: (u8, u8),
+    ) -> Result<PendingChunk, McaError> {
+        layout::check_coordinate(coordinate.0 as usize, coordinate.1 as usize)?;
+
+        Ok(PendingChunk {
+            compression,
+            timestamp,
+            coordinate,
+            raw_len: Some(raw_data.len()),
+            data: ChunkData::Deferred(raw_data, CompressedCache::default()),
+        })
+    }
+
+    /// Returns false while the chunk's compression is still deferred
+    pub fn is_compressed(&self) -> bool {
+        matches!(self.data, ChunkData::Compressed(_))
+    }
+
+    /// The data compressed with `compression`, `None` while the chunk's compression is deferred
+    pub fn compressed_data(&self) -> Option<&[u8]> {
+        match &self.data {
+            ChunkData::Compressed(data) => Some(data),
+            ChunkData::Deferred(..) => None,
+        }
+    }
+
+    /// The compressed data for adjusting it before writing, `None` while the chunk's compression is deferred  
+    /// It must stay valid for `compression`.
+    pub fn compressed_data_mut(&mut self) -> Option<&mut Vec<u8>> {
+        match &mut self.data {
+            ChunkData::Compressed(data) => Some(data),
+            ChunkData::Deferred(..) => None,
+        }
+    }
+
+    /// The data the chunk holds, compressed or still raw while its compression is deferred
+    pub(crate) fn payload(&self) -> &[u8] {
+        match &self.data {
+            ChunkData::Compressed(data) | ChunkData::Deferred(data, _) => data,
+        }
+    }
+
+    /// The size of the chunk's uncompressed data  
+    /// `None` when the chunk was given already compressed, like from [`from_compressed`](PendingChunk::from_compressed)
+    pub fn raw_len(&self) -> Option<usize> {
+        self.raw_len
+    }
+
+    pub(crate) fn with_raw_len(mut self, raw_len: usize) -> PendingChunk {
+        self.raw_len = Some(raw_len);
+        self
+    }
+
+    /// The raw data of a chunk whose compression is still deferred
+    pub fn deferred_data(&self) -> Option<&[u8]> {
+        match &self.data {
+            ChunkData::Compressed(_) => None,
+            ChunkData::Deferred(data, _) => Some(data),
+        }
+    }
+
+    /// The raw data of a chunk whose compression is still deferred, for adjusting it before writing
+    pub fn deferred_data_mut(&mut self) -> Option<&mut Vec<u8>> {
+        match &mut self.data {
+            ChunkData::Compressed(_) => None,
+            ChunkData::Deferred(data, cache) => {
+                *cache = CompressedCache::default();
+                Some(data)
+            }
+        }
+    }
+
+    /// Changes the compression a deferred chunk will be compressed with, dropping its compressed copy
+    pub(crate) fn retag(&mut self, compression: CompressionType) {
+        if let ChunkData::Deferred(_, cache) = &mut self.data {
+            *cache = CompressedCache::default();
+        }
+        self.compression = compression;
+    }
+
+    /// The chunk's data buffers, for reuse once the chunk isn't needed
+    pub(crate) fn into_buffers(self) -> impl Iterator<Item = Vec<u8>> {
+        let (data, cache) = match self.data {
+            ChunkData::Compressed(data) => (data, None),
+            ChunkData::Deferred(data, cache) => (data, cache.0.into_inner().map(|c| c.data)),
+        };
+        std::iter::once(data).chain(cache)
+    }
+
+    /// The bytes allocated for the chunk's data, including a deferred chunk's compressed copy
+    pub(crate) fn heap_size(&self) -> usize {
+        match &self.data {
+            ChunkData::Compressed(data) => data.capacity(),
+            ChunkData::Deferred(data, cache) => {
+                data.capacity() + cache.0.get().map_or(0, |c| c.data.capacity())
+            }
+        }
+    }
+
+    /// Compresses a deferred chunk, does nothing if it already is
+    pub fn compress(&mut self, level: CompressionLevel) -> Result<(), McaError> {
+        if let ChunkData::Deferred(raw_data, cache) = &mut self.data {
+            let lz4 = Lz4Framing::default();
+            let compressed_data = match cache.0.take() {
+                Some(c) if c.matches(&self.compression, level, lz4, false) => c.data,
+                _ => self.compression.compress_with_level(raw_data, level)?,
+            };
+            self.data = ChunkData::Compressed(compressed_data);
+        }
+
+        Ok(())
+    }
+
+    /// A compressed copy of a deferred chunk, `None` if it already is compressed  
+    /// With `verify` the copy is checked to decompress back to the raw data.  
+    /// The first copy is kept in the chunk, later calls with the same settings reuse it.
+    pub(crate) fn compressed(
+        &self,
+        scratch: &mut CompressScratch,
+        level: CompressionLevel,
+        verify: bool,
+    ) -> Result<Option<PendingChunk>, McaError> {
+        let ChunkData::Deferred(raw_data, cache) = &self.data else {
+            return Ok(None);
+        };
+        let compressed_data = match cache.0.get() {
+            Some(c) if c.matches(&self.compression, level, scratch.lz4, verify) => c.data.clone(),
+            _ => {
+                let data = if verify {
+                    scratch.compress_verified(
+                        &self.compression,
+                        raw_data,
+                        level,
+                        self.coordinate,
+                    )?
+                } else {
+                    scratch.compress(&self.compression, raw_data, level)?
+                };
+                // a stale copy stays until the raw data changes, the next write compresses again then
+                let _ = cache.0.set(Compressed {
+                    compression: self.compression.clone(),
+                    level,
+                    lz4: scratch.lz4,
+                    verified: verify,
+                    data: data.clone(),
+                });
+                data
+            }
+        };
+
+        Ok(Some(
+            PendingChunk::from_compressed(
+                compressed_data,
+                self.compression.clone(),
+                self.timestamp,
+                self.coordinate,
+            )
+            .with_raw_len(raw_data.len()),
+        ))
+    }
+
+    /// Start building a pending chunk from raw chunk data, compressed once [`build`](PendingChunkBuilder::build) is called  
+    /// Defaults to `Zlib` with [`CompressionLevel::Default`] and the current time as timestamp
+    ///
+    /// ## Example
+    /// ```ignore
+    /// use mca::{CompressionLevel, CompressionType, PendingChunk};
+    ///
+    /// let chunk = PendingChunk::builder(&data)
+    ///     .coordinate(4, 6)
+    ///     .compression(CompressionType::Zlib)
+    ///     .level(CompressionLevel::Best)
+    ///     .build()?;
+    /// ```
+    pub fn builder(raw_data: &[u8]) -> PendingChunkBuilder<'_> {
+        PendingChunkBuilder::new(BuilderData::Raw(raw_data))
+    }
+
+    /// Start building a pending chunk from data already compressed with the builder's compression type,
+    /// the bytes are used verbatim
+    pub fn precompressed_builder(compressed_data: Vec<u8>) -> PendingChunkBuilder<'static> {
+        PendingChunkBuilder::new(BuilderData::Compressed(compressed_data))
+    }
+
+    /// Create a new pending chunk from data that's already compressed with `compression`.  
+    /// The bytes are trusted as is and written verbatim, no compression is done.  
+    /// The coordinate is checked once the chunk is pushed to a writer.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// use mca::{PendingChunk, CompressionType};
+    ///
+    /// let chunk = region.get_chunk(4, 6)?.unwrap();
+    ///
+    /// let chunk = PendingChunk::from_compressed(chunk.raw_data.to_vec(), chunk.get_compression_type(), 1724372177, (4, 6));
+    /// ```
+    pub fn from_compressed(
+        compressed_data: Vec<u8>,
+        compression: CompressionType,
+        timestamp: u32,
+        coordinate: (u8, u8),
+    ) -> PendingChunk {
+        PendingChunk {
+            compression,
+            timestamp,
+            coordinate,
+            data: ChunkData::Compressed(compressed_data),
+            raw_len: None,
+        }
+    }
+
+    /// Create a new pending chunk from a chunk read out of a region.  
+    /// The compressed payload & compression type are copied as is, no recompression is done.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// use mca::PendingChunk;
+    ///
+    /// let chunk = region.get_chunk(4, 6)?.unwrap();
+    /// let timestamp = region.get_chunk_timestamp(4, 6);
+    ///
+    /// let chunk = PendingChunk::from_raw_chunk(&chunk, (4, 6), timestamp);
+    /// ```
+    pub fn from_raw_chunk(chunk: &RawChunk, coordinate: (u8, u8), timestamp: u32) -> PendingChunk {
+        PendingChunk::from_compressed(
+            chunk.raw_data.to_vec(),
+            chunk.get_compression_type(),
+            timestamp,
+            coordinate,
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+enum BuilderData<'a> {
+    Raw(&'a [u8]),
+    Compressed(Vec<u8>),
+}
+
+/// Builds a [`PendingChunk`], see [`PendingChunk::builder`]
+#[derive(Debug, Clone)]
+pub struct PendingChunkBuilder<'a> {
+    source: BuilderData<'a>,
+    coordinate: Option<(u8, u8)>,
+    compression: CompressionType,
+    level: CompressionLevel,
+    timestamp: Option<u32>,
+}
+
+impl<'a> PendingChunkBuilder<'a> {
+    fn new(source: BuilderData<'a>) -> PendingChunkBuilder<'a> {
+        PendingChunkBuilder {
+            source,
+            coordinate: None,
+            compression: CompressionType::Zlib,
+            level: CompressionLevel::default(),
+            timestamp: None,
+        }
+    }
+
+    /// The chunk's coordinate inside the region, required
+    pub fn coordinate(mut self, x: u8, z: u8) -> Self {
+        self.coordinate = Some((x, z));
+        self
+    }
+
+    /// The compression type, for precompressed data the one it's compressed with
+    pub fn compression(mut self, compression: CompressionType) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// The compression level, unused for precompressed data
+    pub fn level(mut self, level: CompressionLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// The timestamp in unix epoch seconds, defaults to the current time
+    pub fn timestamp(mut self, timestamp: u32) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Compresses the data if needed and creates the pending chunk  
+    /// Fails with [`McaError::InvalidCoordinate`] when the coordinate is outside the region
+    /// and [`McaError::InvalidChunkPayload`] when none was set
+    pub fn build(self) -> Result<PendingChunk, McaError> {
+        let (x, z) = self.coordinate.ok_or_else(|| {
+            McaError::InvalidChunkPayload("pending chunk has no coordinate".to_string())
+        })?;
+        layout::check_coordinate(x as usize, z as usize)?;
+
+        let (compressed_data, raw_len) = match self.source {
+            BuilderData::Raw(raw_data) => (
+                self.compression.compress_with_level(raw_data, self.level)?,
+                Some(raw_data.len()),
+            ),
+            BuilderData::Compressed(data) => (data, None),
+        };
+
+        Ok(PendingChunk {
+            compression: self.compression,
+            timestamp: match self.timestamp {
+                Some(timestamp) => timestamp,
+                None => RegionWriter::get_current_timestamp()?,
+            },
+            coordinate: (x, z),
+            data: ChunkData::Compressed(compressed_data),
+            raw_len,
         })
     }
 }
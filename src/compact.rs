@@ -0,0 +1,59 @@
+use crate::{McaError, RegionIter, RegionReader, RegionWriter};
+
+/// Rewrites a region with every chunk packed back to back in coordinate order, dropping gaps & stale sectors.
+/// Payloads & timestamps are copied verbatim, nothing is recompressed.
+///
+/// Fails on externally stored chunks since their data lives in a separate `.mcc` file.
+///
+/// ## Example
+/// ```ignore
+/// let data = std::fs::read("r.0.0.mca")?;
+/// let compacted = mca::compact_region(&data)?;
+///
+/// std::fs::write("r.0.0.mca", &compacted)?;
+/// ```
+pub fn compact_region(data: &[u8]) -> Result<Vec<u8>, McaError> {
+    let region = RegionReader::new(data)?;
+    let mut writer = RegionWriter::with_capacity(RegionIter::MAX);
+
+    for idx in 0..RegionIter::MAX {
+        let (x, z) = RegionIter::get_chunk_coordinate(idx);
+        writer.copy_chunk_from(&region, (x, z), (x as u8, z as u8))?;
+    }
+
+    writer.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGION: &[u8] = include_bytes!("../benches/r.0.0.mca");
+
+    #[test]
+    fn compacted_is_equal_and_smaller() {
+        let compacted = compact_region(REGION).unwrap();
+        assert!(compacted.len() <= REGION.len());
+
+        let region = RegionReader::new(REGION).unwrap();
+        let compact = RegionReader::new(&compacted).unwrap();
+        for idx in 0..RegionIter::MAX {
+            let (x, z) = RegionIter::get_chunk_coordinate(idx);
+            let (before, after) = (
+                region.get_chunk(x, z).unwrap(),
+                compact.get_chunk(x, z).unwrap(),
+            );
+            assert_eq!(before, after);
+            if before.is_some() {
+                assert_eq!(
+                    compact.get_chunk_timestamp(x, z),
+                    region.get_chunk_timestamp(x, z)
+                );
+            }
+        }
+        assert!(compact.gap_extents().is_empty());
+
+        // already compact regions come back unchanged
+        assert_eq!(compact_region(&compacted).unwrap(), compacted);
+    }
+}
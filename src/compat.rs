@@ -0,0 +1,205 @@
+use std::fmt;
+
+use crate::{
+    layout::{self, SectorMap, CHUNK_COUNT, EXTERNAL_FLAG, HEADER_SIZE, SECTOR_SIZE},
+    CompressionType,
+};
+
+/// The oldest vanilla release able to load a chunk, ordered from least to most restrictive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MinVersion {
+    /// Any Anvil release (1.2.1+), GZip & Zlib chunks
+    Anvil,
+    /// 1.15+, uncompressed chunks and chunks stored in external `.mcc` files
+    V1_15,
+    /// 1.20.5+, LZ4 chunks
+    V1_20_5,
+    /// No vanilla release loads this, custom (127) or unknown compression ids
+    NotVanilla,
+}
+
+impl MinVersion {
+    /// Get the requirement for a raw compression byte from a chunk payload.  
+    /// The `0x80` bit marks an external `.mcc` chunk and is checked separately from the id.
+    pub fn for_compression_byte(byte: u8) -> MinVersion {
+        let id = match byte & 0x7F {
+            1 | 2 => MinVersion::Anvil,
+            3 => MinVersion::V1_15,
+            4 => MinVersion::V1_20_5,
+            _ => MinVersion::NotVanilla,
+        };
+
+        if byte & 0x80 != 0 {
+            id.max(MinVersion::V1_15)
+        } else {
+            id
+        }
+    }
+}
+
+impl fmt::Display for MinVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MinVersion::Anvil => f.write_str("1.2.1+"),
+            MinVersion::V1_15 => f.write_str("1.15+"),
+            MinVersion::V1_20_5 => f.write_str("1.20.5+"),
+            MinVersion::NotVanilla => f.write_str("not vanilla loadable"),
+        }
+    }
+}
+
+/// The vanilla release a [`RegionWriter`](crate::RegionWriter) writes for, see [`RegionWriter::for_target`](crate::RegionWriter::for_target)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TargetVersion {
+    /// GZip & Zlib only, no external chunks
+    V1_12,
+    /// Uncompressed chunks & external `.mcc` chunks too
+    V1_17,
+    /// Same as [`V1_17`](TargetVersion::V1_17), the last release without LZ4
+    V1_20_4,
+    /// LZ4 chunks too
+    V1_20_5Plus,
+    /// Anything this crate can write, custom compression included
+    Latest,
+}
+
+impl TargetVersion {
+    /// The most restrictive requirement a chunk may have for this target
+    pub fn max_requirement(&self) -> MinVersion {
+        match self {
+            TargetVersion::V1_12 => MinVersion::Anvil,
+            TargetVersion::V1_17 | TargetVersion::V1_20_4 => MinVersion::V1_15,
+            TargetVersion::V1_20_5Plus => MinVersion::V1_20_5,
+            TargetVersion::Latest => MinVersion::NotVanilla,
+        }
+    }
+
+    /// Returns true if the target loads chunks compressed with `compression`
+    pub fn allows(&self, compression: &CompressionType) -> bool {
+        MinVersion::for_compression_byte(compression.to_u8()) <= self.max_requirement()
+    }
+
+    /// Returns true if the target loads chunks stored in external `.mcc` files
+    pub fn allows_external(&self) -> bool {
+        MinVersion::for_compression_byte(EXTERNAL_FLAG | 2) <= self.max_requirement()
+    }
+
+    /// The compression type vanilla writes in this release
+    pub fn default_compression(&self) -> CompressionType {
+        CompressionType::Zlib
+    }
+}
+
+impl fmt::Display for TargetVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetVersion::V1_12 => f.write_str("1.12"),
+            TargetVersion::V1_17 => f.write_str("1.17"),
+            TargetVersion::V1_20_4 => f.write_str("1.20.4"),
+            TargetVersion::V1_20_5Plus => f.write_str("1.20.5+"),
+            TargetVersion::Latest => f.write_str("latest"),
+        }
+    }
+}
+
+/// A chunk that can't be loaded by every Anvil release
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CompatIssue {
+    pub coordinate: (usize, usize),
+    pub compression_byte: u8,
+    pub required: MinVersion,
+}
+
+/// The result of [`RegionReader::vanilla_compatibility`](crate::RegionReader::vanilla_compatibility)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CompatReport {
+    /// The oldest release able to load every chunk in the region
+    pub required: MinVersion,
+    /// Chunks needing something newer than [`MinVersion::Anvil`], in header order
+    pub issues: Vec<CompatIssue>,
+}
+
+impl CompatReport {
+    /// Returns true if some vanilla release can load the whole region
+    pub fn is_vanilla_loadable(&self) -> bool {
+        self.required != MinVersion::NotVanilla
+    }
+}
+
+/// The first structural invariant vanilla relies on that `data` breaks, see [`RegionWriter::set_strict_vanilla`](crate::RegionWriter::set_strict_vanilla)  
+/// Slack bytes after a payload aren't checked, vanilla leaves old data there itself.
+pub(crate) fn conformance_issue(data: &[u8]) -> Option<String> {
+    if data.len() < HEADER_SIZE {
+        return Some(format!("{} bytes is too short for the header", data.len()));
+    }
+    if !data.len().is_multiple_of(SECTOR_SIZE) {
+        return Some(format!(
+            "{} bytes isn't a whole number of sectors",
+            data.len()
+        ));
+    }
+
+    let mut sectors = SectorMap::new(data.len());
+    for index in 0..CHUNK_COUNT {
+        let (x, z) = (index % 32, index / 32);
+        let (Some(entry), Some(timestamp)) = (
+            layout::bytes_at(data, index * 4),
+            layout::bytes_at(data, SECTOR_SIZE + index * 4),
+        ) else {
+            return Some(format!("{} bytes is too short for the header", data.len()));
+        };
+
+        if entry == [0; 4] {
+            if timestamp != [0; 4] {
+                return Some(format!("empty slot ({x}, {z}) has a timestamp"));
+            }
+            continue;
+        }
+
+        let (offset, count) = layout::decode_location(entry);
+        if offset < HEADER_SIZE / SECTOR_SIZE || count == 0 {
+            return Some(format!(
+                "chunk ({x}, {z}) points at sector {offset} with {count} sectors"
+            ));
+        }
+        if (offset + count) * SECTOR_SIZE > data.len() {
+            return Some(format!(
+                "chunk ({x}, {z}) reaches past the end of the region"
+            ));
+        }
+        if !sectors.is_free(offset, count) {
+            return Some(format!(
+                "chunk ({x}, {z}) shares sectors with another chunk"
+            ));
+        }
+        sectors.mark(offset, count, true);
+
+        let start = offset * SECTOR_SIZE;
+        let (Some(len), Some(&compression)) = (
+            layout::bytes_at(data, start),
+            data.get(start + layout::LENGTH_FIELD_SIZE),
+        ) else {
+            return Some(format!(
+                "chunk ({x}, {z}) reaches past the end of the region"
+            ));
+        };
+        let len = u32::from_be_bytes(len) as usize;
+        // vanilla only needs the payload to fit, extra sectors are slack it rewrites later
+        if len == 0 || layout::sectors_for_payload(len - 1) as usize > count {
+            return Some(format!(
+                "chunk ({x}, {z}) has a {len} byte payload in {count} sectors"
+            ));
+        }
+        if MinVersion::for_compression_byte(compression) == MinVersion::NotVanilla {
+            return Some(format!("chunk ({x}, {z}) uses compression {compression}"));
+        }
+        if compression & EXTERNAL_FLAG != 0 && len != 1 {
+            return Some(format!(
+                "external chunk ({x}, {z}) has {} bytes inline",
+                len - 1
+            ));
+        }
+    }
+
+    None
+}
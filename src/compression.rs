@@ -1,6 +1,11 @@
 use std::io::{Read, Write};
 
-use crate::McaError;
+use miniz_oxide::{
+    deflate::core::{compress, CompressorOxide, TDEFLFlush, TDEFLStatus},
+    DataFormat,
+};
+
+use crate::{layout::CHUNK_COUNT, McaError};
 
 /// Compression types used in chunks
 ///
@@ -15,8 +20,181 @@ pub enum CompressionType {
     Custom = 127,
 }
 
-impl From<u8> for CompressionType {
-    fn from(value: u8) -> Self {
+/// How much effort is spent compressing, only `Zlib` has tunable levels
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CompressionLevel {
+    /// Zlib level 1
+    Fastest,
+    /// Zlib level 4
+    #[default]
+    Default,
+    /// Zlib level 9
+    Best,
+    /// A specific zlib level, from 0 (no compression) to 10
+    Custom(u8),
+    /// Zopfli, many times slower than `Best` for zlib streams a few percent smaller  
+    /// The output is still plain zlib, readable by vanilla & any inflate  
+    /// Without the `zopfli` feature this is the same as `Best`
+    Archival,
+}
+
+impl CompressionLevel {
+    /// Get the zlib level this maps to
+    pub fn zlib_level(&self) -> u8 {
+        match self {
+            CompressionLevel::Fastest => 1,
+            CompressionLevel::Default => 4,
+            CompressionLevel::Best => 9,
+            CompressionLevel::Custom(level) => (*level).min(10),
+            CompressionLevel::Archival => 9,
+        }
+    }
+}
+
+/// The checksum stored with every block of an LZ4 chunk
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Lz4Checksum {
+    /// What lz4-java writes by default, xxHash32 seeded with `0x9747b28c` with its top 4 bits dropped
+    #[default]
+    XxHash32,
+    /// Every block stores 0, written by servers giving lz4-java a no-op checksum
+    Zero,
+}
+
+/// The framing of LZ4 chunks, the block size & checksum lz4-java's `LZ4BlockOutputStream` was created with.  
+/// Defaults to 64 KiB blocks with [`Lz4Checksum::XxHash32`], what vanilla writes.
+///
+/// Decompressing accepts every framing, this only matters for writing the same bytes a server would.
+///
+/// ## Example
+/// ```ignore
+/// let framing = Lz4Framing::new(256 * 1024, Lz4Checksum::XxHash32)?;
+/// writer.set_lz4_framing(framing);
+///
+/// // or whatever an existing chunk was written with
+/// writer.set_lz4_framing(Lz4Framing::detect(chunk.raw_data).unwrap_or_default());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Lz4Framing {
+    block_size: usize,
+    checksum: Lz4Checksum,
+}
+
+impl Default for Lz4Framing {
+    fn default() -> Self {
+        Lz4Framing {
+            block_size: Self::DEFAULT_BLOCK_SIZE,
+            checksum: Lz4Checksum::default(),
+        }
+    }
+}
+
+impl Lz4Framing {
+    /// lz4-java's default block size, 64 KiB
+    pub const DEFAULT_BLOCK_SIZE: usize = 1 << 16;
+    /// The smallest block size lz4-java accepts
+    pub const MIN_BLOCK_SIZE: usize = 64;
+    /// The largest block size lz4-java accepts, 32 MiB
+    pub const MAX_BLOCK_SIZE: usize = 1 << 25;
+
+    /// Fails with [`McaError::InvalidLz4BlockSize`] unless `block_size` is within
+    /// [`MIN_BLOCK_SIZE`](Lz4Framing::MIN_BLOCK_SIZE) & [`MAX_BLOCK_SIZE`](Lz4Framing::MAX_BLOCK_SIZE)
+    pub fn new(block_size: usize, checksum: Lz4Checksum) -> Result<Lz4Framing, McaError> {
+        if !(Self::MIN_BLOCK_SIZE..=Self::MAX_BLOCK_SIZE).contains(&block_size) {
+            return Err(McaError::InvalidLz4BlockSize(block_size));
+        }
+
+        Ok(Lz4Framing {
+            block_size,
+            checksum,
+        })
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn checksum(&self) -> Lz4Checksum {
+        self.checksum
+    }
+
+    /// The framing an LZ4 chunk's compressed data was written with, read from its first block header  
+    /// Blocks only store their size rounded up to a power of 2, so that's the block size returned.
+    /// `None` if the data doesn't start with an LZ4 block.
+    pub fn detect(data: &[u8]) -> Option<Lz4Framing> {
+        let header: &[u8; LZ4_HEADER_SIZE] = data.get(..LZ4_HEADER_SIZE)?.try_into().ok()?;
+        if &header[..8] != b"LZ4Block" || !matches!(header[8] >> 4, 1 | 2) {
+            return None;
+        }
+
+        let checksum = match u32::from_le_bytes(header[17..21].try_into().ok()?) {
+            0 => Lz4Checksum::Zero,
+            _ => Lz4Checksum::XxHash32,
+        };
+        Lz4Framing::new(1 << (10 + (header[8] & 0x0f)), checksum).ok()
+    }
+
+    /// Compresses `data` into lz4-java's block format with this framing
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>, McaError> {
+        let mut buf = vec![];
+        self.compress_into(&mut buf, data)?;
+        Ok(buf)
+    }
+
+    fn compress_into(&self, out: &mut Vec<u8>, data: &[u8]) -> Result<(), McaError> {
+        let context = lz4_java_wrc::Context::default();
+        match self.checksum {
+            Lz4Checksum::XxHash32 => {
+                lz4_java_wrc::Lz4BlockOutput::with_context(out, context, self.block_size)?
+                    .write_all(data)?
+            }
+            Lz4Checksum::Zero => {
+                lz4_java_wrc::Lz4BlockOutput::with_checksum(out, context, self.block_size, |_| 0)?
+                    .write_all(data)?
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Zopfli's default 15 iterations, the output is a regular zlib stream
+#[cfg(feature = "zopfli")]
+fn zopfli_zlib(data: &[u8]) -> Result<Vec<u8>, McaError> {
+    let mut buf = vec![];
+    zopfli::compress(
+        zopfli::Options::default(),
+        zopfli::Format::Zlib,
+        data,
+        &mut buf,
+    )?;
+    Ok(buf)
+}
+
+// magic, token, compressed & decompressed length, checksum
+const LZ4_HEADER_SIZE: usize = 21;
+
+/// Decompresses lz4-java's block format with any block size, accepting zeroed checksums too
+fn decompress_lz4(data: &[u8]) -> Result<Vec<u8>, McaError> {
+    let mut buf = vec![];
+    let Err(e) = lz4_java_wrc::Lz4BlockInput::new(data).read_to_end(&mut buf) else {
+        return Ok(buf);
+    };
+    if Lz4Framing::detect(data).is_none_or(|f| f.checksum != Lz4Checksum::Zero) {
+        return Err(e.into());
+    }
+
+    buf.clear();
+    let context = lz4_java_wrc::Context::default();
+    lz4_java_wrc::Lz4BlockInputBase::with_checksum(data, context, |_| 0, true)
+        .read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+impl TryFrom<u8> for CompressionType {
+    type Error = McaError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
         CompressionType::from_u8(value)
     }
 }
@@ -28,14 +206,15 @@ impl From<CompressionType> for u8 {
 }
 
 impl CompressionType {
-    pub fn from_u8(value: u8) -> CompressionType {
+    /// Fails with [`McaError::UnknownCompression`] for ids vanilla doesn't define
+    pub fn from_u8(value: u8) -> Result<CompressionType, McaError> {
         match value {
-            1 => CompressionType::GZip,
-            2 => CompressionType::Zlib,
-            3 => CompressionType::Uncompressed,
-            4 => CompressionType::LZ4,
-            127 => CompressionType::Custom,
-            _ => panic!("Invalid compression type: {}", value),
+            1 => Ok(CompressionType::GZip),
+            2 => Ok(CompressionType::Zlib),
+            3 => Ok(CompressionType::Uncompressed),
+            4 => Ok(CompressionType::LZ4),
+            127 => Ok(CompressionType::Custom),
+            _ => Err(McaError::UnknownCompression(value)),
         }
     }
 
@@ -51,33 +230,219 @@ impl CompressionType {
 
     /// Takes in a byte slice and uses the current compression type to **compress** the data
     pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>, McaError> {
+        self.compress_with_level(data, CompressionLevel::default())
+    }
+
+    /// Same as [`CompressionType::compress`] but with a specific [`CompressionLevel`]
+    pub fn compress_with_level(
+        &self,
+        data: &[u8],
+        level: CompressionLevel,
+    ) -> Result<Vec<u8>, McaError> {
         match self {
-            CompressionType::Zlib => Ok(miniz_oxide::deflate::compress_to_vec_zlib(data, 4)),
+            #[cfg(feature = "zopfli")]
+            CompressionType::Zlib if level == CompressionLevel::Archival => zopfli_zlib(data),
+            CompressionType::Zlib => Ok(miniz_oxide::deflate::compress_to_vec_zlib(
+                data,
+                level.zlib_level(),
+            )),
             CompressionType::Uncompressed => Ok(data.to_vec()),
-            CompressionType::LZ4 => Ok({
-                let mut buf: Vec<u8> = Vec::new();
-                lz4_java_wrc::Lz4BlockOutput::new(&mut buf).write_all(data)?;
-                buf
-            }),
-            CompressionType::GZip => unimplemented!("This is unused in practice and if you somehow need this, make an issue on github and i'll add it <3"),
-            CompressionType::Custom => unimplemented!("Haven't implemented this and i don't personally need this but make an issue on github and i'll fix it <3"),
+            CompressionType::LZ4 => Lz4Framing::default().compress(data),
+            // unused in practice, make an issue on github if you need either of these <3
+            CompressionType::GZip | CompressionType::Custom => {
+                Err(McaError::UnimplementedCompression(self.clone()))
+            }
         }
     }
 
     /// Takes in a byte slice and uses the current compression type to **decompress** the data
     pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, McaError> {
         match self {
-            CompressionType::Zlib => Ok(miniz_oxide::inflate::decompress_to_vec_zlib(
-                data,
-            )?),
+            CompressionType::Zlib => Ok(miniz_oxide::inflate::decompress_to_vec_zlib(data)?),
             CompressionType::Uncompressed => Ok(data.to_vec()),
-            CompressionType::LZ4 => Ok({
-                let mut buf: Vec<u8> = Vec::new();
-                lz4_java_wrc::Lz4BlockInput::new(data).read_to_end(&mut buf)?;
-                buf
+            CompressionType::LZ4 => decompress_lz4(data),
+            CompressionType::GZip | CompressionType::Custom => {
+                Err(McaError::UnimplementedCompression(self.clone()))
+            }
+        }
+    }
+}
+
+/// Buffers reused across many compressions, only the final compressed bytes are allocated per chunk.  
+/// The LZ4 encoder still allocates its own blocks, it has no way to reuse them.
+///
+/// Carries no meaningful state besides the LZ4 framing, so it compares by that alone & clones
+/// with empty buffers, letting the writers holding one keep their derives.
+pub(crate) struct CompressScratch {
+    // what LZ4 chunks are framed with, see `RegionWriter::set_lz4_framing`
+    pub(crate) lz4: Lz4Framing,
+    // does the actual compressing, `compress_with_scratch` unless swapped out
+    pub(crate) codec: Codec,
+    zlib: Option<Box<CompressorOxide>>,
+    out: Vec<u8>,
+    // buffers of cleared chunks by ascending capacity, see `recycle`
+    spare: Vec<Vec<u8>>,
+}
+
+/// Compresses `data` into a new buffer, with the scratch buffers at hand
+pub(crate) type Codec = fn(
+    &mut CompressScratch,
+    &CompressionType,
+    &[u8],
+    CompressionLevel,
+) -> Result<Vec<u8>, McaError>;
+
+impl Default for CompressScratch {
+    fn default() -> Self {
+        CompressScratch {
+            lz4: Lz4Framing::default(),
+            codec: CompressScratch::compress_with_scratch,
+            zlib: None,
+            out: vec![],
+            spare: vec![],
+        }
+    }
+}
+
+impl CompressScratch {
+    /// Same as [`CompressionType::compress_with_level`], the output is identical
+    /// except LZ4 chunks being framed with `lz4`
+    pub(crate) fn compress(
+        &mut self,
+        compression: &CompressionType,
+        data: &[u8],
+        level: CompressionLevel,
+    ) -> Result<Vec<u8>, McaError> {
+        (self.codec)(self, compression, data, level)
+    }
+
+    /// Same as [`compress`](CompressScratch::compress) followed by decompressing the result,
+    /// fails with [`McaError::VerifyFailed`] if that doesn't give back `data`
+    pub(crate) fn compress_verified(
+        &mut self,
+        compression: &CompressionType,
+        data: &[u8],
+        level: CompressionLevel,
+        coordinate: (u8, u8),
+    ) -> Result<Vec<u8>, McaError> {
+        let compressed = self.compress(compression, data, level)?;
+        match compression.decompress(&compressed) {
+            Ok(raw) if raw == data => Ok(compressed),
+            _ => Err(McaError::VerifyFailed {
+                x: coordinate.0,
+                z: coordinate.1,
             }),
-            CompressionType::GZip => unimplemented!("This is unused in practice and if you somehow need this, make an issue on github and i'll add it <3"),
-            CompressionType::Custom => unimplemented!("Haven't implemented this and i don't personally need this but make an issue on github and i'll fix it <3")
         }
     }
+
+    /// The default [`Codec`], reusing the zlib compressor & output buffers
+    pub(crate) fn compress_with_scratch(
+        &mut self,
+        compression: &CompressionType,
+        data: &[u8],
+        level: CompressionLevel,
+    ) -> Result<Vec<u8>, McaError> {
+        match compression {
+            #[cfg(feature = "zopfli")]
+            CompressionType::Zlib if level == CompressionLevel::Archival => zopfli_zlib(data),
+            CompressionType::Zlib => {
+                self.zlib(data, level.zlib_level())?;
+                Ok(self.output())
+            }
+            CompressionType::LZ4 => {
+                self.out.clear();
+                self.lz4.compress_into(&mut self.out, data)?;
+                Ok(self.output())
+            }
+            _ => compression.compress_with_level(data, level),
+        }
+    }
+
+    /// Keeps `buf` around to hold a later compression's output instead of allocating a new one
+    pub(crate) fn recycle(&mut self, mut buf: Vec<u8>) {
+        if buf.capacity() > 0 && self.spare.len() < CHUNK_COUNT {
+            buf.clear();
+            let at = self
+                .spare
+                .partition_point(|b| b.capacity() < buf.capacity());
+            self.spare.insert(at, buf);
+        }
+    }
+
+    /// The bytes held by the zlib compressor, the output buffer & recycled buffers
+    pub(crate) fn heap_size(&self) -> usize {
+        self.zlib
+            .as_ref()
+            .map_or(0, |_| std::mem::size_of::<CompressorOxide>())
+            + self.out.capacity()
+            + self.spare.iter().map(Vec::capacity).sum::<usize>()
+    }
+
+    /// A copy of `out`, in the smallest recycled buffer it fits if there is one
+    fn output(&mut self) -> Vec<u8> {
+        let at = self
+            .spare
+            .partition_point(|b| b.capacity() < self.out.len());
+        if at == self.spare.len() {
+            return self.out.clone();
+        }
+
+        let mut buf = self.spare.remove(at);
+        buf.extend_from_slice(&self.out);
+        buf
+    }
+
+    fn zlib(&mut self, mut data: &[u8], level: u8) -> Result<(), McaError> {
+        let compressor = self.zlib.get_or_insert_with(Box::default);
+        compressor.reset();
+        compressor.set_format_and_level(DataFormat::Zlib, level);
+
+        let len = self.out.capacity().max(data.len() / 2).max(64);
+        self.out.resize(len, 0);
+
+        let mut pos = 0;
+        loop {
+            let out = self.out.get_mut(pos..).unwrap_or_default();
+            let (status, read, written) = compress(compressor, data, out, TDEFLFlush::Finish);
+            pos += written;
+
+            match status {
+                TDEFLStatus::Done => break,
+                TDEFLStatus::Okay => {
+                    data = data.get(read..).unwrap_or_default();
+                    if self.out.len() - pos < 30 {
+                        self.out.resize(self.out.len() * 2, 0);
+                    }
+                }
+                status => return Err(McaError::ZLibCompression(status)),
+            }
+        }
+
+        self.out.truncate(pos);
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for CompressScratch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompressScratch").finish_non_exhaustive()
+    }
+}
+
+impl Clone for CompressScratch {
+    fn clone(&self) -> Self {
+        CompressScratch {
+            lz4: self.lz4,
+            codec: self.codec,
+            ..CompressScratch::default()
+        }
+    }
+}
+
+impl PartialEq for CompressScratch {
+    fn eq(&self, other: &Self) -> bool {
+        self.lz4 == other.lz4
+    }
 }
+
+impl Eq for CompressScratch {}
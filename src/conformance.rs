@@ -0,0 +1,166 @@
+//! Structural checks of vanilla region fixtures & the writer's output against them.
+
+use crate::{
+    compat::conformance_issue,
+    layout::{HEADER_SIZE, SECTOR_SIZE},
+    CompressionType, McaError, PendingChunk, RegionIter, RegionReader, RegionWriter,
+};
+
+// written by a vanilla server
+const FIXTURES: &[&[u8]] = &[include_bytes!("../benches/r.0.0.mca")];
+
+/// Every invariant vanilla relies on, plus zero padding which only this crate's writer guarantees
+fn assert_written_conformant(data: &[u8]) {
+    assert_eq!(conformance_issue(data), None);
+
+    let region = RegionReader::new(data).unwrap();
+    for idx in 0..RegionIter::MAX {
+        let (x, z) = RegionIter::get_chunk_coordinate(idx);
+        if let Some(padding) = region.padding_bytes(x, z).unwrap() {
+            assert!(
+                padding.iter().all(|b| *b == 0),
+                "({x}, {z}) has dirty padding"
+            );
+        }
+    }
+}
+
+#[test]
+fn vanilla_fixtures() {
+    for fixture in FIXTURES {
+        assert_eq!(conformance_issue(fixture), None);
+
+        let region = RegionReader::new(fixture).unwrap();
+        for idx in 0..RegionIter::MAX {
+            let (x, z) = RegionIter::get_chunk_coordinate(idx);
+            if let Some(chunk) = region.get_chunk(x, z).unwrap() {
+                chunk.decompress().unwrap();
+                assert_ne!(region.get_chunk_timestamp(x, z), 0);
+            }
+        }
+    }
+}
+
+#[test]
+fn writer_round_trip() {
+    for fixture in FIXTURES {
+        let region = RegionReader::new(fixture).unwrap();
+        let writer = RegionWriter::from_region(&region).unwrap();
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        assert_written_conformant(&buf);
+
+        let written = RegionReader::new(&buf).unwrap();
+        let first = written
+            .iter()
+            .flatten()
+            .flatten()
+            .map(|c| c.raw_data.as_ptr() as usize - buf.as_ptr() as usize)
+            .min()
+            .unwrap();
+        assert_eq!(first, HEADER_SIZE + 5);
+
+        for idx in 0..RegionIter::MAX {
+            let (x, z) = RegionIter::get_chunk_coordinate(idx);
+            assert_eq!(
+                written.get_chunk_timestamp(x, z),
+                region.get_chunk_timestamp(x, z)
+            );
+            assert_eq!(
+                written.get_chunk(x, z).unwrap(),
+                region.get_chunk(x, z).unwrap()
+            );
+        }
+
+        // a preserved layout keeps vanilla's slack bytes, so only the vanilla invariants apply
+        let mut preserved = RegionWriter::from_region(&region).unwrap();
+        preserved.preserve_layout(&region);
+        preserved.set_strict_vanilla(true);
+        preserved.push_chunk(&[10, 0, 0, 0], (3, 3)).unwrap();
+        buf.clear();
+        preserved.write(&mut buf).unwrap();
+        assert_eq!(conformance_issue(&buf), None);
+    }
+}
+
+#[test]
+fn empty_and_sparse_regions() {
+    let mut buf = vec![];
+    RegionWriter::new().write(&mut buf).unwrap();
+    assert_eq!(buf, vec![0; HEADER_SIZE]);
+    assert_written_conformant(&buf);
+
+    let mut writer = RegionWriter::new();
+    writer.set_strict_vanilla(true);
+    for (i, coordinate) in [(0, 0), (31, 0), (0, 31), (17, 9)].into_iter().enumerate() {
+        writer
+            .push_chunk_with_timestamp(
+                &[10, i as u8, 0, 0],
+                coordinate,
+                CompressionType::Zlib,
+                1000 + i as u32,
+            )
+            .unwrap();
+    }
+    buf.clear();
+    writer.write(&mut buf).unwrap();
+    assert_written_conformant(&buf);
+    assert_eq!(buf.len(), HEADER_SIZE + 4 * SECTOR_SIZE);
+
+    let region = RegionReader::new(&buf).unwrap();
+    assert_eq!(region.get_chunk_timestamp(0, 0), 1000);
+    assert_eq!(region.get_chunk_timestamp(31, 0), 1001);
+    assert_eq!(region.get_chunk_timestamp(0, 31), 1002);
+    assert_eq!(region.get_chunk_timestamp(17, 9), 1003);
+    assert_eq!(region.get_chunk_timestamp(1, 0), 0);
+}
+
+#[test]
+fn strict_vanilla_rejects() {
+    let mut writer = RegionWriter::new();
+    writer.set_strict_vanilla(true);
+    writer
+        .push_pending_chunk(PendingChunk::from_compressed(
+            vec![1, 2, 3],
+            CompressionType::Custom,
+            0,
+            (2, 2),
+        ))
+        .unwrap();
+    let mut buf = vec![];
+    assert!(matches!(
+        writer.write(&mut buf),
+        Err(McaError::NotVanilla(_))
+    ));
+    assert!(buf.is_empty());
+    assert!(writer.clone().into_bytes().is_err());
+
+    // identical payloads sharing sectors break vanilla once one of them is freed
+    let mut writer = RegionWriter::new();
+    writer.set_strict_vanilla(true);
+    writer.set_deduplicate(true);
+    writer.push_chunk(&[10, 0, 0, 0], (0, 0)).unwrap();
+    writer.push_chunk(&[10, 0, 0, 0], (1, 0)).unwrap();
+    assert!(matches!(
+        writer.write(&mut buf),
+        Err(McaError::NotVanilla(_))
+    ));
+    assert!(matches!(
+        writer.write_into(&mut [0; HEADER_SIZE * 2]),
+        Err(McaError::NotVanilla(_))
+    ));
+
+    // a base region with trailing bytes that aren't a whole sector
+    let mut base = FIXTURES[0].to_vec();
+    base.extend_from_slice(&[0; 100]);
+    let base = RegionReader::new(&base).unwrap();
+    let mut writer = RegionWriter::from_region(&base).unwrap();
+    writer.preserve_layout(&base);
+    writer.set_strict_vanilla(true);
+    writer.push_chunk(&[10, 0, 0, 0], (5, 5)).unwrap();
+    assert!(matches!(
+        writer.write(&mut buf),
+        Err(McaError::NotVanilla(_))
+    ));
+}
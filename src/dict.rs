@@ -0,0 +1,124 @@
+//! Helpers for building compression dictionaries out of chunk data.
+
+use crate::{layout::CHUNK_COUNT, McaError, RegionIter, RegionReader};
+
+/// Collects decompressed chunk payloads to train a compression dictionary (e.g. zstd) with.
+///
+/// Samples are taken round-robin across all regions, and within a region spread out over
+/// its generated chunks instead of just taking the first ones.  
+/// Chunks that fail to read or decompress are skipped, as are chunks that would push the
+/// total over `max_bytes`.
+///
+/// ## Example
+/// ```ignore
+/// use mca::{dict, RegionReader};
+///
+/// let regions = files.iter().map(|data| RegionReader::new(data)).collect::<Result<Vec<_>, _>>()?;
+///
+/// let samples = dict::collect_samples(regions.into_iter(), 1000, 64 * 1024 * 1024)?;
+/// ```
+pub fn collect_samples<'a>(
+    regions: impl Iterator<Item = RegionReader<'a>>,
+    max_samples: usize,
+    max_bytes: usize,
+) -> Result<Vec<Vec<u8>>, McaError> {
+    let regions: Vec<RegionReader<'a>> = regions.collect();
+
+    let orders: Vec<Vec<(usize, usize)>> = regions.iter().map(spread_order).collect();
+    let rounds = orders.iter().map(Vec::len).max().unwrap_or(0);
+
+    let mut samples = vec![];
+    let mut total = 0;
+
+    for round in 0..rounds {
+        for (region, order) in regions.iter().zip(orders.iter()) {
+            if samples.len() >= max_samples {
+                return Ok(samples);
+            }
+
+            let (x, z) = match order.get(round) {
+                Some(coord) => *coord,
+                None => continue,
+            };
+
+            let data = match region.get_chunk(x, z) {
+                Ok(Some(chunk)) => match chunk.decompress() {
+                    Ok(data) => data,
+                    Err(_) => continue,
+                },
+                _ => continue,
+            };
+
+            if total + data.len() > max_bytes {
+                continue;
+            }
+
+            total += data.len();
+            samples.push(data);
+        }
+    }
+
+    Ok(samples)
+}
+
+/// Orders a region's generated chunks so every prefix is spread out over the whole region,
+/// by sorting on the bit reversed position in header order.
+fn spread_order(region: &RegionReader) -> Vec<(usize, usize)> {
+    let occupancy = region.occupancy();
+
+    let mut present: Vec<(usize, (usize, usize))> = (0..CHUNK_COUNT)
+        .map(RegionIter::get_chunk_coordinate)
+        .filter(|&(x, z)| occupancy.get(x, z))
+        .enumerate()
+        .collect();
+
+    present.sort_by_key(|(i, _)| (*i as u32).reverse_bits());
+    present.into_iter().map(|(_, coord)| coord).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGION: &[u8] = include_bytes!("../benches/r.0.0.mca");
+
+    #[test]
+    fn sample_bounds() {
+        let region = RegionReader::new(REGION).unwrap();
+        let sources: Vec<Vec<u8>> = region
+            .iter()
+            .filter_map(|chunk| chunk.unwrap())
+            .map(|chunk| chunk.decompress().unwrap())
+            .collect();
+
+        let regions = vec![region.clone(), region.clone()];
+        let samples = collect_samples(regions.into_iter(), 20, usize::MAX).unwrap();
+        assert_eq!(samples.len(), 20);
+        for sample in samples.iter() {
+            assert!(sources.contains(sample));
+        }
+
+        let max_bytes = sources[0].len() * 3;
+        let samples = collect_samples(std::iter::once(region.clone()), 100, max_bytes).unwrap();
+        assert!(!samples.is_empty());
+        assert!(samples.iter().map(Vec::len).sum::<usize>() <= max_bytes);
+
+        let samples = collect_samples(std::iter::once(region), usize::MAX, usize::MAX).unwrap();
+        assert_eq!(samples.len(), sources.len());
+    }
+
+    #[test]
+    fn samples_are_spread() {
+        let region = RegionReader::new(REGION).unwrap();
+        let order = spread_order(&region);
+
+        // the first few picks shouldn't all come from the first rows of the region
+        let rows: Vec<usize> = order.iter().take(4).map(|(_, z)| *z).collect();
+        assert!(rows.iter().max().unwrap() - rows.iter().min().unwrap() >= 8);
+
+        let mut sorted = order.clone();
+        sorted.sort_by_key(|&(x, z)| (z, x));
+        sorted.dedup();
+        assert_eq!(sorted.len(), region.occupancy().count());
+    }
+}
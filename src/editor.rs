@@ -0,0 +1,1195 @@
+use std::{
+    fs::File,
+    io::{Cursor, ErrorKind, Read, Seek, SeekFrom, Write},
+};
+
+use crate::{
+    layout::{self, SectorMap, CHUNK_COUNT, HEADER_SIZE, MAX_CHUNK_SECTORS, SECTOR_SIZE},
+    writer::write_payload,
+    CompressionType, FirstFit, McaError, PendingChunk, RegionReader, RegionWriter, SectorAllocator,
+};
+
+#[cfg(feature = "fs-lock")]
+use crate::{LockedFile, RegionLock};
+#[cfg(feature = "fs-lock")]
+use std::path::Path;
+
+/// Sets the timestamps of chunks in a region, leaving every byte outside the timestamp table as is  
+/// Coordinates outside the region fail with [`McaError::InvalidCoordinate`], nothing is changed then.
+///
+/// Coordinates without a chunk are touched as well, use [`touch_timestamps_strict`] to reject those.
+///
+/// ## Example
+/// ```ignore
+/// let data = std::fs::read("r.0.0.mca")?;
+/// let data = mca::touch_timestamps(&data, &[((4, 6), 0), ((5, 6), 1700000000)])?;
+/// ```
+pub fn touch_timestamps(
+    region: &[u8],
+    updates: &[((usize, usize), u32)],
+) -> Result<Vec<u8>, McaError> {
+    touch(region, updates, false)
+}
+
+/// Same as [`touch_timestamps`] but fails with [`McaError::NotGenerated`] when a coordinate has no chunk
+pub fn touch_timestamps_strict(
+    region: &[u8],
+    updates: &[((usize, usize), u32)],
+) -> Result<Vec<u8>, McaError> {
+    touch(region, updates, true)
+}
+
+fn touch(
+    region: &[u8],
+    updates: &[((usize, usize), u32)],
+    strict: bool,
+) -> Result<Vec<u8>, McaError> {
+    let region = RegionReader::new(region)?.inner();
+    check_touches(updates, strict, |offset| {
+        layout::bytes_at(region, offset).is_some_and(|entry| entry != [0; 4])
+    })?;
+
+    let mut out = region.to_vec();
+    for ((x, z), timestamp) in updates {
+        let offset = SECTOR_SIZE + layout::header_offset_for(*x, *z);
+        if let Some(entry) = out.get_mut(offset..offset + 4) {
+            entry.copy_from_slice(&timestamp.to_be_bytes());
+        }
+    }
+
+    Ok(out)
+}
+
+/// Validates every update before anything is touched, `has_chunk` gets the location entry offset
+fn check_touches<F>(
+    updates: &[((usize, usize), u32)],
+    strict: bool,
+    has_chunk: F,
+) -> Result<(), McaError>
+where
+    F: Fn(usize) -> bool,
+{
+    for ((x, z), _) in updates {
+        if *x >= 32 || *z >= 32 {
+            return Err(McaError::InvalidCoordinate { x: *x, z: *z });
+        }
+        if strict && !has_chunk(layout::header_offset_for(*x, *z)) {
+            return Err(McaError::NotGenerated);
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies edits to an existing in-memory region (`mca`) file.  
+///
+/// Untouched chunks keep their compressed bytes & sector offsets verbatim, only chunks given to
+/// [`set_chunk`](RegionEditor::set_chunk) are compressed & placed in free sectors.  
+/// Externally stored chunks aren't supported, [`new`](RegionEditor::new) fails on them.
+///
+/// ## Example
+/// ```ignore
+/// use mca::{CompressionType, RegionEditor};
+///
+/// let mut editor = RegionEditor::new(std::fs::read("r.0.0.mca")?)?;
+///
+/// editor.set_chunk(4, 6, &nbt, CompressionType::Zlib)?;
+/// editor.remove_chunk(0, 0);
+///
+/// std::fs::write("r.0.0.mca", editor.into_bytes()?)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct RegionEditor {
+    writer: RegionWriter,
+}
+
+impl RegionEditor {
+    /// Creates an editor over the bytes of a region file
+    pub fn new(data: Vec<u8>) -> Result<RegionEditor, McaError> {
+        let region = RegionReader::new(&data)?;
+        let mut writer = RegionWriter::from_region(&region)?;
+        writer.preserve_layout(&region);
+
+        Ok(RegionEditor { writer })
+    }
+
+    fn coordinate(x: usize, z: usize) -> Result<(u8, u8), McaError> {
+        layout::check_coordinate(x, z)?;
+        Ok((x as u8, z as u8))
+    }
+
+    /// Sets the chunk at `x` `z` to `raw_data`, creating it if the region didn't have one  
+    /// Timestamp will be current time since `UNIX_EPOCH`
+    pub fn set_chunk(
+        &mut self,
+        x: usize,
+        z: usize,
+        raw_data: &[u8],
+        compression_type: CompressionType,
+    ) -> Result<(), McaError> {
+        self.writer
+            .push_chunk_with_compression(raw_data, Self::coordinate(x, z)?, compression_type)
+    }
+
+    /// Removes the chunk at `x` `z`, its location entry will be zeroed  
+    /// Returns the removed chunk if there was one
+    pub fn remove_chunk(&mut self, x: usize, z: usize) -> Option<PendingChunk> {
+        self.writer.remove_chunk(Self::coordinate(x, z).ok()?)
+    }
+
+    /// Sets the timestamp of the chunk at `x` `z`  
+    /// Fails with [`McaError::NotGenerated`] if there's no chunk there
+    pub fn set_timestamp(&mut self, x: usize, z: usize, timestamp: u32) -> Result<(), McaError> {
+        match self
+            .writer
+            .set_timestamp(Self::coordinate(x, z)?, timestamp)
+        {
+            true => Ok(()),
+            false => Err(McaError::NotGenerated),
+        }
+    }
+
+    /// Starts staging edits that are only applied once [committed](Transaction::commit)
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let mut tx = editor.begin();
+    /// for (x, z, nbt) in edits {
+    ///     // returning early drops `tx`, nothing is applied
+    ///     tx.set_chunk(x, z, &nbt, CompressionType::Zlib)?;
+    /// }
+    /// tx.commit()?;
+    /// ```
+    pub fn begin(&mut self) -> Transaction<'_> {
+        Transaction {
+            editor: self,
+            staged: vec![],
+        }
+    }
+
+    /// Writes the edited region into a new buffer
+    pub fn into_bytes(self) -> Result<Vec<u8>, McaError> {
+        let mut buf = Vec::with_capacity(self.writer.estimated_len());
+        self.writer.write(&mut buf)?;
+
+        Ok(buf)
+    }
+}
+
+/// Edits staged on a [`RegionEditor`], see [`begin`](RegionEditor::begin)  
+/// Dropping it without committing is the same as [`rollback`](Transaction::rollback).
+#[derive(Debug)]
+pub struct Transaction<'a> {
+    editor: &'a mut RegionEditor,
+    // new chunks, `None` for removed ones, applied in order on commit
+    staged: Vec<((u8, u8), Option<PendingChunk>)>,
+}
+
+impl Transaction<'_> {
+    /// Stages setting the chunk at `x` `z` to `raw_data`, it's compressed right away  
+    /// Fails with [`McaError::ChunkTooLarge`] if it needs more than 255 sectors, the editor can't store it externally.
+    /// Timestamp will be current time since `UNIX_EPOCH`
+    pub fn set_chunk(
+        &mut self,
+        x: usize,
+        z: usize,
+        raw_data: &[u8],
+        compression_type: CompressionType,
+    ) -> Result<(), McaError> {
+        let coordinate = RegionEditor::coordinate(x, z)?;
+        // compressed the way `RegionEditor::set_chunk` would, only queued on commit
+        let chunk = self
+            .editor
+            .writer
+            .prepare_chunk_now(raw_data, coordinate, compression_type)?;
+        if chunk.is_compressed()
+            && layout::sectors_for_payload(chunk.payload().len()) > MAX_CHUNK_SECTORS
+        {
+            return Err(McaError::ChunkTooLarge {
+                x: coordinate.0,
+                z: coordinate.1,
+                bytes: chunk.payload().len(),
+            });
+        }
+        self.staged.push((coordinate, Some(chunk)));
+
+        Ok(())
+    }
+
+    /// Stages removing the chunk at `x` `z`
+    pub fn remove_chunk(&mut self, x: usize, z: usize) -> Result<(), McaError> {
+        self.staged.push((RegionEditor::coordinate(x, z)?, None));
+
+        Ok(())
+    }
+
+    /// Applies every staged edit to the editor  
+    /// The edits are applied to a copy first, if any of them fails the editor is left untouched.
+    pub fn commit(self) -> Result<(), McaError> {
+        let mut writer = self.editor.writer.clone();
+        for (coordinate, chunk) in self.staged {
+            match chunk {
+                Some(chunk) => writer.push_pending_chunk(chunk)?,
+                None => {
+                    writer.remove_chunk(coordinate);
+                }
+            }
+        }
+        self.editor.writer = writer;
+
+        Ok(())
+    }
+
+    /// Discards every staged edit
+    pub fn rollback(self) {}
+}
+
+/// Edits a region (`mca`) file in place, like vanilla does.  
+///
+/// A replaced chunk is written to the first free sector run big enough for it,
+/// or appended to the end of the file if none fits, then its location & timestamp entries are patched.  
+/// The sectors it used before become free for later writes. See [`set_allocator`](RegionFileEditor::set_allocator) to change where chunks go.
+///
+/// Payloads are always written before the header is patched,
+/// so a crash in between leaves the old chunk in place.
+///
+/// ## Example
+/// ```ignore
+/// use mca::{CompressionType, RegionFileEditor};
+///
+/// let file = std::fs::OpenOptions::new().read(true).write(true).open("r.0.0.mca")?;
+/// let mut editor = RegionFileEditor::open(file)?;
+///
+/// editor.set_chunk(4, 6, &nbt, CompressionType::Zlib)?;
+/// ```
+#[derive(Debug)]
+pub struct RegionFileEditor<F: Read + Write + Seek> {
+    file: F,
+    locations: [[u8; 4]; CHUNK_COUNT],
+    sectors: SectorMap,
+    allocator: Box<dyn SectorAllocator>,
+}
+
+impl<F: Read + Write + Seek> RegionFileEditor<F> {
+    /// Opens an editor over a region file, reading its location header
+    pub fn open(mut file: F) -> Result<RegionFileEditor<F>, McaError> {
+        let len = file.seek(SeekFrom::End(0))? as usize;
+        file.seek(SeekFrom::Start(0))?;
+
+        let mut header = [0; SECTOR_SIZE];
+        match file.read_exact(&mut header) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Err(McaError::MissingHeader),
+            result => result?,
+        }
+        if len < HEADER_SIZE {
+            return Err(McaError::MissingHeader);
+        }
+
+        let mut editor = RegionFileEditor {
+            file,
+            locations: [[0; 4]; CHUNK_COUNT],
+            sectors: SectorMap::new(len),
+            allocator: Box::new(FirstFit),
+        };
+
+        for (location, entry) in editor.locations.iter_mut().zip(header.chunks_exact(4)) {
+            if let Ok(entry) = entry.try_into() {
+                *location = entry;
+            }
+            let (offset, count) = layout::decode_location(*location);
+            editor.sectors.reserve(offset, count);
+        }
+
+        Ok(editor)
+    }
+
+    /// Sets where replaced chunks are written, [`FirstFit`] by default like vanilla
+    ///
+    /// ## Example
+    /// ```ignore
+    /// // never overwrite freed sectors, defragment once done instead
+    /// editor.set_allocator(AppendOnly);
+    /// ```
+    pub fn set_allocator<A: SectorAllocator + 'static>(&mut self, allocator: A) {
+        self.allocator = Box::new(allocator);
+    }
+
+    /// Where `count` new sectors go, as picked by the allocator
+    fn allocate(&mut self, count: usize) -> Result<usize, McaError> {
+        let free = self.sectors.free_extents();
+        match self.allocator.allocate(count, &free) {
+            Some(start)
+                if free
+                    .iter()
+                    .any(|r| r.start <= start && start + count <= r.end) =>
+            {
+                Ok(start)
+            }
+            Some(start) => Err(McaError::InvalidAllocation { start, count }),
+            None => Ok(self.sectors.end()),
+        }
+    }
+
+    fn patch(&mut self, offset: usize, bytes: [u8; 4]) -> Result<(), McaError> {
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file.write_all(&bytes)?;
+        Ok(())
+    }
+
+    fn index(x: usize, z: usize) -> Result<usize, McaError> {
+        layout::check_coordinate(x, z)?;
+        Ok(layout::header_offset_for(x, z) / 4)
+    }
+
+    /// The location entry of a header slot, empty for slots outside the header
+    fn location(&self, index: usize) -> [u8; 4] {
+        self.locations.get(index).copied().unwrap_or([0; 4])
+    }
+
+    fn set_location(&mut self, index: usize, entry: [u8; 4]) {
+        if let Some(location) = self.locations.get_mut(index) {
+            *location = entry;
+        }
+    }
+
+    /// Compresses & writes a raw chunk at `x` `z`, replacing the one there if any  
+    /// Timestamp will be current time since `UNIX_EPOCH`
+    pub fn set_chunk(
+        &mut self,
+        x: usize,
+        z: usize,
+        raw_data: &[u8],
+        compression_type: CompressionType,
+    ) -> Result<(), McaError> {
+        let index = Self::index(x, z)?;
+        let entry = self.write_new_payload(x, z, raw_data, compression_type)?;
+
+        self.repoint(&[(index, entry, RegionWriter::get_current_timestamp()?)])
+    }
+
+    /// Compresses & writes a payload for the chunk at `x` `z` into newly allocated sectors, marking them used  
+    /// Returns the location entry pointing at it, the header isn't patched.
+    fn write_new_payload(
+        &mut self,
+        x: usize,
+        z: usize,
+        raw_data: &[u8],
+        compression_type: CompressionType,
+    ) -> Result<[u8; 4], McaError> {
+        let data = compression_type.compress(raw_data)?;
+
+        let sector_count = layout::sectors_for_payload(data.len());
+        if sector_count > MAX_CHUNK_SECTORS {
+            return Err(McaError::ChunkTooLarge {
+                x: x as u8,
+                z: z as u8,
+                bytes: data.len(),
+            });
+        }
+        let sector_count = sector_count as usize;
+
+        // the old sectors can't be reused for the new payload, a crash mid write would lose both
+        let offset = self.allocate(sector_count)?;
+        let entry = RegionWriter::location_entry(offset * SECTOR_SIZE, sector_count as u8)?;
+
+        self.file
+            .seek(SeekFrom::Start((offset * SECTOR_SIZE) as u64))?;
+        write_payload(&mut self.file, compression_type.to_u8(), &data)?;
+        self.file.flush()?;
+        self.sectors.mark(offset, sector_count, true);
+
+        Ok(entry)
+    }
+
+    /// Patches the location & timestamp entries of every header slot given, then frees the sectors they pointed at
+    fn repoint(&mut self, entries: &[(usize, [u8; 4], u32)]) -> Result<(), McaError> {
+        for (index, entry, timestamp) in entries {
+            self.patch(index * 4, *entry)?;
+            self.patch(SECTOR_SIZE + index * 4, timestamp.to_be_bytes())?;
+        }
+        self.file.flush()?;
+
+        for (index, entry, _) in entries {
+            let (old_offset, old_count) = layout::decode_location(self.location(*index));
+            self.sectors.mark(old_offset, old_count, false);
+            self.set_location(*index, *entry);
+        }
+        // sectors shared with a slot that wasn't repointed stay in use
+        for entry in self.locations {
+            let (offset, count) = layout::decode_location(entry);
+            self.sectors.reserve(offset, count);
+        }
+
+        Ok(())
+    }
+
+    /// Starts staging edits that only become visible in the file once [committed](FileTransaction::commit)
+    ///
+    /// Staged payloads go into free or appended sectors right away, only the header is patched on commit.
+    /// A crash or failed edit before that leaves every chunk as it was, at most some unused sectors are left behind.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let mut tx = editor.begin();
+    /// tx.set_chunk(4, 6, &nbt, CompressionType::Zlib)?;
+    /// tx.remove_chunk(0, 0)?;
+    /// tx.commit()?;
+    /// ```
+    pub fn begin(&mut self) -> FileTransaction<'_, F> {
+        FileTransaction {
+            editor: self,
+            staged: vec![],
+        }
+    }
+
+    /// Removes the chunk at `x` `z` by zeroing its location & timestamp entries  
+    /// Returns false if there was no chunk
+    pub fn remove_chunk(&mut self, x: usize, z: usize) -> Result<bool, McaError> {
+        let index = Self::index(x, z)?;
+        if self.location(index) == [0; 4] {
+            return Ok(false);
+        }
+
+        self.repoint(&[(index, [0; 4], 0)])?;
+
+        Ok(true)
+    }
+
+    /// Patches the timestamp entries of the given chunks in place, see [`touch_timestamps`]
+    pub fn touch_timestamps(&mut self, updates: &[((usize, usize), u32)]) -> Result<(), McaError> {
+        self.touch(updates, false)
+    }
+
+    /// Same as [`touch_timestamps`](RegionFileEditor::touch_timestamps) but fails with [`McaError::NotGenerated`] when a coordinate has no chunk
+    pub fn touch_timestamps_strict(
+        &mut self,
+        updates: &[((usize, usize), u32)],
+    ) -> Result<(), McaError> {
+        self.touch(updates, true)
+    }
+
+    fn touch(&mut self, updates: &[((usize, usize), u32)], strict: bool) -> Result<(), McaError> {
+        check_touches(updates, strict, |offset| {
+            self.location(offset / 4) != [0; 4]
+        })?;
+
+        for ((x, z), timestamp) in updates {
+            self.patch(
+                SECTOR_SIZE + layout::header_offset_for(*x, *z),
+                timestamp.to_be_bytes(),
+            )?;
+        }
+        self.file.flush()?;
+
+        Ok(())
+    }
+
+    /// The amount of sectors not used by the header or any chunk
+    pub fn free_sectors(&self) -> usize {
+        self.sectors.free()
+    }
+
+    /// Returns the underlying file
+    pub fn into_inner(self) -> F {
+        self.file
+    }
+}
+
+/// A file a [`RegionFileEditor`] can shrink, see [`defragment_in_place`](RegionFileEditor::defragment_in_place)
+pub trait SetLen {
+    /// Truncates or extends the file to `len` bytes
+    fn set_len(&mut self, len: u64) -> std::io::Result<()>;
+}
+
+impl SetLen for File {
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        File::set_len(self, len)
+    }
+}
+
+impl SetLen for Cursor<Vec<u8>> {
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        self.get_mut().resize(len as usize, 0);
+        Ok(())
+    }
+}
+
+/// Edits staged on a [`RegionFileEditor`], see [`begin`](RegionFileEditor::begin)  
+/// Dropping it without committing is the same as [`rollback`](FileTransaction::rollback).
+#[derive(Debug)]
+pub struct FileTransaction<'a, F: Read + Write + Seek> {
+    editor: &'a mut RegionFileEditor<F>,
+    // header slot, new location entry & timestamp of every staged edit, one per slot
+    staged: Vec<(usize, [u8; 4], u32)>,
+}
+
+impl<F: Read + Write + Seek> FileTransaction<'_, F> {
+    /// Stages replacing the chunk at `x` `z`, its payload is written right away  
+    /// Timestamp will be current time since `UNIX_EPOCH`
+    pub fn set_chunk(
+        &mut self,
+        x: usize,
+        z: usize,
+        raw_data: &[u8],
+        compression_type: CompressionType,
+    ) -> Result<(), McaError> {
+        let index = RegionFileEditor::<F>::index(x, z)?;
+        let entry = self
+            .editor
+            .write_new_payload(x, z, raw_data, compression_type)?;
+        self.unstage(index);
+        self.staged
+            .push((index, entry, RegionWriter::get_current_timestamp()?));
+
+        Ok(())
+    }
+
+    /// Stages removing the chunk at `x` `z`
+    pub fn remove_chunk(&mut self, x: usize, z: usize) -> Result<(), McaError> {
+        let index = RegionFileEditor::<F>::index(x, z)?;
+        self.unstage(index);
+        self.staged.push((index, [0; 4], 0));
+
+        Ok(())
+    }
+
+    /// Patches the header to point at every staged edit
+    pub fn commit(mut self) -> Result<(), McaError> {
+        let staged = std::mem::take(&mut self.staged);
+        self.editor.repoint(&staged)
+    }
+
+    /// Discards every staged edit, the sectors of staged payloads become free again
+    pub fn rollback(self) {}
+
+    /// Drops an earlier edit staged for the slot, freeing its payload's sectors
+    fn unstage(&mut self, index: usize) {
+        if let Some(at) = self.staged.iter().position(|(i, _, _)| *i == index) {
+            let (_, entry, _) = self.staged.remove(at);
+            let (offset, count) = layout::decode_location(entry);
+            self.editor.sectors.mark(offset, count, false);
+        }
+    }
+}
+
+impl<F: Read + Write + Seek> Drop for FileTransaction<'_, F> {
+    fn drop(&mut self) {
+        for (_, entry, _) in self.staged.drain(..) {
+            let (offset, count) = layout::decode_location(entry);
+            self.editor.sectors.mark(offset, count, false);
+        }
+    }
+}
+
+/// What [`RegionFileEditor::defragment_in_place`] changed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DefragStats {
+    /// Payloads moved toward the front, a payload shared by several chunks counts once
+    pub chunks_moved: usize,
+    /// File length in bytes before defragmenting
+    pub old_len: usize,
+    /// File length in bytes after defragmenting
+    pub new_len: usize,
+}
+
+impl<F: Read + Write + Seek + SetLen> RegionFileEditor<F> {
+    /// Slides every payload toward the front of the file, closing the gaps replaced & removed chunks left behind,
+    /// then truncates the file after the last one. Chunks sharing a payload keep sharing it.
+    ///
+    /// A payload is always copied before its location entry is patched, so every chunk stays readable if interrupted.
+    /// A payload that would overwrite its own start is copied past the end of the file first for the same reason.  
+    /// Fails with [`McaError::InvalidChunkPayload`] before moving anything if two payloads overlap.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let stats = editor.defragment_in_place()?;
+    /// println!("{} bytes freed", stats.old_len - stats.new_len);
+    /// ```
+    pub fn defragment_in_place(&mut self) -> Result<DefragStats, McaError> {
+        let old_len = self.file.seek(SeekFrom::End(0))? as usize;
+
+        // payloads in file order, with every header slot pointing at it
+        let mut used = (0..CHUNK_COUNT)
+            .filter(|index| self.location(*index) != [0; 4])
+            .map(|index| (layout::decode_location(self.location(index)), index))
+            .collect::<Vec<_>>();
+        used.sort_unstable();
+
+        let mut payloads: Vec<(usize, usize, Vec<usize>)> = vec![];
+        for ((offset, count), index) in used {
+            match payloads.last_mut() {
+                Some((last, last_count, slots)) if *last == offset && *last_count == count => {
+                    slots.push(index)
+                }
+                Some((last, last_count, _)) if offset < *last + *last_count => {
+                    return Err(McaError::InvalidChunkPayload(format!(
+                        "payloads at sectors {last} and {offset} overlap"
+                    )));
+                }
+                _ if offset < HEADER_SIZE / SECTOR_SIZE => {
+                    return Err(McaError::InvalidChunkPayload(format!(
+                        "payload at sector {offset} overlaps the header"
+                    )));
+                }
+                _ => payloads.push((offset, count, vec![index])),
+            }
+        }
+
+        let mut end = HEADER_SIZE / SECTOR_SIZE;
+        let mut chunks_moved = 0;
+        for (offset, count, slots) in payloads {
+            if offset != end {
+                let mut from = offset;
+                if end + count > offset {
+                    let len = self.file.seek(SeekFrom::End(0))? as usize;
+                    let tail = len.div_ceil(SECTOR_SIZE).max(offset + count);
+                    self.move_payload(from, tail, count, &slots)?;
+                    from = tail;
+                }
+                self.move_payload(from, end, count, &slots)?;
+                chunks_moved += 1;
+            }
+            end += count;
+        }
+
+        let new_len = end * SECTOR_SIZE;
+        self.file.set_len(new_len as u64)?;
+        self.file.flush()?;
+
+        self.sectors = SectorMap::new(new_len);
+        for entry in self.locations {
+            let (offset, count) = layout::decode_location(entry);
+            self.sectors.reserve(offset, count);
+        }
+
+        Ok(DefragStats {
+            chunks_moved,
+            old_len,
+            new_len,
+        })
+    }
+
+    /// Copies `count` sectors from `from` to `to`, then points `slots` at the copy  
+    /// The ranges mustn't overlap, sectors past the end of the file are copied as zeroes
+    fn move_payload(
+        &mut self,
+        from: usize,
+        to: usize,
+        count: usize,
+        slots: &[usize],
+    ) -> Result<(), McaError> {
+        let mut sector = [0; SECTOR_SIZE];
+        for i in 0..count {
+            self.file
+                .seek(SeekFrom::Start(((from + i) * SECTOR_SIZE) as u64))?;
+            sector.fill(0);
+            let mut read = 0;
+            while let Some(rest) = sector.get_mut(read..) {
+                match self.file.read(rest)? {
+                    0 => break,
+                    n => read += n,
+                }
+            }
+
+            self.file
+                .seek(SeekFrom::Start(((to + i) * SECTOR_SIZE) as u64))?;
+            self.file.write_all(&sector)?;
+        }
+        self.file.flush()?;
+
+        let entry = RegionWriter::location_entry(to * SECTOR_SIZE, count as u8)?;
+        for index in slots {
+            self.patch(index * 4, entry)?;
+            self.set_location(*index, entry);
+        }
+        self.file.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "fs-lock")]
+impl RegionFileEditor<LockedFile> {
+    /// Opens the region file at `path` for editing, blocking until its [`RegionLock`] is acquired  
+    /// The lock is held until the editor is dropped
+    pub fn open_locked(path: &Path) -> Result<RegionFileEditor<LockedFile>, McaError> {
+        let lock = RegionLock::lock(path)?;
+        RegionFileEditor::open(LockedFile::open(path, lock)?)
+    }
+
+    /// Same as [`open_locked`](RegionFileEditor::open_locked) but fails with [`McaError::Locked`]
+    /// instead of waiting when another writer holds the lock
+    pub fn try_open_locked(path: &Path) -> Result<RegionFileEditor<LockedFile>, McaError> {
+        let lock = RegionLock::try_lock(path)?;
+        RegionFileEditor::open(LockedFile::open(path, lock)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DuplicatePolicy;
+
+    const REGION: &[u8] = include_bytes!("../benches/r.0.0.mca");
+
+    fn temp_copy(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("mca-{name}-{}.mca", std::process::id()));
+        std::fs::write(&path, REGION).unwrap();
+        path
+    }
+
+    #[test]
+    fn transactions() {
+        let oversized = (0..SECTOR_SIZE * 256)
+            .map(|i| (i * 31 % 256) as u8)
+            .collect::<Vec<_>>();
+        let region = RegionReader::new(REGION).unwrap();
+        let (x, z) = (0..CHUNK_COUNT)
+            .map(|i| (i % 32, i / 32))
+            .find(|(x, z)| region.get_chunk(*x, *z).unwrap().is_some())
+            .unwrap();
+
+        let batch = |tx: &mut Transaction| -> Result<(), McaError> {
+            tx.set_chunk(1, 1, &[10, 1, 0, 0], CompressionType::Zlib)?;
+            tx.remove_chunk(x, z)?;
+            tx.set_chunk(2, 2, &oversized, CompressionType::Uncompressed)?;
+            tx.set_chunk(3, 3, &[10, 3, 0, 0], CompressionType::Zlib)
+        };
+
+        let mut editor = RegionEditor::new(REGION.to_vec()).unwrap();
+        let mut tx = editor.begin();
+        assert!(matches!(
+            batch(&mut tx),
+            Err(McaError::ChunkTooLarge { x: 2, z: 2, .. })
+        ));
+        drop(tx);
+        let mut tx = editor.begin();
+        tx.set_chunk(1, 1, &[10, 1, 0, 0], CompressionType::Zlib)
+            .unwrap();
+        tx.rollback();
+        assert_eq!(editor.clone().into_bytes().unwrap(), REGION);
+
+        // a commit failing halfway leaves none of its edits behind
+        editor.writer.set_duplicate_policy(DuplicatePolicy::Error);
+        let mut tx = editor.begin();
+        tx.remove_chunk(x, z).unwrap();
+        tx.set_chunk(1, 1, &[10, 1, 0, 0], CompressionType::Zlib)
+            .unwrap();
+        tx.set_chunk(1, 1, &[10, 1, 0, 0], CompressionType::Zlib)
+            .unwrap();
+        assert!(matches!(
+            tx.commit(),
+            Err(McaError::DuplicateChunk { x: 1, z: 1 })
+        ));
+        assert_eq!(editor.clone().into_bytes().unwrap(), REGION);
+        editor.writer.set_duplicate_policy(DuplicatePolicy::Replace);
+
+        // staged chunks are compressed like direct edits, at the writer's level
+        let mut direct = editor.clone();
+        direct
+            .set_chunk(1, 1, &oversized[..4096], CompressionType::Zlib)
+            .unwrap();
+        let mut tx = editor.begin();
+        tx.set_chunk(1, 1, &oversized[..4096], CompressionType::Zlib)
+            .unwrap();
+        tx.commit().unwrap();
+        assert_eq!(
+            editor.writer.get_chunk((1, 1)).unwrap().compressed_data(),
+            direct.writer.get_chunk((1, 1)).unwrap().compressed_data()
+        );
+
+        let mut tx = editor.begin();
+        tx.set_chunk(1, 1, &[10, 1, 0, 0], CompressionType::Zlib)
+            .unwrap();
+        tx.remove_chunk(x, z).unwrap();
+        tx.commit().unwrap();
+        let edited = editor.into_bytes().unwrap();
+        let edited = RegionReader::new(&edited).unwrap();
+        assert!(edited.get_chunk(x, z).unwrap().is_none());
+        assert_eq!(
+            edited
+                .get_chunk(1, 1)
+                .unwrap()
+                .unwrap()
+                .decompress()
+                .unwrap(),
+            [10, 1, 0, 0]
+        );
+    }
+
+    #[test]
+    fn file_transactions() {
+        let oversized = (0..SECTOR_SIZE * 256)
+            .map(|i| (i * 31 % 256) as u8)
+            .collect::<Vec<_>>();
+        let region = RegionReader::new(REGION).unwrap();
+        let (x, z) = (0..CHUNK_COUNT)
+            .map(|i| (i % 32, i / 32))
+            .find(|(x, z)| region.get_chunk(*x, *z).unwrap().is_some())
+            .unwrap();
+
+        let mut editor = RegionFileEditor::open(Cursor::new(REGION.to_vec())).unwrap();
+        let free = editor.free_sectors();
+        let mut tx = editor.begin();
+        tx.set_chunk(1, 1, &[10, 1, 0, 0], CompressionType::Zlib)
+            .unwrap();
+        tx.set_chunk(x, z, &[10, 2, 0, 0], CompressionType::Zlib)
+            .unwrap();
+        tx.remove_chunk(3, 3).unwrap();
+        assert!(matches!(
+            tx.set_chunk(2, 2, &oversized, CompressionType::Uncompressed),
+            Err(McaError::ChunkTooLarge { x: 2, z: 2, .. })
+        ));
+        drop(tx);
+        assert_eq!(editor.free_sectors(), free);
+
+        // staged payloads only ever land in unused sectors, every chunk reads as before
+        let staged = editor.into_inner().into_inner();
+        assert_eq!(staged[..HEADER_SIZE], REGION[..HEADER_SIZE]);
+        let staged_region = RegionReader::new(&staged).unwrap();
+        for index in 0..CHUNK_COUNT {
+            let (x, z) = (index % 32, index / 32);
+            assert_eq!(
+                staged_region.get_chunk(x, z).unwrap(),
+                region.get_chunk(x, z).unwrap()
+            );
+        }
+
+        let mut editor = RegionFileEditor::open(Cursor::new(staged)).unwrap();
+        let mut tx = editor.begin();
+        tx.set_chunk(1, 1, &[10, 1, 0, 0], CompressionType::Zlib)
+            .unwrap();
+        tx.set_chunk(x, z, &[10, 9, 0, 0], CompressionType::Zlib)
+            .unwrap();
+        tx.set_chunk(x, z, &[10, 2, 0, 0], CompressionType::Zlib)
+            .unwrap();
+        tx.remove_chunk(1, 1).unwrap();
+        tx.set_chunk(4, 4, &[10, 4, 0, 0], CompressionType::Zlib)
+            .unwrap();
+        tx.commit().unwrap();
+
+        let edited = editor.into_inner().into_inner();
+        let edited = RegionReader::new(&edited).unwrap();
+        let raw = |x, z| {
+            edited
+                .get_chunk(x, z)
+                .unwrap()
+                .map(|c| c.decompress().unwrap())
+        };
+        assert_eq!(raw(1, 1), None);
+        assert_eq!(raw(x, z), Some(vec![10, 2, 0, 0]));
+        assert_eq!(raw(4, 4), Some(vec![10, 4, 0, 0]));
+        assert_eq!(crate::compat::conformance_issue(edited.inner()), None);
+    }
+
+    #[test]
+    fn defragment_fragmented_file() {
+        let path = temp_copy("defragment");
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let mut editor = RegionFileEditor::open(file).unwrap();
+
+        // grow a few chunks past their sectors & drop others, leaving holes all over the file
+        let big = (0..SECTOR_SIZE * 2)
+            .map(|i| (i * 13 % 251) as u8)
+            .collect::<Vec<u8>>();
+        for (i, (x, z)) in [(0, 0), (5, 2), (8, 3), (20, 10)].into_iter().enumerate() {
+            let mut data = big.clone();
+            data[0] = i as u8;
+            editor
+                .set_chunk(x, z, &data, CompressionType::Uncompressed)
+                .unwrap();
+        }
+        for (x, z) in [(1, 0), (9, 3), (30, 30), (6, 2)] {
+            editor.remove_chunk(x, z).unwrap();
+        }
+        let before = std::fs::read(&path).unwrap();
+        assert!(editor.free_sectors() > 0);
+
+        let stats = editor.defragment_in_place().unwrap();
+        assert_eq!(editor.free_sectors(), 0);
+        assert!(stats.chunks_moved > 0);
+        assert_eq!(stats.old_len, before.len());
+        assert!(stats.new_len < stats.old_len);
+        drop(editor.into_inner());
+
+        let after = std::fs::read(&path).unwrap();
+        assert_eq!(after.len(), stats.new_len);
+        let (before, after) = (
+            RegionReader::new(&before).unwrap(),
+            RegionReader::new(&after).unwrap(),
+        );
+        let mut sectors = HEADER_SIZE / SECTOR_SIZE;
+        for z in 0..32 {
+            for x in 0..32 {
+                assert_eq!(
+                    before.get_chunk(x, z).unwrap(),
+                    after.get_chunk(x, z).unwrap()
+                );
+                assert_eq!(
+                    before.get_chunk_timestamp(x, z),
+                    after.get_chunk_timestamp(x, z)
+                );
+                let offset = RegionReader::chunk_offset(x, z).unwrap();
+                sectors += after.get_location(offset).map_or(0, |l| l[3] as usize);
+            }
+        }
+        assert_eq!(sectors * SECTOR_SIZE, stats.new_len);
+
+        // already packed, nothing left to move
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let mut editor = RegionFileEditor::open(file).unwrap();
+        let again = editor.defragment_in_place().unwrap();
+        assert_eq!(again.chunks_moved, 0);
+        assert_eq!(again.new_len, stats.new_len);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn defragment_rejects_overlapping_payloads() {
+        let mut data = REGION.to_vec();
+        let first = RegionReader::chunk_offset(0, 0).unwrap();
+        let second = RegionReader::chunk_offset(1, 0).unwrap();
+        let (offset, _) = layout::decode_location([
+            data[first],
+            data[first + 1],
+            data[first + 2],
+            data[first + 3],
+        ]);
+        let overlapping = RegionWriter::location_entry((offset + 1) * SECTOR_SIZE, 1).unwrap();
+        data[second..second + 4].copy_from_slice(&overlapping);
+
+        let mut editor = RegionFileEditor::open(Cursor::new(data.clone())).unwrap();
+        assert!(matches!(
+            editor.defragment_in_place(),
+            Err(McaError::InvalidChunkPayload(_))
+        ));
+        assert_eq!(editor.into_inner().into_inner(), data);
+    }
+
+    #[test]
+    fn file_editor_reuses_sectors() {
+        let path = temp_copy("file-editor");
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let mut editor = RegionFileEditor::open(file).unwrap();
+        let free = editor.free_sectors();
+
+        // a big incompressible chunk doesn't fit any gap and gets appended
+        let big = (0..SECTOR_SIZE * 3)
+            .map(|i| (i * 7 % 251) as u8)
+            .collect::<Vec<u8>>();
+        editor
+            .set_chunk(8, 3, &big, CompressionType::Uncompressed)
+            .unwrap();
+        let grown = std::fs::metadata(&path).unwrap().len() as usize;
+        assert_eq!(grown, REGION.len() + SECTOR_SIZE * 4);
+
+        // small chunks land in the sectors freed above
+        editor
+            .set_chunk(9, 3, &[10, 1], CompressionType::Zlib)
+            .unwrap();
+        editor
+            .set_chunk(0, 31, &[10, 2], CompressionType::LZ4)
+            .unwrap();
+        assert!(editor.remove_chunk(10, 3).unwrap());
+        assert!(!editor.remove_chunk(10, 3).unwrap());
+        assert_eq!(std::fs::metadata(&path).unwrap().len() as usize, grown);
+        assert!(editor.free_sectors() >= free);
+        drop(editor.into_inner());
+
+        let data = std::fs::read(&path).unwrap();
+        let region = RegionReader::new(REGION).unwrap();
+        let edited = RegionReader::new(&data).unwrap();
+
+        let read = |x, z| {
+            edited
+                .get_chunk(x, z)
+                .unwrap()
+                .unwrap()
+                .decompress()
+                .unwrap()
+        };
+        assert_eq!(read(8, 3), big);
+        assert_eq!(read(9, 3), vec![10, 1]);
+        assert_eq!(read(0, 31), vec![10, 2]);
+        assert!(edited.get_chunk(10, 3).unwrap().is_none());
+
+        for z in 0..32 {
+            for x in 0..32 {
+                if [(8, 3), (9, 3), (10, 3), (0, 31)].contains(&(x, z)) {
+                    continue;
+                }
+
+                let old = region.get_chunk(x, z).unwrap();
+                let new = edited.get_chunk(x, z).unwrap();
+                assert_eq!(old.is_some(), new.is_some());
+                if let (Some(old), Some(new)) = (old, new) {
+                    assert_eq!(old.decompress().unwrap(), new.decompress().unwrap());
+                }
+            }
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn assert_only_timestamps_changed(old: &[u8], new: &[u8], touched: &[(usize, usize)]) {
+        assert_eq!(old.len(), new.len());
+        for (i, (a, b)) in old.iter().zip(new).enumerate() {
+            if a == b {
+                continue;
+            }
+            let slot = touched
+                .iter()
+                .map(|(x, z)| SECTOR_SIZE + layout::header_offset_for(*x, *z))
+                .any(|offset| (offset..offset + 4).contains(&i));
+            assert!(slot, "byte {i} changed");
+        }
+    }
+
+    #[test]
+    fn touch_timestamps_in_memory() {
+        let region = RegionReader::new(REGION).unwrap();
+        let empty = (0..1024)
+            .map(|i| (i % 32, i / 32))
+            .find(|(x, z)| region.get_chunk(*x, *z).unwrap().is_none())
+            .unwrap();
+
+        let updates = [((8, 3), 0), ((9, 3), 1_700_000_000), (empty, 5)];
+        let data = touch_timestamps(REGION, &updates).unwrap();
+        assert_only_timestamps_changed(REGION, &data, &[(8, 3), (9, 3), empty]);
+
+        let touched = RegionReader::new(&data).unwrap();
+        assert_eq!(touched.get_chunk_timestamp(8, 3), 0);
+        assert_eq!(touched.get_chunk_timestamp(9, 3), 1_700_000_000);
+        assert_eq!(touched.get_chunk_timestamp(empty.0, empty.1), 5);
+
+        assert!(matches!(
+            touch_timestamps_strict(REGION, &updates),
+            Err(McaError::NotGenerated)
+        ));
+        assert!(matches!(
+            touch_timestamps(REGION, &[((8, 3), 0), ((32, 0), 0)]),
+            Err(McaError::InvalidCoordinate { x: 32, z: 0 })
+        ));
+        let strict = touch_timestamps_strict(REGION, &updates[..2]).unwrap();
+        assert_only_timestamps_changed(REGION, &strict, &[(8, 3), (9, 3)]);
+    }
+
+    #[test]
+    fn touch_timestamps_in_file() {
+        let path = temp_copy("touch-timestamps");
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let mut editor = RegionFileEditor::open(file).unwrap();
+
+        editor
+            .touch_timestamps_strict(&[((8, 3), 1), ((9, 3), 2)])
+            .unwrap();
+        assert!(matches!(
+            editor.touch_timestamps(&[((0, 40), 1)]),
+            Err(McaError::InvalidCoordinate { x: 0, z: 40 })
+        ));
+        drop(editor.into_inner());
+
+        let data = std::fs::read(&path).unwrap();
+        assert_only_timestamps_changed(REGION, &data, &[(8, 3), (9, 3)]);
+        let region = RegionReader::new(&data).unwrap();
+        assert_eq!(region.get_chunk_timestamp(8, 3), 1);
+        assert_eq!(region.get_chunk_timestamp(9, 3), 2);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn edit_single_chunk() {
+        let region = RegionReader::new(REGION).unwrap();
+        let untouched = region.get_chunk(8, 3).unwrap().unwrap();
+
+        let mut editor = RegionEditor::new(REGION.to_vec()).unwrap();
+        editor
+            .set_chunk(8, 3, &[10, 1, 2, 3], CompressionType::LZ4)
+            .unwrap();
+        let buf = editor.into_bytes().unwrap();
+
+        let edited = RegionReader::new(&buf).unwrap();
+        let chunk = edited.get_chunk(8, 3).unwrap().unwrap();
+        assert_eq!(chunk.get_compression_type(), CompressionType::LZ4);
+        assert_eq!(chunk.decompress().unwrap(), vec![10, 1, 2, 3]);
+        assert_ne!(chunk.raw_data, untouched.raw_data);
+
+        for z in 0..32 {
+            for x in 0..32 {
+                if (x, z) == (8, 3) {
+                    continue;
+                }
+
+                let old = region.get_chunk(x, z).unwrap();
+                let new = edited.get_chunk(x, z).unwrap();
+                assert_eq!(old.is_some(), new.is_some());
+                if let (Some(old), Some(new)) = (old, new) {
+                    assert_eq!(old.raw_data, new.raw_data);
+                    assert_eq!(old.decompress().unwrap(), new.decompress().unwrap());
+                }
+                assert_eq!(
+                    region.get_chunk_timestamp(x, z),
+                    edited.get_chunk_timestamp(x, z)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn remove_create_and_timestamps() {
+        let region = RegionReader::new(REGION).unwrap();
+        let (empty_x, empty_z) = (0..1024)
+            .map(|i| (i % 32, i / 32))
+            .find(|(x, z)| region.get_chunk(*x, *z).unwrap().is_none())
+            .unwrap();
+
+        let mut editor = RegionEditor::new(REGION.to_vec()).unwrap();
+        assert!(editor.remove_chunk(8, 3).is_some());
+        assert!(editor.remove_chunk(8, 3).is_none());
+        assert!(matches!(
+            editor.set_timestamp(empty_x, empty_z, 5),
+            Err(McaError::NotGenerated)
+        ));
+
+        editor
+            .set_chunk(empty_x, empty_z, &[10, 0], CompressionType::Zlib)
+            .unwrap();
+        editor.set_timestamp(empty_x, empty_z, 5).unwrap();
+        editor.set_timestamp(9, 3, 6).unwrap();
+        let buf = editor.into_bytes().unwrap();
+
+        let edited = RegionReader::new(&buf).unwrap();
+        let offset = RegionReader::chunk_offset(8, 3).unwrap();
+        assert_eq!(buf[offset..offset + 4], [0, 0, 0, 0]);
+        assert!(edited.get_chunk(8, 3).unwrap().is_none());
+        assert_eq!(edited.get_chunk_timestamp(empty_x, empty_z), 5);
+        assert_eq!(edited.get_chunk_timestamp(9, 3), 6);
+        assert_eq!(
+            edited
+                .get_chunk(empty_x, empty_z)
+                .unwrap()
+                .unwrap()
+                .decompress()
+                .unwrap(),
+            vec![10, 0]
+        );
+    }
+}
@@ -1,7 +1,10 @@
-use miniz_oxide::inflate;
+use miniz_oxide::{deflate::core::TDEFLStatus, inflate};
 use thiserror::Error;
 
+use crate::{CompressionType, MinVersion, TargetVersion};
+
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum McaError {
     #[error("Chunk hasn't been generated yet")]
     NotGenerated,
@@ -12,13 +15,121 @@ pub enum McaError {
     #[error("Invalid chunk: {0}")]
     InvalidChunkPayload(String),
 
+    #[error("Region is {actual} bytes but its location table needs {expected}")]
+    TruncatedRegion { expected: usize, actual: usize },
+
+    #[error("Chunk ({x}, {z}) has a payload length of 0")]
+    EmptyPayload { x: usize, z: usize },
+
+    #[error("Chunk ({x}, {z}) is cut off by the end of the region, {missing} bytes are missing")]
+    TruncatedChunk { x: usize, z: usize, missing: usize },
+
+    #[error("Unknown compression type {0}")]
+    UnknownCompression(u8),
+
+    #[error("{0:?} compression isn't implemented")]
+    UnimplementedCompression(CompressionType),
+
     #[cfg(not(feature = "unsafe"))]
     #[error("Out of bounds byte access")]
     OutOfBoundsByte,
 
+    #[error("Chunk ({x}, {z}) has already been pushed")]
+    DuplicateChunk { x: u8, z: u8 },
+
+    #[error("Chunk ({x}, {z}) is {bytes} bytes compressed, more than fits in 255 sectors")]
+    ChunkTooLarge { x: u8, z: u8, bytes: usize },
+
+    #[error("Region is too large, sector offset {sector} doesn't fit in a location entry")]
+    RegionTooLarge { sector: usize },
+
+    #[error("Chunk coordinate ({x}, {z}) is outside the region")]
+    InvalidCoordinate { x: usize, z: usize },
+
+    #[cfg(feature = "fastnbt")]
+    #[error("Chunk NBT is at {found:?} but was pushed to {expected:?}")]
+    PositionMismatch {
+        expected: (i32, i32),
+        found: (i32, i32),
+    },
+
+    #[cfg(feature = "fastnbt")]
+    #[error("Moving region ({}, {}) to ({}, {}) overflows chunk coordinates", from.0, from.1, to.0, to.1)]
+    RelocationOverflow { from: (i32, i32), to: (i32, i32) },
+
+    #[error("Chunk ({}, {}) is outside region ({}, {})", chunk.0, chunk.1, region.0, region.1)]
+    ChunkOutsideRegion {
+        chunk: (i32, i32),
+        region: (i32, i32),
+    },
+
+    #[error("Writer doesn't know its region position, create it with `RegionWriter::for_region`")]
+    UnknownRegion,
+
+    #[error("System clock is set before the unix epoch")]
+    ClockError,
+
+    #[error("Buffer is {provided} bytes but the region needs {needed}")]
+    BufferTooSmall { needed: usize, provided: usize },
+
+    #[error("Chunk ({x}, {z}) doesn't decompress back to what was compressed")]
+    VerifyFailed { x: u8, z: u8 },
+
+    #[error("Chunk ({x}, {z}) doesn't match the layout it's written with")]
+    LayoutMismatch { x: u8, z: u8 },
+
+    #[error("Sector allocator picked {count} sectors at {start}, which aren't all free")]
+    InvalidAllocation { start: usize, count: usize },
+
+    #[error("Generating chunk ({x}, {z}) failed: {source}")]
+    GenerateFailed {
+        x: u8,
+        z: u8,
+        #[source]
+        source: Box<McaError>,
+    },
+
+    #[error("LZ4 block size {0} isn't between 64 bytes & 32 MiB")]
+    InvalidLz4BlockSize(usize),
+
+    #[error("{} isn't named like an external chunk file, c.x.z.mcc", .0.display())]
+    InvalidMccName(std::path::PathBuf),
+
+    #[error("Region wouldn't be vanilla conformant, {0}")]
+    NotVanilla(String),
+
+    #[error(
+        "{compression:?} compression needs vanilla {required} but the writer targets {target}"
+    )]
+    UnsupportedCompression {
+        compression: CompressionType,
+        required: MinVersion,
+        target: TargetVersion,
+    },
+
+    #[cfg(feature = "fs-lock")]
+    #[error("Region file {} is locked by another writer", .0.display())]
+    Locked(std::path::PathBuf),
+
+    #[cfg(feature = "rayon")]
+    #[error("Writing {} region files failed{}", .0.len(), first_failure(.0))]
+    FlushFailed(Vec<(crate::RegionPos, McaError)>),
+
     #[error("Io failed: {0}")]
     IoError(#[from] std::io::Error),
 
     #[error("Zlib Decompression failed: {0}")]
     ZLib(#[from] inflate::DecompressError),
+
+    #[error("Zlib Compression failed: {0:?}")]
+    ZLibCompression(TDEFLStatus),
+}
+
+/// Where & why the first of the failed region files failed, if there's one
+#[cfg(feature = "rayon")]
+fn first_failure(failed: &[(crate::RegionPos, McaError)]) -> String {
+    match failed.first() {
+        Some((pos, error)) => format!(", the first at {pos:?}: {error}"),
+        None => String::new(),
+    }
 }
@@ -0,0 +1,151 @@
+use std::ops::Range;
+
+use crate::{McaError, RawChunk, RegionIter, RegionReader, RegionWriter};
+
+/// Copies the chunks inside `x_range` & `z_range` into a new region, every other slot is left empty.
+/// Payloads & timestamps are copied verbatim and chunks keep their coordinates.
+///
+/// Fails with [`McaError::InvalidCoordinate`] if a range reaches past the region,
+/// and on externally stored chunks since their data lives in a separate `.mcc` file.
+///
+/// ## Example
+/// ```ignore
+/// let region = RegionReader::new(&data)?;
+/// let area = mca::extract_area(&region, 10..14, 20..24)?;
+///
+/// std::fs::write("r.0.0.mca", &area)?;
+/// ```
+pub fn extract_area(
+    src: &RegionReader,
+    x_range: Range<usize>,
+    z_range: Range<usize>,
+) -> Result<Vec<u8>, McaError> {
+    if x_range.end > 32 || z_range.end > 32 {
+        return Err(McaError::InvalidCoordinate {
+            x: x_range.end.max(1) - 1,
+            z: z_range.end.max(1) - 1,
+        });
+    }
+
+    let mut writer = RegionWriter::new();
+    for z in z_range {
+        for x in x_range.clone() {
+            writer.copy_chunk_from(src, (x, z), (x as u8, z as u8))?;
+        }
+    }
+
+    writer.into_bytes()
+}
+
+/// Copies the chunks `pred` returns true for into a new region, every other slot is left empty.  
+/// `pred` is called with each present chunk's coordinate, payload & timestamp, in header order.
+/// Payloads & timestamps are copied verbatim and chunks keep their coordinates.
+///
+/// Fails on kept externally stored chunks since their data lives in a separate `.mcc` file.
+///
+/// ## Example
+/// ```ignore
+/// let region = RegionReader::new(&data)?;
+///
+/// // drop chunks nobody visited since the last cleanup
+/// let kept = mca::retain(&region, |_, _, _, timestamp| timestamp >= last_cleanup)?;
+///
+/// // drop chunks whose NBT doesn't parse
+/// let kept = mca::retain(&region, |_, _, chunk, _| {
+///     chunk.decompress().is_ok_and(|nbt| parse(&nbt).is_ok())
+/// })?;
+/// ```
+pub fn retain(
+    src: &RegionReader,
+    mut pred: impl FnMut(usize, usize, &RawChunk, u32) -> bool,
+) -> Result<Vec<u8>, McaError> {
+    let mut writer = RegionWriter::new();
+    for idx in 0..RegionIter::MAX {
+        let (x, z) = RegionIter::get_chunk_coordinate(idx);
+        let Some(chunk) = src.get_chunk(x, z)? else {
+            continue;
+        };
+
+        if pred(x, z, &chunk, src.get_chunk_timestamp(x, z)) {
+            writer.copy_chunk_from(src, (x, z), (x as u8, z as u8))?;
+        }
+    }
+
+    writer.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGION: &[u8] = include_bytes!("../benches/r.0.0.mca");
+
+    #[test]
+    fn extracts_only_the_area() {
+        let region = RegionReader::new(REGION).unwrap();
+        let area = extract_area(&region, 10..14, 20..24).unwrap();
+        let area = RegionReader::new(&area).unwrap();
+        assert!(area.occupancy().count() > 0);
+
+        for idx in 0..RegionIter::MAX {
+            let (x, z) = RegionIter::get_chunk_coordinate(idx);
+            let chunk = area.get_chunk(x, z).unwrap();
+            if (10..14).contains(&x) && (20..24).contains(&z) {
+                assert_eq!(chunk, region.get_chunk(x, z).unwrap());
+                if chunk.is_some() {
+                    assert_eq!(
+                        area.get_chunk_timestamp(x, z),
+                        region.get_chunk_timestamp(x, z)
+                    );
+                }
+            } else {
+                assert!(chunk.is_none());
+            }
+        }
+
+        assert!(matches!(
+            extract_area(&region, 30..33, 0..1),
+            Err(McaError::InvalidCoordinate { x: 32, z: 0 })
+        ));
+        let empty = extract_area(&region, 5..5, 0..32).unwrap();
+        assert_eq!(RegionReader::new(&empty).unwrap().occupancy().count(), 0);
+    }
+
+    #[test]
+    fn retains_matching_chunks() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut seen = vec![];
+        let kept = retain(&region, |x, z, chunk, timestamp| {
+            assert_eq!(Some(chunk), region.get_chunk(x, z).unwrap().as_ref());
+            assert_eq!(timestamp, region.get_chunk_timestamp(x, z));
+            seen.push((x, z));
+            x % 3 == 0 && z < 16
+        })
+        .unwrap();
+        let kept = RegionReader::new(&kept).unwrap();
+        assert_eq!(seen.len(), region.occupancy().count());
+
+        let mut survivors = 0;
+        for idx in 0..RegionIter::MAX {
+            let (x, z) = RegionIter::get_chunk_coordinate(idx);
+            let chunk = kept.get_chunk(x, z).unwrap();
+            if x % 3 == 0 && z < 16 {
+                assert_eq!(chunk, region.get_chunk(x, z).unwrap());
+                if chunk.is_some() {
+                    survivors += 1;
+                    assert_eq!(
+                        kept.get_chunk_timestamp(x, z),
+                        region.get_chunk_timestamp(x, z)
+                    );
+                }
+            } else {
+                assert!(chunk.is_none());
+            }
+        }
+        assert!(survivors > 0);
+        assert_eq!(kept.occupancy().count(), survivors);
+
+        let none = retain(&region, |_, _, _, _| false).unwrap();
+        assert_eq!(RegionReader::new(&none).unwrap().occupancy().count(), 0);
+    }
+}
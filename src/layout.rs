@@ -0,0 +1,337 @@
+//! Constants & sector math describing the on-disk layout of a region file.
+//!
+//! A region starts with an 8 KiB header, one sector of 4-byte location entries
+//! followed by one sector of 4-byte timestamps, both indexed by [`header_offset_for`].  
+//! Chunk payloads follow in whole sectors, each being a 4-byte big endian length,
+//! 1 compression byte and then the compressed data, zero padded up to the next sector.
+//!
+//! ## Payload layout
+//! For a payload starting at byte `start`, the first byte of its first sector,
+//! with `length` in its length field:
+//!
+//! | bytes | contents |
+//! |---|---|
+//! | `start..start + 4` | `length`, counting the compression byte & data but not itself |
+//! | `start + 4` | the compression byte, see [`EXTERNAL_FLAG`] |
+//! | `start + 5..payload_end(start, length)` | the compressed data, `length - 1` bytes |
+//! | `payload_end(start, length)..` | padding up to the end of the last allocated sector |
+//!
+//! A `length` of 0 has no compression byte either, the slot reads as empty.
+
+use std::ops::Range;
+
+use crate::McaError;
+
+/// The size of one sector, all offsets & lengths in the header are counted in sectors
+pub const SECTOR_SIZE: usize = 4096;
+
+/// The size of the location & timestamp tables combined
+pub const HEADER_SIZE: usize = SECTOR_SIZE * 2;
+
+/// The amount of chunk slots in a region
+pub const CHUNK_COUNT: usize = 32 * 32;
+
+/// The most sectors a single chunk can occupy, its sector count is stored in one byte
+pub const MAX_CHUNK_SECTORS: u32 = u8::MAX as u32;
+
+/// The highest sector offset a location entry can point to, it's stored in 3 bytes
+pub const MAX_SECTOR_OFFSET: usize = 0xFF_FFFF;
+
+/// Set on a payload's compression byte when the chunk data is stored in an external `c.x.z.mcc` file.  
+/// The payload in the region itself is then just a 1 byte long stub.
+pub const EXTERNAL_FLAG: u8 = 0x80;
+
+/// The size of a payload's big endian length field
+pub const LENGTH_FIELD_SIZE: usize = 4;
+
+/// Bytes in front of the compressed data in every payload, the length field and compression byte
+pub const PAYLOAD_HEADER_SIZE: usize = LENGTH_FIELD_SIZE + 1;
+
+/// The end of a payload starting at byte `start` with `length` in its length field,
+/// exclusive, see the [payload layout](self#payload-layout)
+#[inline]
+pub fn payload_end(start: usize, length: usize) -> usize {
+    start + LENGTH_FIELD_SIZE + length
+}
+
+/// Get the byte offset of a chunk's entry inside the location table.  
+/// The timestamp entry for the same chunk is at `SECTOR_SIZE + header_offset_for(x, z)`
+///
+/// Panics outside the region, use [`RegionReader::chunk_offset`](crate::RegionReader::chunk_offset)
+/// to have those rejected with an error instead.
+#[inline(always)]
+pub fn header_offset_for(x: usize, z: usize) -> usize {
+    assert!(x < 32);
+    assert!(z < 32);
+
+    4 * (x + z * 32)
+}
+
+/// Get the amount of sectors a payload with `len` bytes of compressed data occupies
+#[inline]
+pub fn sectors_for_payload(len: usize) -> u32 {
+    (len + PAYLOAD_HEADER_SIZE).div_ceil(SECTOR_SIZE) as u32
+}
+
+/// Get the amount of zero bytes needed after a payload with `len` bytes of compressed data
+/// to end it on a sector boundary
+#[inline]
+pub fn padding_for(len: usize) -> usize {
+    (SECTOR_SIZE - (len + PAYLOAD_HEADER_SIZE) % SECTOR_SIZE) % SECTOR_SIZE
+}
+
+/// Errors with [`McaError::InvalidCoordinate`] unless both `x` & `z` are inside a region
+pub(crate) fn check_coordinate(x: usize, z: usize) -> Result<(), McaError> {
+    if x >= 32 || z >= 32 {
+        return Err(McaError::InvalidCoordinate { x, z });
+    }
+
+    Ok(())
+}
+
+/// The 4 bytes at `offset` of `data`, a header entry or a payload's length field  
+/// `None` when they'd reach past the end
+#[inline]
+pub(crate) fn bytes_at(data: &[u8], offset: usize) -> Option<[u8; 4]> {
+    data.get(offset..offset.checked_add(4)?)?.try_into().ok()
+}
+
+/// Splits a location entry into its sector offset & sector count
+#[inline]
+pub(crate) fn decode_location(entry: [u8; 4]) -> (usize, usize) {
+    let offset = u32::from_be_bytes([0, entry[0], entry[1], entry[2]]) as usize;
+    (offset, entry[3] as usize)
+}
+
+/// Where one chunk's payload sits in a region
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChunkPlacement {
+    /// The first sector of the payload, counted from the start of the region
+    pub sector_offset: usize,
+    /// How many sectors the payload occupies
+    pub sector_count: usize,
+    /// The payload length including its length field, compression byte & padding
+    pub padded_len: usize,
+}
+
+impl ChunkPlacement {
+    /// The 4 byte location table entry pointing at this placement
+    pub fn location_entry(&self) -> [u8; 4] {
+        let offset = (self.sector_offset as u32).to_be_bytes();
+        [offset[1], offset[2], offset[3], self.sector_count as u8]
+    }
+}
+
+/// Where every chunk of a region goes, see [`RegionWriter::layout`](crate::RegionWriter::layout)  
+/// Chunks sharing a payload (deduplicated) have identical placements.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Layout {
+    placements: Vec<Option<ChunkPlacement>>,
+    file_len: usize,
+}
+
+impl Layout {
+    /// Builds a layout from a location table, empty entries have no placement
+    pub(crate) fn from_locations(
+        locations: impl Iterator<Item = [u8; 4]>,
+        file_len: usize,
+    ) -> Layout {
+        let placements = locations
+            .map(|entry| match decode_location(entry) {
+                (_, 0) => None,
+                (sector_offset, sector_count) => Some(ChunkPlacement {
+                    sector_offset,
+                    sector_count,
+                    padded_len: sector_count * SECTOR_SIZE,
+                }),
+            })
+            .collect();
+
+        Layout {
+            placements,
+            file_len,
+        }
+    }
+
+    /// The placement of the chunk at `x` `z`, `None` if the slot stays empty
+    pub fn get(&self, x: usize, z: usize) -> Option<ChunkPlacement> {
+        check_coordinate(x, z).ok()?;
+        self.placements.get(header_offset_for(x, z) / 4).copied()?
+    }
+
+    /// Iterates over every placed chunk in header order, with its coordinate
+    pub fn iter(&self) -> impl Iterator<Item = ((usize, usize), ChunkPlacement)> + '_ {
+        self.placements
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, placement)| placement.map(|p| ((idx % 32, idx / 32), p)))
+    }
+
+    /// The length of the whole region file in bytes, header included
+    pub fn file_len(&self) -> usize {
+        self.file_len
+    }
+}
+
+/// Tracks which sectors of a region are in use, the two header sectors always are
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct SectorMap {
+    used: Vec<bool>,
+}
+
+impl SectorMap {
+    /// A map for a region of `len` bytes with nothing but the header in use
+    pub(crate) fn new(len: usize) -> SectorMap {
+        let mut map = SectorMap {
+            used: vec![false; len.div_ceil(SECTOR_SIZE)],
+        };
+        map.mark(0, HEADER_SIZE / SECTOR_SIZE, true);
+        map
+    }
+
+    pub(crate) fn mark(&mut self, offset: usize, count: usize, used: bool) {
+        if offset + count > self.used.len() {
+            self.used.resize(offset + count, false);
+        }
+        self.used
+            .iter_mut()
+            .skip(offset)
+            .take(count)
+            .for_each(|u| *u = used);
+        self.used
+            .iter_mut()
+            .take(HEADER_SIZE / SECTOR_SIZE)
+            .for_each(|u| *u = true);
+    }
+
+    /// Marks the sectors of an existing location as used, only those inside the region  
+    /// A corrupt location far past the end would otherwise have new payloads appended gigabytes away.
+    pub(crate) fn reserve(&mut self, offset: usize, count: usize) {
+        let end = (offset + count).min(self.used.len());
+        if offset < end {
+            self.mark(offset, end - offset, true);
+        }
+    }
+
+    /// The first free run of `count` sectors, past the end of the region if none fits
+    pub(crate) fn allocate(&self, count: usize) -> usize {
+        let mut run = 0;
+        for (sector, used) in self.used.iter().enumerate() {
+            if *used {
+                run = 0;
+                continue;
+            }
+
+            run += 1;
+            if run == count {
+                return sector + 1 - count;
+            }
+        }
+
+        // a free tail can be extended past the end of the region
+        self.used.len() - run
+    }
+
+    /// Every run of free sectors followed by a used one, in file order  
+    /// The free run at the end of the region isn't one, it's where [`end`](SectorMap::end) points
+    pub(crate) fn free_extents(&self) -> Vec<Range<usize>> {
+        let mut extents = vec![];
+        let mut start = None;
+        for (sector, used) in self.used.iter().enumerate() {
+            match (used, start) {
+                (false, None) => start = Some(sector),
+                (true, Some(free)) => {
+                    extents.push(free..sector);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        extents
+    }
+
+    /// The sector after the last one in use, where appended payloads go
+    pub(crate) fn end(&self) -> usize {
+        self.used
+            .iter()
+            .rposition(|used| *used)
+            .map_or(0, |last| last + 1)
+    }
+
+    /// Returns true if none of the `count` sectors starting at `offset` are in use
+    pub(crate) fn is_free(&self, offset: usize, count: usize) -> bool {
+        (offset..offset + count).all(|sector| !self.used.get(sector).copied().unwrap_or(false))
+    }
+
+    /// The amount of sectors not in use
+    pub(crate) fn free(&self) -> usize {
+        self.used.iter().filter(|u| !**u).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompressionType, RegionReader, RegionWriter};
+
+    #[test]
+    fn helpers() {
+        assert_eq!(sectors_for_payload(0), 1);
+        assert_eq!(sectors_for_payload(4090), 1);
+        assert_eq!(sectors_for_payload(4091), 1);
+        assert_eq!(sectors_for_payload(4092), 2);
+        assert_eq!(sectors_for_payload(8187), 2);
+        assert_eq!(sectors_for_payload(8188), 3);
+        assert_eq!(
+            sectors_for_payload(255 * SECTOR_SIZE - 5),
+            MAX_CHUNK_SECTORS
+        );
+
+        assert_eq!(padding_for(4090), 1);
+        assert_eq!(padding_for(4091), 0);
+        assert_eq!(padding_for(4092), 4095);
+        assert_eq!(padding_for(8187), 0);
+
+        assert_eq!(header_offset_for(0, 0), 0);
+        assert_eq!(header_offset_for(31, 0), 124);
+        assert_eq!(header_offset_for(0, 1), 128);
+        assert_eq!(header_offset_for(31, 31), SECTOR_SIZE - 4);
+
+        let data = [1, 2, 3, 4, 5];
+        assert_eq!(bytes_at(&data, 0), Some([1, 2, 3, 4]));
+        assert_eq!(bytes_at(&data, 1), Some([2, 3, 4, 5]));
+        assert_eq!(bytes_at(&data, 2), None);
+        assert_eq!(bytes_at(&data, usize::MAX), None);
+    }
+
+    #[test]
+    fn helpers_match_writer() {
+        for len in [0, 1, 4090, 4091, 4092, 8186, 8187, 8188, 12_000] {
+            let data = vec![7u8; len];
+
+            let mut writer = RegionWriter::new();
+            writer
+                .push_chunk_with_compression(&data, (3, 5), CompressionType::Uncompressed)
+                .unwrap();
+
+            let mut buf = vec![];
+            writer.write(&mut buf).unwrap();
+
+            let sectors = sectors_for_payload(len) as usize;
+            assert_eq!(buf.len(), HEADER_SIZE + sectors * SECTOR_SIZE);
+            assert_eq!(
+                buf.len(),
+                HEADER_SIZE + PAYLOAD_HEADER_SIZE + len + padding_for(len)
+            );
+
+            let region = RegionReader::new(&buf).unwrap();
+            let location = region.get_location(header_offset_for(3, 5)).unwrap();
+            assert_eq!(location, [0, 0, 2, sectors as u8]);
+            assert_eq!(decode_location(location), (2, sectors));
+            let padding = region.padding_bytes(3, 5).unwrap().unwrap();
+            assert_eq!(padding.len(), padding_for(len));
+            assert_eq!(region.get_chunk(3, 5).unwrap().unwrap().raw_data, &data[..]);
+        }
+    }
+}
@@ -1,20 +1,80 @@
+// region data is untrusted, the library itself reports errors instead of panicking
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::todo,
+        clippy::unimplemented,
+        clippy::unreachable,
+        clippy::indexing_slicing
+    )
+)]
+
+#[cfg(test)]
+mod adversarial;
+mod allocator;
+pub mod backup;
+mod cache;
 mod chunk;
+mod compact;
+mod compat;
 mod compression;
+#[cfg(test)]
+mod conformance;
+pub mod dict;
+mod editor;
 mod error;
+mod extract;
+pub mod layout;
+#[cfg(feature = "fs-lock")]
+mod lock;
+mod mcc;
+mod merge;
+#[cfg(feature = "fastnbt")]
+mod nbt;
+mod pos;
 mod reader;
+#[cfg(feature = "fastnbt")]
+mod relocate;
+mod stream;
+mod world;
 mod writer;
 
-pub use chunk::{PendingChunk, RawChunk};
-pub use compression::CompressionType;
+pub use allocator::{AppendOnly, BestFit, FirstFit, SectorAllocator};
+pub use cache::{CacheLimit, CachedRegion};
+pub use chunk::{ChunkSource, PendingChunk, PendingChunkBuilder, RawChunk};
+pub use compact::compact_region;
+pub use compat::{CompatIssue, CompatReport, MinVersion, TargetVersion};
+pub use compression::{CompressionLevel, CompressionType, Lz4Checksum, Lz4Framing};
+pub use editor::{
+    touch_timestamps, touch_timestamps_strict, DefragStats, FileTransaction, RegionEditor,
+    RegionFileEditor, SetLen, Transaction,
+};
 pub use error::McaError;
-pub use reader::{RegionIter, RegionReader};
-pub use writer::RegionWriter;
-
-const SECTOR_SIZE: usize = 4096;
+pub use extract::{extract_area, retain};
+#[cfg(feature = "fs-lock")]
+pub use lock::{LockedFile, RegionLock};
+pub use mcc::MccFile;
+pub use merge::{
+    merge3, merge_regions, merge_regions_with_warnings, ChunkChange, ConflictPolicy, Merge3Result,
+    MergeInput, MergePolicy, MergeWarning,
+};
+pub use pos::RegionPos;
+pub use reader::{Occupancy, RegionCenterIter, RegionIter, RegionReader};
+#[cfg(feature = "fastnbt")]
+pub use relocate::relocate_region;
+pub use stream::RegionStreamWriter;
+pub use world::WorldWriter;
+pub use writer::{
+    ChunkWriteRecord, DuplicatePolicy, LayoutStrategy, RegionWriter, WriteSummary, WriterIssue,
+};
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::layout::{HEADER_SIZE, SECTOR_SIZE};
 
     const REGION: &[u8] = include_bytes!("../benches/r.0.0.mca");
 
@@ -64,7 +124,7 @@ mod tests {
     fn get_location() {
         let region = RegionReader::new(REGION).unwrap();
         let location = region
-            .get_location(RegionReader::chunk_offset(0, 0))
+            .get_location(RegionReader::chunk_offset(0, 0).unwrap())
             .unwrap();
 
         assert_eq!(location, [0, 3, 22, 2]);
@@ -74,11 +134,11 @@ mod tests {
     fn get_timestamp() {
         let region = RegionReader::new(REGION).unwrap();
         #[cfg(feature = "unsafe")]
-        let timestamp = region.get_timestamp(RegionReader::chunk_offset(0, 0));
+        let timestamp = region.get_timestamp(RegionReader::chunk_offset(0, 0).unwrap());
 
         #[cfg(not(feature = "unsafe"))]
         let timestamp = region
-            .get_timestamp(RegionReader::chunk_offset(0, 0))
+            .get_timestamp(RegionReader::chunk_offset(0, 0).unwrap())
             .unwrap();
 
         assert_eq!(timestamp, [102, 128, 130, 115]);
@@ -93,10 +153,380 @@ mod tests {
 
         let chunk = region.get_chunk(0, 0);
 
-        if let Err(McaError::InvalidChunkPayload(_)) = chunk {
-            assert!(true)
-        } else {
-            assert!(false)
+        assert!(matches!(
+            chunk,
+            Err(McaError::TruncatedChunk {
+                x: 0,
+                z: 0,
+                missing: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn iter_from_center() {
+        let region = RegionReader::new(REGION).unwrap();
+
+        let present: Vec<(usize, usize)> = region
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| matches!(chunk, Ok(Some(_))))
+            .map(|(idx, _)| RegionIter::get_chunk_coordinate(idx))
+            .collect();
+
+        let (cx, cz) = present[present.len() / 2];
+        let visited: Vec<(usize, usize)> = region
+            .iter_from(cx, cz)
+            .map(|chunk| chunk.unwrap().0)
+            .collect();
+
+        assert_eq!(visited[0], (cx, cz));
+        assert_eq!(visited.len(), present.len());
+
+        let mut sorted = visited.clone();
+        sorted.sort_by_key(|&(x, z)| (z, x));
+        sorted.dedup();
+        assert_eq!(sorted, present);
+
+        let rings: Vec<usize> = visited
+            .iter()
+            .map(|&(x, z)| x.abs_diff(cx).max(z.abs_diff(cz)))
+            .collect();
+        assert!(rings.windows(2).all(|w| w[0] <= w[1]));
+
+        let again: Vec<(usize, usize)> = region
+            .iter_from(cx, cz)
+            .map(|chunk| chunk.unwrap().0)
+            .collect();
+        assert_eq!(visited, again);
+    }
+
+    #[test]
+    fn occupancy() {
+        let region = RegionReader::new(REGION).unwrap();
+        let occupancy = region.occupancy();
+
+        let generated = region
+            .iter()
+            .filter(|chunk| matches!(chunk, Ok(Some(_))))
+            .count();
+        assert_eq!(occupancy.count(), generated);
+
+        let grid = occupancy.to_grid();
+        for (idx, chunk) in region.iter().enumerate() {
+            let (x, z) = RegionIter::get_chunk_coordinate(idx);
+            assert_eq!(grid[z][x], chunk.unwrap().is_some());
         }
+
+        let map = occupancy.to_string();
+        assert_eq!(map.lines().count(), 32);
+        assert_eq!(map.matches('#').count(), generated);
+    }
+
+    #[test]
+    fn vanilla_compatibility() {
+        let region = RegionReader::new(REGION).unwrap();
+        let report = region.vanilla_compatibility();
+
+        assert_eq!(report.required, MinVersion::Anvil);
+        assert!(report.issues.is_empty());
+
+        let mut writer = RegionWriter::new();
+        writer
+            .push_chunk_with_compression(&[10, 0, 0, 0], (0, 0), CompressionType::Zlib)
+            .unwrap();
+        writer
+            .push_chunk_with_compression(&[10, 0, 0, 0], (1, 0), CompressionType::Uncompressed)
+            .unwrap();
+        writer
+            .push_chunk_with_compression(&[10, 0, 0, 0], (2, 0), CompressionType::LZ4)
+            .unwrap();
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+
+        let report = RegionReader::new(&buf).unwrap().vanilla_compatibility();
+        assert_eq!(report.required, MinVersion::V1_20_5);
+        assert!(report.is_vanilla_loadable());
+        assert_eq!(
+            report.issues,
+            vec![
+                CompatIssue {
+                    coordinate: (1, 0),
+                    compression_byte: 3,
+                    required: MinVersion::V1_15,
+                },
+                CompatIssue {
+                    coordinate: (2, 0),
+                    compression_byte: 4,
+                    required: MinVersion::V1_20_5,
+                },
+            ]
+        );
+
+        // the first chunk payload starts right after the header, patch it to a custom id
+        buf[SECTOR_SIZE * 2 + 4] = 127;
+        let report = RegionReader::new(&buf).unwrap().vanilla_compatibility();
+        assert_eq!(report.required, MinVersion::NotVanilla);
+        assert!(!report.is_vanilla_loadable());
+        assert_eq!(report.issues[0].coordinate, (0, 0));
+        assert_eq!(report.issues.len(), 3);
+    }
+
+    #[test]
+    fn resume_iteration() {
+        let region = RegionReader::new(REGION).unwrap();
+
+        let mut iter = region.iter();
+        for _ in iter.by_ref().take(300) {}
+        assert_eq!(iter.position(), 300);
+
+        let saved = iter.clone();
+        let resumed = region.iter_from_index(saved.position());
+        let skipped = region.iter().skip(300);
+
+        let resumed: Vec<_> = resumed.map(|c| c.unwrap()).collect();
+        let skipped: Vec<_> = skipped.map(|c| c.unwrap()).collect();
+        let continued: Vec<_> = iter.map(|c| c.unwrap()).collect();
+        assert_eq!(resumed, skipped);
+        assert_eq!(resumed, continued);
+
+        assert_eq!(region.iter_from_index(RegionIter::MAX).count(), 0);
+        assert_eq!(region.iter_from_index(5000).count(), 0);
+    }
+
+    #[test]
+    fn truncated_region() {
+        let region = RegionReader::new_strict(REGION).unwrap();
+        assert_eq!(region.expected_len(), REGION.len());
+
+        // the chunk whose payload ends last, read straight from the header
+        let (last, sector, count, payload_end) = (0..RegionIter::MAX)
+            .filter_map(|idx| {
+                let (x, z) = RegionIter::get_chunk_coordinate(idx);
+                let loc = region.get_location(RegionReader::chunk_offset(x, z).unwrap())?;
+                let sector = u32::from_be_bytes([0, loc[0], loc[1], loc[2]]) as usize;
+                let start = sector * SECTOR_SIZE;
+                let len = u32::from_be_bytes(REGION[start..start + 4].try_into().unwrap());
+                Some(((x, z), sector, loc[3] as usize, start + 4 + len as usize))
+            })
+            .max_by_key(|(_, sector, _, _)| *sector)
+            .unwrap();
+        assert_eq!((sector + count) * SECTOR_SIZE, REGION.len());
+
+        let short = &REGION[..payload_end - 10];
+        assert!(matches!(
+            RegionReader::new_strict(short),
+            Err(McaError::TruncatedRegion { expected, actual })
+                if expected == REGION.len() && actual == payload_end - 10
+        ));
+        let region = RegionReader::new(short).unwrap();
+        assert!(matches!(
+            region.get_chunk(last.0, last.1),
+            Err(McaError::TruncatedChunk { x, z, missing: 10 }) if (x, z) == last
+        ));
+        assert!(matches!(
+            region.padding_bytes(last.0, last.1),
+            Err(McaError::TruncatedChunk { missing, .. })
+                if missing == REGION.len() - short.len()
+        ));
+
+        // the payload is complete, only its padding is cut off
+        let region = RegionReader::new(&REGION[..payload_end]).unwrap();
+        assert!(region.get_chunk(last.0, last.1).unwrap().is_some());
+        assert!(RegionReader::new_strict(&REGION[..payload_end]).is_err());
+
+        // cut inside the length field
+        let region = RegionReader::new(&REGION[..sector * SECTOR_SIZE + 1]).unwrap();
+        assert!(matches!(
+            region.get_chunk(last.0, last.1),
+            Err(McaError::TruncatedChunk { missing: 3, .. })
+        ));
+        assert_eq!(region.expected_len(), REGION.len());
+    }
+
+    #[test]
+    fn tiny_payload_lengths() {
+        // a single chunk at sector 2, its payload ending `tail` bytes before the end of the data
+        let region_with = |length: u32, tail: usize| {
+            let mut bytes = vec![0; HEADER_SIZE];
+            bytes[..4].copy_from_slice(&[0, 0, 2, 1]);
+            bytes.extend_from_slice(&length.to_be_bytes());
+            bytes.extend_from_slice(&[CompressionType::LZ4.to_u8(), 10, 0][..length as usize]);
+            bytes.extend(std::iter::repeat_n(0, tail));
+            bytes
+        };
+
+        for tail in [0, 1, SECTOR_SIZE] {
+            let empty = region_with(0, tail);
+            let region = RegionReader::new(&empty).unwrap();
+            assert_eq!(region.get_chunk(0, 0).unwrap(), None);
+
+            let only_compression = region_with(1, tail);
+            let region = RegionReader::new(&only_compression).unwrap();
+            let chunk = region.get_chunk(0, 0).unwrap().unwrap();
+            assert_eq!(chunk.get_compression_type(), CompressionType::LZ4);
+            assert!(chunk.raw_data.is_empty());
+
+            let one_byte = region_with(2, tail);
+            let region = RegionReader::new(&one_byte).unwrap();
+            assert_eq!(region.get_chunk(0, 0).unwrap().unwrap().raw_data, [10]);
+        }
+
+        // the 2 byte payload cut short, then the length field itself
+        for cut in 1..=6 {
+            let full = region_with(2, 0);
+            let region = RegionReader::new(&full[..full.len() - cut]).unwrap();
+            let expected = if cut <= 2 { cut } else { cut - 2 };
+            assert!(matches!(
+                region.get_chunk(0, 0),
+                Err(McaError::TruncatedChunk { missing, .. }) if missing == expected
+            ));
+        }
+        let strict = region_with(0, SECTOR_SIZE - 4);
+        assert!(matches!(
+            RegionReader::new_strict(&strict).unwrap().get_chunk(0, 0),
+            Err(McaError::EmptyPayload { x: 0, z: 0 })
+        ));
+    }
+
+    #[test]
+    fn payload_ends_at_eof() {
+        // the last chunk's payload fills its 2 sectors, ending exactly where the file does
+        let data = (0..2 * SECTOR_SIZE - 5)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let mut writer = RegionWriter::new();
+        writer
+            .push_chunk_with_compression(&[1; 10], (0, 0), CompressionType::Uncompressed)
+            .unwrap();
+        writer
+            .push_chunk_with_compression(&data, (7, 3), CompressionType::Uncompressed)
+            .unwrap();
+        let mut exact = vec![];
+        writer.write(&mut exact).unwrap();
+
+        let region = RegionReader::new_strict(&exact).unwrap();
+        let chunk = region.get_chunk(7, 3).unwrap().unwrap();
+        assert_eq!(chunk.raw_data, data);
+        assert_eq!(
+            chunk.raw_data.as_ptr_range().end,
+            exact.as_ptr_range().end,
+            "the payload ends at EOF"
+        );
+        assert_eq!(region.padding_bytes(7, 3).unwrap(), Some(&[][..]));
+        assert_eq!(compat::conformance_issue(&exact), None);
+
+        let length_at = exact.len() - 2 * SECTOR_SIZE;
+        let with_length = |delta: i32| {
+            let mut region = exact.clone();
+            let length = u32::from_be_bytes(region[length_at..length_at + 4].try_into().unwrap());
+            region[length_at..length_at + 4]
+                .copy_from_slice(&length.wrapping_add_signed(delta).to_be_bytes());
+            region
+        };
+
+        // one byte short of EOF, the last data byte turns into padding
+        let short = with_length(-1);
+        let region = RegionReader::new_strict(&short).unwrap();
+        assert_eq!(
+            region.get_chunk(7, 3).unwrap().unwrap().raw_data,
+            &data[..data.len() - 1]
+        );
+        assert_eq!(
+            region.padding_bytes(7, 3).unwrap(),
+            Some(&data[data.len() - 1..])
+        );
+
+        // one byte past EOF
+        let long = with_length(1);
+        let region = RegionReader::new_strict(&long).unwrap();
+        assert!(matches!(
+            region.get_chunk(7, 3),
+            Err(McaError::TruncatedChunk {
+                x: 7,
+                z: 3,
+                missing: 1
+            })
+        ));
+        assert!(matches!(
+            region.padding_bytes(7, 3),
+            Err(McaError::InvalidChunkPayload(_))
+        ));
+
+        // the file itself one byte short & long of the payload
+        let cut = &exact[..exact.len() - 1];
+        assert!(matches!(
+            RegionReader::new(cut).unwrap().get_chunk(7, 3),
+            Err(McaError::TruncatedChunk {
+                x: 7,
+                z: 3,
+                missing: 1
+            })
+        ));
+        let mut extended = exact.clone();
+        extended.push(0xAA);
+        let region = RegionReader::new(&extended).unwrap();
+        assert_eq!(region.get_chunk(7, 3).unwrap().unwrap().raw_data, data);
+        assert_eq!(region.padding_bytes(7, 3).unwrap(), Some(&[][..]));
+    }
+
+    #[test]
+    fn padding_and_gaps() {
+        let region = RegionReader::new(REGION).unwrap();
+
+        // vanilla reuses sectors without clearing them, so the bundled region carries residue
+        let mut residue = 0;
+        for idx in 0..RegionIter::MAX {
+            let (x, z) = RegionIter::get_chunk_coordinate(idx);
+            let location = match region.get_location(RegionReader::chunk_offset(x, z).unwrap()) {
+                Some(loc) => loc,
+                None => continue,
+            };
+
+            let chunk = region.get_chunk(x, z).unwrap().unwrap();
+            let padding = region.padding_bytes(x, z).unwrap().unwrap();
+            assert_eq!(
+                padding.len(),
+                location[3] as usize * SECTOR_SIZE - 5 - chunk.raw_data.len()
+            );
+
+            if padding.iter().any(|b| *b != 0) {
+                residue += 1;
+            }
+        }
+        assert!(residue > 0);
+
+        let mut writer = RegionWriter::new();
+        for x in 0..3 {
+            writer
+                .push_chunk_with_compression(&[10, 0, 0, 0], (x, 0), CompressionType::Uncompressed)
+                .unwrap();
+        }
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+
+        let region = RegionReader::new(&buf).unwrap();
+        assert!(region.gap_extents().is_empty());
+        let padding = region.padding_bytes(0, 0).unwrap().unwrap();
+        assert_eq!(padding.len(), SECTOR_SIZE - 5 - 4);
+
+        // leave residue in (0, 0)'s padding, forget (1, 0) and append a stray sector
+        let start = SECTOR_SIZE * 2;
+        buf[start + 100] = 0xAB;
+        buf[RegionReader::chunk_offset(1, 0).unwrap()..][..4].copy_from_slice(&[0, 0, 0, 0]);
+        buf.extend_from_slice(&[1; 10]);
+
+        let region = RegionReader::new(&buf).unwrap();
+        let padding = region.padding_bytes(0, 0).unwrap().unwrap();
+        assert_eq!(padding[100 - 9], 0xAB);
+        assert_eq!(region.padding_bytes(1, 0).unwrap(), None);
+        assert_eq!(
+            region.gap_extents(),
+            vec![
+                SECTOR_SIZE * 3..SECTOR_SIZE * 4,
+                SECTOR_SIZE * 5..SECTOR_SIZE * 5 + 10
+            ]
+        );
     }
 }
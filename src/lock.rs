@@ -0,0 +1,189 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{McaError, SetLen};
+
+/// An advisory lock on a region file, held until dropped.
+///
+/// The lock is taken on a `.{name}.lock` file next to the region rather than the region itself,
+/// since [`RegionWriter::write_to_path`](crate::RegionWriter::write_to_path) renames a new file over it.
+/// The lock file is left behind afterwards, removing it could race with a writer waiting on it.
+///
+/// Only writers going through these locks are kept out, any other program can still write the file.
+///
+/// ## Example
+/// ```ignore
+/// let _lock = RegionLock::try_lock(Path::new("world/region/r.0.0.mca"))?;
+/// // nobody else using a `RegionLock` writes r.0.0.mca until `_lock` is dropped
+/// ```
+#[derive(Debug)]
+pub struct RegionLock {
+    file: File,
+}
+
+impl RegionLock {
+    /// Locks the region file at `path`, blocking until no other writer holds its lock
+    pub fn lock(path: &Path) -> Result<RegionLock, McaError> {
+        let file = Self::open(path)?;
+        file.lock()?;
+
+        Ok(RegionLock { file })
+    }
+
+    /// Locks the region file at `path`, failing with [`McaError::Locked`] if another writer holds its lock
+    pub fn try_lock(path: &Path) -> Result<RegionLock, McaError> {
+        let file = Self::open(path)?;
+        match file.try_lock() {
+            Ok(()) => Ok(RegionLock { file }),
+            Err(std::fs::TryLockError::WouldBlock) => Err(McaError::Locked(path.to_path_buf())),
+            Err(std::fs::TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    /// The lock file guarding the region file at `path`
+    pub fn lock_path(path: &Path) -> PathBuf {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        path.with_file_name(format!(".{name}.lock"))
+    }
+
+    fn open(path: &Path) -> Result<File, McaError> {
+        Ok(OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(Self::lock_path(path))?)
+    }
+}
+
+impl Drop for RegionLock {
+    fn drop(&mut self) {
+        // closing the file releases it too, this only makes it explicit
+        let _ = self.file.unlock();
+    }
+}
+
+/// A region file opened for editing while holding its [`RegionLock`]
+/// See [`RegionFileEditor::open_locked`](crate::RegionFileEditor::open_locked)
+#[derive(Debug)]
+pub struct LockedFile {
+    file: File,
+    _lock: RegionLock,
+}
+
+impl LockedFile {
+    pub(crate) fn open(path: &Path, lock: RegionLock) -> Result<LockedFile, McaError> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+
+        Ok(LockedFile { file, _lock: lock })
+    }
+}
+
+impl Read for LockedFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Write for LockedFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for LockedFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl SetLen for LockedFile {
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        self.file.set_len(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompressionType, RegionFileEditor, RegionWriter};
+    use std::sync::mpsc;
+
+    #[test]
+    fn contending_writers() {
+        let dir = std::env::temp_dir().join(format!("mca-lock-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("r.0.0.mca");
+
+        let (locked, release) = (mpsc::channel(), mpsc::channel::<()>());
+        let holder = {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                let _lock = RegionLock::lock(&path).unwrap();
+                locked.0.send(()).unwrap();
+                release.1.recv().unwrap();
+            })
+        };
+        locked.1.recv().unwrap();
+
+        let mut writer = RegionWriter::new();
+        writer.push_chunk(&[10, 0, 0, 0], (0, 0)).unwrap();
+        assert!(matches!(
+            writer.try_write_to_path(&path),
+            Err(McaError::Locked(p)) if p == path
+        ));
+        assert!(!path.exists());
+
+        release.0.send(()).unwrap();
+        holder.join().unwrap();
+        writer.try_write_to_path(&path).unwrap();
+
+        // a write failing partway still releases the lock
+        let mut too_large = RegionWriter::new();
+        too_large
+            .push_chunk_with_compression(
+                &vec![1; 256 * 4096],
+                (0, 0),
+                CompressionType::Uncompressed,
+            )
+            .unwrap();
+        assert!(matches!(
+            too_large.write_to_path(&path),
+            Err(McaError::ChunkTooLarge { .. })
+        ));
+        drop(RegionLock::try_lock(&path).unwrap());
+
+        writer.push_chunk(&[10, 1, 0, 0], (1, 0)).unwrap();
+        writer.write_to_path(&path).unwrap();
+
+        let mut editor = RegionFileEditor::try_open_locked(&path).unwrap();
+        assert!(matches!(
+            RegionFileEditor::try_open_locked(&path),
+            Err(McaError::Locked(_))
+        ));
+        assert!(matches!(
+            writer.try_write_to_path(&path),
+            Err(McaError::Locked(_))
+        ));
+        editor.remove_chunk(1, 0).unwrap();
+        drop(editor);
+
+        let data = std::fs::read(&path).unwrap();
+        let region = crate::RegionReader::new(&data).unwrap();
+        assert!(region.get_chunk(0, 0).unwrap().is_some());
+        assert!(region.get_chunk(1, 0).unwrap().is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
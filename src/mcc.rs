@@ -0,0 +1,274 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    chunk::ExternalChunk, writer::write_atomic, CompressionLevel, CompressionType, McaError,
+    PendingChunk, RawChunk, RegionPos,
+};
+
+/// A chunk too large for its region, stored in a `c.{x}.{z}.mcc` file next to the region file.
+///
+/// The file only holds the compressed chunk data, its compression type is the one
+/// in the region's 1 sector stub for the chunk, see [`RawChunk::is_external`].
+///
+/// ## Example
+/// ```ignore
+/// let region = RegionReader::new(&data)?;
+///
+/// if let Some(mcc) = region.read_external(4, 9, RegionPos::new(0, 0), Path::new("world/region"))? {
+///     let nbt = mcc.decompress()?;
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MccFile {
+    /// The world chunk position
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    pub compression: CompressionType,
+    /// The compressed chunk data, the whole file
+    pub data: Vec<u8>,
+}
+
+impl MccFile {
+    /// Creates an external chunk file from its world chunk position & compressed data
+    pub fn new(chunk_x: i32, chunk_z: i32, compression: CompressionType, data: Vec<u8>) -> MccFile {
+        MccFile {
+            chunk_x,
+            chunk_z,
+            compression,
+            data,
+        }
+    }
+
+    /// Reads the external chunk file at `path`, its position is taken from the file name  
+    /// `compression` is the one of the chunk's stub in the region.
+    /// Fails with [`McaError::InvalidMccName`] if the file isn't named `c.{x}.{z}.mcc`.
+    pub fn read(path: &Path, compression: CompressionType) -> Result<MccFile, McaError> {
+        let (chunk_x, chunk_z) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(Self::parse_file_name)
+            .ok_or_else(|| McaError::InvalidMccName(path.to_path_buf()))?;
+
+        Ok(MccFile::new(chunk_x, chunk_z, compression, fs::read(path)?))
+    }
+
+    /// Same as [`read`](MccFile::read) with the file's name & contents already in memory
+    pub fn from_bytes(
+        file_name: &str,
+        compression: CompressionType,
+        data: Vec<u8>,
+    ) -> Result<MccFile, McaError> {
+        let (chunk_x, chunk_z) = Self::parse_file_name(file_name)
+            .ok_or_else(|| McaError::InvalidMccName(PathBuf::from(file_name)))?;
+
+        Ok(MccFile::new(chunk_x, chunk_z, compression, data))
+    }
+
+    /// Writes the data to `path`, atomically like [`RegionWriter::write_to_path`](crate::RegionWriter::write_to_path)
+    pub fn write(&self, path: &Path) -> Result<(), McaError> {
+        write_atomic(path, |w| Ok(w.write_all(&self.data)?))
+    }
+
+    /// Writes the data to its `c.x.z.mcc` file inside `dir`, returns the path written to
+    pub fn write_to_dir(&self, dir: &Path) -> Result<PathBuf, McaError> {
+        let path = dir.join(self.file_name());
+        self.write(&path)?;
+
+        Ok(path)
+    }
+
+    /// The vanilla file name of this chunk, `c.{x}.{z}.mcc`
+    pub fn file_name(&self) -> String {
+        Self::name_for(self.chunk_x, self.chunk_z)
+    }
+
+    pub(crate) fn name_for(chunk_x: i32, chunk_z: i32) -> String {
+        format!("c.{chunk_x}.{chunk_z}.mcc")
+    }
+
+    /// The world chunk position a `c.{x}.{z}.mcc` file name is for, `None` for any other name
+    pub fn parse_file_name(name: &str) -> Option<(i32, i32)> {
+        let (x, z) = name
+            .strip_prefix("c.")?
+            .strip_suffix(".mcc")?
+            .split_once('.')?;
+
+        Some((x.parse().ok()?, z.parse().ok()?))
+    }
+
+    /// The world chunk positions of every `c.{x}.{z}.mcc` file in `dir`, sorted  
+    /// Doesn't check whether a region still points at them, stray files left behind by a
+    /// chunk shrinking back into its region are listed too.
+    pub fn find_in(dir: &Path) -> Result<Vec<(i32, i32)>, McaError> {
+        let mut found = vec![];
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if let Some(pos) = entry.file_name().to_str().and_then(Self::parse_file_name) {
+                if entry.file_type()?.is_file() {
+                    found.push(pos);
+                }
+            }
+        }
+        found.sort_unstable();
+
+        Ok(found)
+    }
+
+    /// The region owning the chunk & the chunk's local coordinate inside it
+    pub fn region(&self) -> (RegionPos, (u8, u8)) {
+        RegionPos::from_chunk(self.chunk_x, self.chunk_z)
+    }
+
+    /// Decompresses the chunk data depending on its compression type
+    pub fn decompress(&self) -> Result<Vec<u8>, McaError> {
+        self.compression.decompress(&self.data)
+    }
+
+    /// The chunk data as a raw chunk, as if it was stored inside the region
+    pub fn raw_chunk(&self) -> RawChunk<'_> {
+        RawChunk::new(&self.data, self.compression.clone())
+    }
+
+    /// A pending chunk at the local coordinate inside its region, the data is kept compressed
+    pub fn to_pending_chunk(&self, timestamp: u32) -> PendingChunk {
+        let (_, coordinate) = self.region();
+        PendingChunk::from_compressed(
+            self.data.clone(),
+            self.compression.clone(),
+            timestamp,
+            coordinate,
+        )
+    }
+
+    /// The external chunk file for a pending chunk of `region`, compressing it first if deferred
+    pub fn from_pending_chunk(
+        chunk: &PendingChunk,
+        region: RegionPos,
+    ) -> Result<MccFile, McaError> {
+        let data = match chunk.deferred_data() {
+            Some(raw) => chunk
+                .compression
+                .compress_with_level(raw, CompressionLevel::default())?,
+            None => chunk.payload().to_vec(),
+        };
+        let (chunk_x, chunk_z) = region.chunk_pos(chunk.coordinate);
+
+        Ok(MccFile::new(
+            chunk_x,
+            chunk_z,
+            chunk.compression.clone(),
+            data,
+        ))
+    }
+}
+
+impl ExternalChunk {
+    /// The external chunk file for this chunk of `region`
+    pub(crate) fn to_mcc(&self, region: RegionPos) -> MccFile {
+        let (chunk_x, chunk_z) = region.chunk_pos(self.coordinate);
+        MccFile::new(
+            chunk_x,
+            chunk_z,
+            self.compression.clone(),
+            self.data.clone(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        layout::{MAX_CHUNK_SECTORS, SECTOR_SIZE},
+        RegionReader, RegionWriter,
+    };
+
+    #[test]
+    fn file_names() {
+        assert_eq!(MccFile::parse_file_name("c.4.9.mcc"), Some((4, 9)));
+        assert_eq!(MccFile::parse_file_name("c.-33.-1.mcc"), Some((-33, -1)));
+        assert_eq!(
+            MccFile::parse_file_name("c.-2147483648.7.mcc"),
+            Some((i32::MIN, 7))
+        );
+        for name in [
+            "c.1.mcc",
+            "c.1.2.3.mcc",
+            "c.a.2.mcc",
+            "r.1.2.mca",
+            "c.1.2.mca",
+            "c..2.mcc",
+        ] {
+            assert_eq!(MccFile::parse_file_name(name), None, "{name}");
+        }
+
+        let mcc = MccFile::from_bytes("c.-33.-1.mcc", CompressionType::Zlib, vec![1]).unwrap();
+        assert_eq!((mcc.chunk_x, mcc.chunk_z), (-33, -1));
+        assert_eq!(mcc.file_name(), "c.-33.-1.mcc");
+        assert_eq!(mcc.region(), (RegionPos::new(-2, -1), (31, 31)));
+        assert!(matches!(
+            MccFile::from_bytes("c.1.mca", CompressionType::Zlib, vec![]),
+            Err(McaError::InvalidMccName(_))
+        ));
+    }
+
+    #[test]
+    fn region_round_trip() {
+        let dir = std::env::temp_dir().join(format!("mca-mcc-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let max = MAX_CHUNK_SECTORS as usize * SECTOR_SIZE - 5;
+        let big = (0..max + 100).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let pos = RegionPos::new(-1, 2);
+
+        let mut writer = RegionWriter::for_region(pos);
+        writer
+            .push_chunk_with_compression(&[1; 10], (0, 0), CompressionType::Uncompressed)
+            .unwrap();
+        writer
+            .push_chunk_with_compression(&big, (4, 9), CompressionType::Uncompressed)
+            .unwrap();
+        let mut buf = vec![];
+        let external = writer.write_with_external(&mut buf).unwrap();
+        assert_eq!(external.len(), 1);
+        assert_eq!((external[0].chunk_x, external[0].chunk_z), (-28, 73));
+        assert_eq!(external[0].region(), (pos, (4, 9)));
+        assert!(matches!(
+            RegionWriter::new().write_with_external(&mut vec![]),
+            Err(McaError::UnknownRegion)
+        ));
+
+        let path = external[0].write_to_dir(&dir).unwrap();
+        assert_eq!(path, dir.join("c.-28.73.mcc"));
+        // not a chunk file, or not one at all
+        std::fs::write(dir.join("c.1.2.mca"), [0]).unwrap();
+        std::fs::create_dir(dir.join("c.5.5.mcc")).unwrap();
+        assert_eq!(MccFile::find_in(&dir).unwrap(), [(-28, 73)]);
+
+        let region = RegionReader::new(&buf).unwrap();
+        assert_eq!(region.read_external(0, 0, pos, &dir).unwrap(), None);
+        assert_eq!(region.read_external(1, 0, pos, &dir).unwrap(), None);
+        let mcc = region.read_external(4, 9, pos, &dir).unwrap().unwrap();
+        assert_eq!(mcc, external[0]);
+        assert_eq!(mcc.decompress().unwrap(), big);
+        assert_eq!(mcc.raw_chunk().decompress().unwrap(), big);
+        // the file name depends on the region's position
+        assert!(region
+            .read_external(4, 9, RegionPos::new(0, 0), &dir)
+            .is_err());
+
+        let pending = mcc.to_pending_chunk(7);
+        assert_eq!(pending.coordinate, (4, 9));
+        assert_eq!(MccFile::from_pending_chunk(&pending, pos).unwrap(), mcc);
+        let deferred =
+            PendingChunk::deferred(big.clone(), CompressionType::Uncompressed, 7, (4, 9)).unwrap();
+        assert_eq!(MccFile::from_pending_chunk(&deferred, pos).unwrap(), mcc);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,467 @@
+use crate::{layout, McaError, PendingChunk, RawChunk, RegionIter, RegionReader, RegionWriter};
+
+/// Which copy of a chunk [`merge_regions`] keeps when both regions have one
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MergePolicy {
+    /// The copy with the newer timestamp, the first region wins ties
+    #[default]
+    NewestWins,
+    /// Always the copy from the first region
+    PreferFirst,
+}
+
+/// One of the two regions given to [`merge_regions`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MergeInput {
+    First,
+    Second,
+}
+
+/// A chunk that couldn't be read from one of the merged regions, the other region's copy was used if it had one
+#[derive(Debug)]
+pub struct MergeWarning {
+    pub coordinate: (usize, usize),
+    pub input: MergeInput,
+    pub error: McaError,
+}
+
+/// Combines two regions chunk by chunk, keeping the copy `policy` picks for slots present in both.
+/// Payloads are copied verbatim along with their timestamps, slots present in neither stay empty.
+///
+/// Chunks that can't be read are treated as absent, see [`merge_regions_with_warnings`] to find out which.
+/// Fails on externally stored chunks, their data lives in a separate `.mcc` file.
+///
+/// ## Example
+/// ```ignore
+/// let older = RegionReader::new(&backup_a)?;
+/// let newer = RegionReader::new(&backup_b)?;
+///
+/// let merged = mca::merge_regions(&older, &newer, MergePolicy::NewestWins)?;
+/// ```
+pub fn merge_regions(
+    a: &RegionReader,
+    b: &RegionReader,
+    policy: MergePolicy,
+) -> Result<Vec<u8>, McaError> {
+    merge_regions_with_warnings(a, b, policy).map(|(region, _)| region)
+}
+
+/// Same as [`merge_regions`] but also returns every chunk that couldn't be read, in header order
+pub fn merge_regions_with_warnings(
+    a: &RegionReader,
+    b: &RegionReader,
+    policy: MergePolicy,
+) -> Result<(Vec<u8>, Vec<MergeWarning>), McaError> {
+    let mut writer = RegionWriter::new();
+    let mut warnings = vec![];
+
+    for z in 0..32 {
+        for x in 0..32 {
+            let first = readable(a, (x, z), MergeInput::First, &mut warnings)?;
+            let second = readable(b, (x, z), MergeInput::Second, &mut warnings)?;
+
+            let winner = match (first, second) {
+                (Some(first), Some(second)) => match policy {
+                    MergePolicy::NewestWins if second.1 > first.1 => second,
+                    _ => first,
+                },
+                (Some(only), None) | (None, Some(only)) => only,
+                (None, None) => continue,
+            };
+
+            let (chunk, timestamp) = winner;
+            writer.push_pending_chunk(PendingChunk::from_raw_chunk(
+                &chunk,
+                (x as u8, z as u8),
+                timestamp,
+            ))?;
+        }
+    }
+
+    Ok((writer.into_bytes()?, warnings))
+}
+
+/// The chunk & timestamp of a slot, unreadable chunks are recorded as a warning instead  
+/// External chunks fail the merge, their `.mcc` file would be left behind by the merged region.
+fn readable<'a>(
+    region: &'a RegionReader,
+    (x, z): (usize, usize),
+    input: MergeInput,
+    warnings: &mut Vec<MergeWarning>,
+) -> Result<Option<(RawChunk<'a>, u32)>, McaError> {
+    let error = match region.get_chunk(x, z) {
+        Ok(Some(chunk)) if chunk.is_external() => {
+            return Err(McaError::InvalidChunkPayload(format!(
+                "chunk {x} {z} is stored externally"
+            )))
+        }
+        Ok(Some(chunk)) => return Ok(Some((chunk, region.get_chunk_timestamp(x, z)))),
+        Ok(None) => return Ok(None),
+        Err(error) => error,
+    };
+
+    warnings.push(MergeWarning {
+        coordinate: (x, z),
+        input,
+        error,
+    });
+    Ok(None)
+}
+
+/// What [`merge3`] does with a chunk both sides changed differently since the base
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ConflictPolicy {
+    /// The base's copy is kept and the slot is listed in [`Merge3Result::conflicts`]
+    #[default]
+    Report,
+    /// The side with the newer timestamp, ours wins ties
+    NewestWins,
+    /// Always our side
+    PreferOurs,
+}
+
+/// How a chunk slot changed on each side of a [`merge3`] compared to the base  
+/// Removing a chunk or adding one to an empty slot counts as a change too
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChunkChange {
+    Unchanged,
+    OursOnly,
+    TheirsOnly,
+    /// Both sides changed it to the same content
+    BothSame,
+    /// Both sides changed it to different content
+    Conflict,
+}
+
+/// The merged region of a [`merge3`] & how every slot was classified
+#[derive(Debug, Clone)]
+pub struct Merge3Result {
+    pub region: Vec<u8>,
+    /// The classification of every slot, in header order
+    pub changes: Vec<ChunkChange>,
+    /// Conflicting slots left at the base's copy, only with [`ConflictPolicy::Report`]
+    pub conflicts: Vec<(usize, usize)>,
+}
+
+impl Merge3Result {
+    /// How the chunk at `x` `z` changed  
+    /// Errors with [`McaError::InvalidCoordinate`] outside the region
+    pub fn change(&self, x: usize, z: usize) -> Result<ChunkChange, McaError> {
+        layout::check_coordinate(x, z)?;
+        self.changes
+            .get(z * 32 + x)
+            .copied()
+            .ok_or(McaError::InvalidCoordinate { x, z })
+    }
+}
+
+/// Merges `ours` & `theirs`, two regions that diverged from `base`, chunk by chunk.
+/// A slot only one side changed takes that side's copy,
+/// one both changed differently is resolved by `policy` or reported as a conflict.
+///
+/// Chunks are compared by their decompressed data, so recompressing a chunk isn't a change.
+/// Payloads are copied verbatim along with their timestamps.
+/// Fails on chunks that can't be read or decompressed, and on externally stored chunks.
+///
+/// ## Example
+/// ```ignore
+/// let merged = mca::merge3(&snapshot, &production, &staging, ConflictPolicy::Report)?;
+///
+/// let mut editor = RegionEditor::new(merged.region)?;
+/// for (x, z) in merged.conflicts {
+///     let nbt = resolve(x, z)?;
+///     editor.set_chunk(x, z, &nbt, CompressionType::Zlib)?;
+/// }
+/// ```
+pub fn merge3(
+    base: &RegionReader,
+    ours: &RegionReader,
+    theirs: &RegionReader,
+    policy: ConflictPolicy,
+) -> Result<Merge3Result, McaError> {
+    let mut writer = RegionWriter::new();
+    let mut changes = Vec::with_capacity(RegionIter::MAX);
+    let mut conflicts = vec![];
+
+    for idx in 0..RegionIter::MAX {
+        let (x, z) = RegionIter::get_chunk_coordinate(idx);
+        let old = decompressed(base, x, z)?;
+        let ours = decompressed(ours, x, z)?;
+        let theirs = decompressed(theirs, x, z)?;
+
+        fn data<'a>(slot: &'a Option<(RawChunk, u32, Vec<u8>)>) -> Option<&'a [u8]> {
+            slot.as_ref().map(|s| &s.2[..])
+        }
+        let (ours_changed, theirs_changed) =
+            (data(&ours) != data(&old), data(&theirs) != data(&old));
+
+        let (change, winner) = match (ours_changed, theirs_changed) {
+            (false, false) => (ChunkChange::Unchanged, ours),
+            (true, false) => (ChunkChange::OursOnly, ours),
+            (false, true) => (ChunkChange::TheirsOnly, theirs),
+            _ if data(&ours) == data(&theirs) => (ChunkChange::BothSame, ours),
+            _ => {
+                let winner = match policy {
+                    ConflictPolicy::Report => {
+                        conflicts.push((x, z));
+                        old
+                    }
+                    ConflictPolicy::NewestWins
+                        if theirs.as_ref().map(|t| t.1) > ours.as_ref().map(|o| o.1) =>
+                    {
+                        theirs
+                    }
+                    _ => ours,
+                };
+                (ChunkChange::Conflict, winner)
+            }
+        };
+        changes.push(change);
+
+        if let Some((chunk, timestamp, _)) = winner {
+            writer.push_pending_chunk(PendingChunk::from_raw_chunk(
+                &chunk,
+                (x as u8, z as u8),
+                timestamp,
+            ))?;
+        }
+    }
+
+    Ok(Merge3Result {
+        region: writer.into_bytes()?,
+        changes,
+        conflicts,
+    })
+}
+
+/// The chunk, timestamp & decompressed data of a slot
+fn decompressed<'a>(
+    region: &'a RegionReader,
+    x: usize,
+    z: usize,
+) -> Result<Option<(RawChunk<'a>, u32, Vec<u8>)>, McaError> {
+    let Some(chunk) = region.get_chunk(x, z)? else {
+        return Ok(None);
+    };
+    if chunk.is_external() {
+        return Err(McaError::InvalidChunkPayload(format!(
+            "chunk {x} {z} is stored externally"
+        )));
+    }
+
+    let data = chunk.decompress()?;
+    Ok(Some((chunk, region.get_chunk_timestamp(x, z), data)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{layout, CompressionType};
+
+    const REGION: &[u8] = include_bytes!("../benches/r.0.0.mca");
+
+    #[test]
+    fn merge_newest_and_first() {
+        let region = RegionReader::new(REGION).unwrap();
+        let present = (0..1024)
+            .map(|i| (i % 32, i / 32))
+            .filter(|&(x, z)| region.get_chunk(x, z).unwrap().is_some())
+            .collect::<Vec<_>>();
+        let (newer, older, removed, corrupt) = (present[0], present[1], present[2], present[3]);
+        let empty = (0..1024)
+            .map(|i| (i % 32, i / 32))
+            .find(|c| !present.contains(c))
+            .unwrap();
+
+        let mut writer = RegionWriter::from_region(&region).unwrap();
+        let raw = [10, 1, 2, 3];
+        let base = region.get_chunk_timestamp(newer.0, newer.1);
+        writer
+            .push_chunk_with_timestamp(
+                &raw,
+                (newer.0 as u8, newer.1 as u8),
+                CompressionType::Uncompressed,
+                base + 10,
+            )
+            .unwrap();
+        let base = region.get_chunk_timestamp(older.0, older.1);
+        writer
+            .push_chunk_with_timestamp(
+                &raw,
+                (older.0 as u8, older.1 as u8),
+                CompressionType::Uncompressed,
+                base - 10,
+            )
+            .unwrap();
+        writer.remove_chunk((removed.0 as u8, removed.1 as u8));
+        writer
+            .push_chunk_with_timestamp(
+                &raw,
+                (empty.0 as u8, empty.1 as u8),
+                CompressionType::Uncompressed,
+                5,
+            )
+            .unwrap();
+        let mut modified = vec![];
+        writer.write(&mut modified).unwrap();
+
+        // point the corrupt chunk's location past the end of the file
+        let offset = layout::header_offset_for(corrupt.0, corrupt.1);
+        modified[offset..offset + 4].copy_from_slice(&[0xFF, 0, 0, 1]);
+        let modified = RegionReader::new(&modified).unwrap();
+
+        let (merged, warnings) =
+            merge_regions_with_warnings(&region, &modified, MergePolicy::NewestWins).unwrap();
+        let merged = RegionReader::new(&merged).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].coordinate, corrupt);
+        assert_eq!(warnings[0].input, MergeInput::Second);
+
+        let raw_at = |r: &RegionReader, (x, z): (usize, usize)| {
+            r.get_chunk(x, z).unwrap().map(|c| c.raw_data.to_vec())
+        };
+        assert_eq!(raw_at(&merged, newer).unwrap(), raw);
+        assert_eq!(raw_at(&merged, older), raw_at(&region, older));
+        assert_eq!(raw_at(&merged, removed), raw_at(&region, removed));
+        assert_eq!(raw_at(&merged, corrupt), raw_at(&region, corrupt));
+        assert_eq!(raw_at(&merged, empty).unwrap(), raw);
+        assert_eq!(merged.get_chunk_timestamp(empty.0, empty.1), 5);
+        for &(x, z) in &present[4..] {
+            assert_eq!(raw_at(&merged, (x, z)), raw_at(&region, (x, z)));
+            assert_eq!(
+                merged.get_chunk_timestamp(x, z),
+                region.get_chunk_timestamp(x, z)
+            );
+        }
+        assert_eq!(merged.occupancy().count(), present.len() + 1);
+
+        let first = merge_regions(&region, &modified, MergePolicy::PreferFirst).unwrap();
+        let first = RegionReader::new(&first).unwrap();
+        assert_eq!(raw_at(&first, newer), raw_at(&region, newer));
+        assert_eq!(raw_at(&first, empty).unwrap(), raw);
+    }
+
+    #[test]
+    fn three_way() {
+        let nbt = |v: u8| [10, v, 0, 0];
+        let region = |chunks: &[(u8, u8, u32)], compression: CompressionType| {
+            let mut writer = RegionWriter::new();
+            for &(x, v, timestamp) in chunks {
+                writer
+                    .push_chunk_with_timestamp(&nbt(v), (x, 0), compression.clone(), timestamp)
+                    .unwrap();
+            }
+            writer.into_bytes().unwrap()
+        };
+
+        // slot 0 is untouched, 1 changed by ours, 2 by theirs, 3 the same by both, 4 & 5 differently,
+        // 6 removed by ours, 7 added by theirs, 8 removed by ours & changed by theirs
+        let base = region(
+            &[
+                (0, 0, 1),
+                (1, 1, 1),
+                (2, 2, 1),
+                (3, 3, 1),
+                (4, 4, 1),
+                (5, 5, 1),
+                (6, 6, 1),
+                (8, 8, 1),
+            ],
+            CompressionType::Zlib,
+        );
+        let ours = region(
+            &[
+                (0, 0, 1),
+                (1, 11, 2),
+                (2, 2, 1),
+                (3, 33, 2),
+                (4, 41, 5),
+                (5, 51, 2),
+            ],
+            CompressionType::Zlib,
+        );
+        // theirs is stored uncompressed, recompression alone isn't a change
+        let theirs = region(
+            &[
+                (0, 0, 3),
+                (1, 1, 3),
+                (2, 22, 3),
+                (3, 33, 3),
+                (4, 42, 3),
+                (5, 52, 3),
+                (6, 6, 3),
+                (7, 7, 3),
+                (8, 88, 3),
+            ],
+            CompressionType::Uncompressed,
+        );
+        let (base, ours, theirs) = (
+            RegionReader::new(&base).unwrap(),
+            RegionReader::new(&ours).unwrap(),
+            RegionReader::new(&theirs).unwrap(),
+        );
+
+        let merge = |policy| {
+            let result = merge3(&base, &ours, &theirs, policy).unwrap();
+            let slots = (0..10)
+                .map(|x| {
+                    let region = RegionReader::new(&result.region).unwrap();
+                    let chunk = region.get_chunk(x, 0).unwrap()?;
+                    Some((
+                        chunk.decompress().unwrap()[1],
+                        region.get_chunk_timestamp(x, 0),
+                    ))
+                })
+                .collect::<Vec<_>>();
+            (result, slots)
+        };
+
+        let (result, slots) = merge(ConflictPolicy::Report);
+        use ChunkChange::*;
+        assert_eq!(
+            (0..10)
+                .map(|x| result.change(x, 0).unwrap())
+                .collect::<Vec<_>>(),
+            [
+                Unchanged, OursOnly, TheirsOnly, BothSame, Conflict, Conflict, OursOnly,
+                TheirsOnly, Conflict, Unchanged
+            ]
+        );
+        assert!(result.changes[10..].iter().all(|c| *c == Unchanged));
+        assert!(matches!(
+            result.change(32, 0),
+            Err(McaError::InvalidCoordinate { x: 32, z: 0 })
+        ));
+        assert_eq!(result.conflicts, [(4, 0), (5, 0), (8, 0)]);
+        assert_eq!(
+            slots,
+            [
+                Some((0, 1)),
+                Some((11, 2)),
+                Some((22, 3)),
+                Some((33, 2)),
+                Some((4, 1)),
+                Some((5, 1)),
+                None,
+                Some((7, 3)),
+                Some((8, 1)),
+                None
+            ]
+        );
+
+        let (result, slots) = merge(ConflictPolicy::NewestWins);
+        assert!(result.conflicts.is_empty());
+        assert_eq!(slots[4], Some((41, 5)));
+        assert_eq!(slots[5], Some((52, 3)));
+        assert_eq!(slots[8], Some((88, 3)));
+
+        let (_, slots) = merge(ConflictPolicy::PreferOurs);
+        assert_eq!(slots[4], Some((41, 5)));
+        assert_eq!(slots[5], Some((51, 2)));
+        assert_eq!(slots[8], None);
+        assert_eq!(
+            &slots[..4],
+            [Some((0, 1)), Some((11, 2)), Some((22, 3)), Some((33, 2))]
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use fastnbt::Value;
+use serde::Deserialize;
+
+use crate::McaError;
+
+// only the position is read, every other tag is skipped
+#[derive(Deserialize)]
+struct ChunkNbt {
+    #[serde(rename = "xPos")]
+    x: Option<i32>,
+    #[serde(rename = "zPos")]
+    z: Option<i32>,
+    // chunks from before 1.18 keep their data in a `Level` compound
+    #[serde(rename = "Level")]
+    level: Option<Level>,
+}
+
+#[derive(Deserialize)]
+struct Level {
+    #[serde(rename = "xPos")]
+    x: i32,
+    #[serde(rename = "zPos")]
+    z: i32,
+}
+
+/// Reads the world chunk position stored in raw chunk NBT
+pub(crate) fn chunk_position(raw_data: &[u8]) -> Result<(i32, i32), McaError> {
+    let nbt: ChunkNbt = fastnbt::from_bytes(raw_data)
+        .map_err(|e| McaError::InvalidChunkPayload(format!("unreadable chunk NBT: {e}")))?;
+
+    match (nbt.x, nbt.z, nbt.level) {
+        (Some(x), Some(z), _) => Ok((x, z)),
+        (_, _, Some(level)) => Ok((level.x, level.z)),
+        _ => Err(McaError::InvalidChunkPayload(
+            "chunk NBT has no xPos & zPos".to_string(),
+        )),
+    }
+}
+
+/// Errors with [`McaError::PositionMismatch`] when the NBT position isn't `expected`
+pub(crate) fn check_position(raw_data: &[u8], expected: (i32, i32)) -> Result<(), McaError> {
+    let found = chunk_position(raw_data)?;
+    if found != expected {
+        return Err(McaError::PositionMismatch { expected, found });
+    }
+
+    Ok(())
+}
+
+/// Moves raw chunk NBT by `offset` chunks, rewriting `xPos` & `zPos`
+/// and the block position of every block entity along with it
+pub(crate) fn relocate(raw_data: &[u8], offset: (i32, i32)) -> Result<Vec<u8>, McaError> {
+    let mut nbt: Value = fastnbt::from_bytes(raw_data)
+        .map_err(|e| McaError::InvalidChunkPayload(format!("unreadable chunk NBT: {e}")))?;
+
+    let Value::Compound(root) = &mut nbt else {
+        return Err(McaError::InvalidChunkPayload(
+            "chunk NBT root isn't a compound".to_string(),
+        ));
+    };
+
+    // chunks from before 1.18 keep everything in `Level`
+    match root.get_mut("Level") {
+        Some(Value::Compound(level)) => move_chunk(level, offset)?,
+        _ => move_chunk(root, offset)?,
+    }
+
+    fastnbt::to_bytes(&nbt)
+        .map_err(|e| McaError::InvalidChunkPayload(format!("unwritable chunk NBT: {e}")))
+}
+
+fn move_chunk(chunk: &mut HashMap<String, Value>, offset: (i32, i32)) -> Result<(), McaError> {
+    let mut moved = false;
+    for (key, by) in [("xPos", offset.0), ("zPos", offset.1)] {
+        if let Some(Value::Int(pos)) = chunk.get_mut(key) {
+            *pos = moved_by(*pos, Some(by), key)?;
+            moved = true;
+        }
+    }
+    if !moved {
+        return Err(McaError::InvalidChunkPayload(
+            "chunk NBT has no xPos & zPos".to_string(),
+        ));
+    }
+
+    // block entities store absolute block positions
+    for key in ["block_entities", "TileEntities"] {
+        let Some(Value::List(entities)) = chunk.get_mut(key) else {
+            continue;
+        };
+
+        for entity in entities.iter_mut() {
+            let Value::Compound(entity) = entity else {
+                continue;
+            };
+            for (key, by) in [("x", offset.0), ("z", offset.1)] {
+                if let Some(Value::Int(pos)) = entity.get_mut(key) {
+                    *pos = moved_by(*pos, by.checked_mul(16), key)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// `pos` moved by `by`, failing instead of wrapping around, `None` is an offset that already overflowed
+fn moved_by(pos: i32, by: Option<i32>, key: &str) -> Result<i32, McaError> {
+    by.and_then(|by| pos.checked_add(by))
+        .ok_or_else(|| McaError::InvalidChunkPayload(format!("{key} {pos} overflows when moved")))
+}
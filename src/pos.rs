@@ -0,0 +1,34 @@
+/// The position of a region in the world, `r.{x}.{z}.mca`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RegionPos {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl RegionPos {
+    pub fn new(x: i32, z: i32) -> RegionPos {
+        RegionPos { x, z }
+    }
+
+    /// The region owning the world chunk position & the chunk's local coordinate inside it
+    pub fn from_chunk(chunk_x: i32, chunk_z: i32) -> (RegionPos, (u8, u8)) {
+        let region = RegionPos::new(chunk_x.div_euclid(32), chunk_z.div_euclid(32));
+        (
+            region,
+            (chunk_x.rem_euclid(32) as u8, chunk_z.rem_euclid(32) as u8),
+        )
+    }
+
+    /// The vanilla file name of this region, `r.{x}.{z}.mca`
+    pub fn file_name(&self) -> String {
+        format!("r.{}.{}.mca", self.x, self.z)
+    }
+
+    /// The world chunk position of a chunk at the local `coordinate` inside this region
+    pub fn chunk_pos(&self, coordinate: (u8, u8)) -> (i32, i32) {
+        (
+            self.x * 32 + coordinate.0 as i32,
+            self.z * 32 + coordinate.1 as i32,
+        )
+    }
+}
@@ -1,4 +1,15 @@
-use crate::{chunk::RawChunk, compression::CompressionType, McaError, SECTOR_SIZE};
+use std::{fmt, ops::Range, path::Path};
+
+use crate::{
+    chunk::RawChunk,
+    compat::{CompatIssue, CompatReport, MinVersion},
+    compression::CompressionType,
+    layout::{
+        self, CHUNK_COUNT, EXTERNAL_FLAG, HEADER_SIZE, LENGTH_FIELD_SIZE, PAYLOAD_HEADER_SIZE,
+        SECTOR_SIZE,
+    },
+    McaError, MccFile, RegionPos,
+};
 
 /// A Minecraft region
 ///
@@ -7,17 +18,58 @@ use crate::{chunk::RawChunk, compression::CompressionType, McaError, SECTOR_SIZE
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct RegionReader<'a> {
     data: &'a [u8],
+    // zero length payloads are errors instead of empty slots, see `new_strict`
+    strict: bool,
 }
 
 impl<'a> RegionReader<'a> {
     /// Initializes a new region  
     /// Validates that the region size is at least the size of the header
     pub fn new(data: &'a [u8]) -> Result<RegionReader<'a>, McaError> {
-        if data.len() < (SECTOR_SIZE * 2) {
+        if data.len() < HEADER_SIZE {
             return Err(McaError::MissingHeader);
         }
 
-        Ok(RegionReader { data })
+        Ok(RegionReader {
+            data,
+            strict: false,
+        })
+    }
+
+    /// Same as [`new`](RegionReader::new) but also checks that every chunk's allocated sectors lie inside `data`  
+    /// Fails with [`McaError::TruncatedRegion`] if the location table claims more data than there is.
+    ///
+    /// [`get_chunk`](RegionReader::get_chunk) then fails with [`McaError::EmptyPayload`] on payloads
+    /// with a length of 0, instead of treating them as never generated.
+    pub fn new_strict(data: &'a [u8]) -> Result<RegionReader<'a>, McaError> {
+        let region = RegionReader {
+            strict: true,
+            ..RegionReader::new(data)?
+        };
+        let expected = region.expected_len();
+        if expected > data.len() {
+            return Err(McaError::TruncatedRegion {
+                expected,
+                actual: data.len(),
+            });
+        }
+
+        Ok(region)
+    }
+
+    /// The length the location table claims the region has, the end of the last allocated sector  
+    /// A region shorter than this was cut off, how much is missing tells if a backup is salvageable.
+    pub fn expected_len(&self) -> usize {
+        (0..RegionIter::MAX)
+            .filter_map(|index| {
+                let (x, z) = RegionIter::get_chunk_coordinate(index);
+                self.get_location(layout::header_offset_for(x, z))
+            })
+            .map(|location| {
+                let (sector, count) = layout::decode_location(location);
+                (sector + count) * SECTOR_SIZE
+            })
+            .fold(HEADER_SIZE, usize::max)
     }
 
     /// Get the inner data of the region
@@ -26,22 +78,24 @@ impl<'a> RegionReader<'a> {
     }
 
     /// Get an offset depending on the chunk coordinates.  
-    /// Used in getting byte offsets for chunk location & timestamp in headers
+    /// Used in getting byte offsets for chunk location & timestamp in headers,
+    /// fails with [`McaError::InvalidCoordinate`] outside the region.
     #[inline(always)]
-    pub fn chunk_offset(x: usize, z: usize) -> usize {
-        assert!(x < 32);
-        assert!(z < 32);
+    pub fn chunk_offset(x: usize, z: usize) -> Result<usize, McaError> {
+        layout::check_coordinate(x, z)?;
 
-        4 * ((x & 31) + (z & 31) * 32)
+        Ok(layout::header_offset_for(x, z))
     }
 
     /// Get a single [`RawChunk`] based of its chunk coordinates relative to the region itself.  
-    /// Will return [`None`] if chunk hasn't been generated yet.
-    pub fn get_chunk(&self, x: usize, z: usize) -> Result<Option<RawChunk>, McaError> {
+    /// Will return [`None`] if chunk hasn't been generated yet, or if its payload has a length of 0
+    /// (an error with [`new_strict`](RegionReader::new_strict)).
+    /// A length of 1 only covers the compression byte, the chunk's data is empty then.
+    pub fn get_chunk(&self, x: usize, z: usize) -> Result<Option<RawChunk<'_>>, McaError> {
         // just so we dont have to call .len() more than needed, data len stays the same
         let data_len = self.data.len();
 
-        let offset = RegionReader::chunk_offset(x, z);
+        let offset = RegionReader::chunk_offset(x, z)?;
 
         let chunk_location = match self.get_location(offset) {
             Some(loc) => loc,
@@ -53,64 +107,78 @@ impl<'a> RegionReader<'a> {
             u32::from_be_bytes([0, chunk_location[0], chunk_location[1], chunk_location[2]])
                 as usize;
 
-        let payload_offset: usize = endian * SECTOR_SIZE;
+        // see the payload layout in `layout`, every byte read below is before `payload_end`
+        let payload_start: usize = endian * SECTOR_SIZE;
+        let length_end = payload_start + LENGTH_FIELD_SIZE;
 
-        if data_len < (payload_offset + 4) {
-            return Err(McaError::InvalidChunkPayload(
-                "Not enough data for chunk payload".to_string(),
-            ));
+        if data_len < length_end {
+            return Err(McaError::TruncatedChunk {
+                x,
+                z,
+                missing: length_end - data_len,
+            });
         }
 
         #[cfg(feature = "unsafe")]
         let byte_length = u32::from_be_bytes(unsafe {
             [
-                *self.data.get_unchecked(payload_offset),
-                *self.data.get_unchecked(payload_offset + 1),
-                *self.data.get_unchecked(payload_offset + 2),
-                *self.data.get_unchecked(payload_offset + 3),
+                *self.data.get_unchecked(payload_start),
+                *self.data.get_unchecked(payload_start + 1),
+                *self.data.get_unchecked(payload_start + 2),
+                *self.data.get_unchecked(payload_start + 3),
             ]
         }) as usize;
 
         #[cfg(not(feature = "unsafe"))]
         let byte_length = {
-            let byte_length = self
-                .data
-                .get(payload_offset..payload_offset + 4)
-                .ok_or(McaError::OutOfBoundsByte)?;
-
-            let byte_length = [
-                byte_length[0],
-                byte_length[1],
-                byte_length[2],
-                byte_length[3],
-            ];
+            let byte_length =
+                layout::bytes_at(self.data, payload_start).ok_or(McaError::OutOfBoundsByte)?;
 
             u32::from_be_bytes(byte_length) as usize
         };
 
-        if data_len < payload_offset + byte_length {
-            return Err(McaError::InvalidChunkPayload(
-                "Not enough data for chunk bytes".to_string(),
-            ));
+        let payload_end = layout::payload_end(payload_start, byte_length);
+        if data_len < payload_end {
+            return Err(McaError::TruncatedChunk {
+                x,
+                z,
+                missing: payload_end - data_len,
+            });
         }
 
-        let payload_offset = payload_offset + 4;
+        // not even a compression byte
+        if byte_length == 0 {
+            if self.strict {
+                return Err(McaError::EmptyPayload { x, z });
+            }
+            return Ok(None);
+        }
 
+        // `length_end < payload_end` from here on, the compression byte is the last one before the data
         #[cfg(feature = "unsafe")]
-        let compression_type =
-            CompressionType::from(unsafe { *self.data.get_unchecked(payload_offset) });
+        let compression_byte = unsafe { *self.data.get_unchecked(length_end) };
 
         #[cfg(not(feature = "unsafe"))]
-        let compression_type = CompressionType::from(
-            *self
-                .data
-                .get(payload_offset)
-                .ok_or(McaError::OutOfBoundsByte)?,
-        );
+        let compression_byte = *self.data.get(length_end).ok_or(McaError::OutOfBoundsByte)?;
 
-        let raw_data = &self.data[payload_offset + 1..payload_offset + byte_length];
+        let external = compression_byte & EXTERNAL_FLAG != 0;
+        let compression_type = CompressionType::from_u8(compression_byte & !EXTERNAL_FLAG)?;
 
-        Ok(Some(RawChunk::new(raw_data, compression_type)))
+        #[cfg(feature = "unsafe")]
+        let raw_data = unsafe {
+            self.data
+                .get_unchecked(payload_start + PAYLOAD_HEADER_SIZE..payload_end)
+        };
+
+        #[cfg(not(feature = "unsafe"))]
+        let raw_data = self
+            .data
+            .get(payload_start + PAYLOAD_HEADER_SIZE..payload_end)
+            .ok_or(McaError::OutOfBoundsByte)?;
+
+        Ok(Some(
+            RawChunk::new(raw_data, compression_type).with_external(external),
+        ))
     }
 
     #[cfg(feature = "unsafe")]
@@ -141,17 +209,14 @@ impl<'a> RegionReader<'a> {
     /// Get the chunk payload location based off chunk coordinate byte offsets
     #[inline]
     pub fn get_location(&self, offset: usize) -> Option<[u8; 4]> {
-        let bytes = self.data.get(offset..offset + 4);
-
-        if let Some(bytes) = bytes {
-            if bytes[0] == 0 && bytes[3] == 0 {
-                return None;
-            }
+        let bytes = layout::bytes_at(self.data, offset)?;
 
-            Some([bytes[0], bytes[1], bytes[2], bytes[3]])
-        } else {
-            None
+        // Empty chunk locations, hasn't been generated if None
+        if bytes[0] == 0 && bytes[3] == 0 {
+            return None;
         }
+
+        Some(bytes)
     }
 
     #[cfg(feature = "unsafe")]
@@ -172,14 +237,9 @@ impl<'a> RegionReader<'a> {
     /// Get the timestamp big endian bytes for the chunk based off chunk coordinate byte offsets
     #[inline]
     pub fn get_timestamp(&self, offset: usize) -> Result<[u8; 4], McaError> {
-        let offset = SECTOR_SIZE + offset;
-
-        let bytes = self
-            .data
-            .get(offset..offset + 4)
-            .ok_or(McaError::OutOfBoundsByte)?;
+        let offset = offset.saturating_add(SECTOR_SIZE);
 
-        Ok([bytes[0], bytes[1], bytes[2], bytes[3]])
+        layout::bytes_at(self.data, offset).ok_or(McaError::OutOfBoundsByte)
     }
 
     /// Converts the timestamp bytes to u32 unix epoch seconds
@@ -188,14 +248,287 @@ impl<'a> RegionReader<'a> {
         u32::from_be_bytes(timestamp_bytes)
     }
 
-    pub fn iter(&self) -> RegionIter {
+    /// Reads the `c.x.z.mcc` file of an externally stored chunk from `dir`, the directory holding this region  
+    /// `region` is this region's position, which the file name depends on.
+    /// Returns `None` if the slot is empty or the chunk is stored inside the region.
+    pub fn read_external(
+        &self,
+        x: usize,
+        z: usize,
+        region: RegionPos,
+        dir: &Path,
+    ) -> Result<Option<MccFile>, McaError> {
+        layout::check_coordinate(x, z)?;
+        let Some(chunk) = self.get_chunk(x, z)?.filter(RawChunk::is_external) else {
+            return Ok(None);
+        };
+
+        let (chunk_x, chunk_z) = region.chunk_pos((x as u8, z as u8));
+        let path = dir.join(MccFile::name_for(chunk_x, chunk_z));
+        MccFile::read(&path, chunk.get_compression_type()).map(Some)
+    }
+
+    /// Get the timestamp of a chunk in unix epoch seconds, `0` if the slot has never been saved
+    /// or the coordinate is outside the region
+    pub fn get_chunk_timestamp(&self, x: usize, z: usize) -> u32 {
+        let Ok(offset) = RegionReader::chunk_offset(x, z) else {
+            return 0;
+        };
+
+        // the constructor guarantees the whole header is present
+        layout::bytes_at(self.data, SECTOR_SIZE + offset).map_or(0, u32::from_be_bytes)
+    }
+
+    /// Get which chunk slots have been generated, derived purely from the location header
+    pub fn occupancy(&self) -> Occupancy {
+        let mut rows = [0u32; 32];
+
+        for (z, row) in rows.iter_mut().enumerate() {
+            for x in 0..32 {
+                if self.get_location(layout::header_offset_for(x, z)).is_some() {
+                    *row |= 1 << x;
+                }
+            }
+        }
+
+        Occupancy { rows }
+    }
+
+    /// Scans every chunk's compression byte and reports the oldest vanilla release able to load the region.  
+    /// Chunks whose payload lies outside the region data are skipped.
+    pub fn vanilla_compatibility(&self) -> CompatReport {
+        let mut report = CompatReport {
+            required: MinVersion::Anvil,
+            issues: vec![],
+        };
+
+        for index in 0..RegionIter::MAX {
+            let (x, z) = RegionIter::get_chunk_coordinate(index);
+
+            let location = match self.get_location(layout::header_offset_for(x, z)) {
+                Some(loc) => loc,
+                None => continue,
+            };
+
+            let (sector, _) = layout::decode_location(location);
+            let compression_byte = match self
+                .data
+                .get(sector * SECTOR_SIZE + PAYLOAD_HEADER_SIZE - 1)
+            {
+                Some(byte) => *byte,
+                None => continue,
+            };
+
+            let required = MinVersion::for_compression_byte(compression_byte);
+            if required > MinVersion::Anvil {
+                report.required = report.required.max(required);
+                report.issues.push(CompatIssue {
+                    coordinate: (x, z),
+                    compression_byte,
+                    required,
+                });
+            }
+        }
+
+        report
+    }
+
+    /// Get the slack bytes between the end of a chunk's payload and the end of its allocated sectors.  
+    /// Vanilla reuses freed sectors without clearing them, so these often hold residue of older payloads.
+    ///
+    /// Will return [`None`] if chunk hasn't been generated yet.
+    pub fn padding_bytes(&self, x: usize, z: usize) -> Result<Option<&'a [u8]>, McaError> {
+        let location = match self.get_location(RegionReader::chunk_offset(x, z)?) {
+            Some(loc) => loc,
+            None => return Ok(None),
+        };
+
+        let (sector, count) = layout::decode_location(location);
+        let start = sector * SECTOR_SIZE;
+        let end = start + count * SECTOR_SIZE;
+
+        if self.data.len() < end {
+            return Err(McaError::TruncatedChunk {
+                x,
+                z,
+                missing: end - self.data.len(),
+            });
+        }
+
+        let byte_length = match layout::bytes_at(self.data, start) {
+            Some(b) if start + 4 <= end => u32::from_be_bytes(b) as usize,
+            _ => {
+                return Err(McaError::InvalidChunkPayload(
+                    "No sectors allocated for chunk payload".to_string(),
+                ))
+            }
+        };
+
+        // the length field counts the compression byte too
+        let data_len = byte_length.saturating_sub(1);
+        if layout::sectors_for_payload(data_len) as usize > count {
+            return Err(McaError::InvalidChunkPayload(
+                "Chunk payload exceeds its allocated sectors".to_string(),
+            ));
+        }
+
+        let payload_end = layout::payload_end(start, data_len + 1);
+        self.data.get(payload_end..end).map(Some).ok_or_else(|| {
+            McaError::InvalidChunkPayload("Chunk payload exceeds its allocated sectors".to_string())
+        })
+    }
+
+    /// Get the byte ranges after the header that aren't part of any chunk's allocated sectors.  
+    /// Ranges are sorted, merged and clamped to the region data.
+    ///
+    /// Orphaned payloads of deleted chunks are usually found in here.
+    pub fn gap_extents(&self) -> Vec<Range<usize>> {
+        let sectors = self.data.len().div_ceil(SECTOR_SIZE);
+        let mut owned = vec![false; sectors];
+
+        for index in 0..RegionIter::MAX {
+            let (x, z) = RegionIter::get_chunk_coordinate(index);
+
+            if let Some(location) = self.get_location(layout::header_offset_for(x, z)) {
+                let (start, count) = layout::decode_location(location);
+                let end = (start + count).min(sectors);
+
+                for sector in owned.iter_mut().take(end).skip(start) {
+                    *sector = true;
+                }
+            }
+        }
+
+        let mut gaps: Vec<Range<usize>> = vec![];
+        for (sector, _) in owned
+            .iter()
+            .enumerate()
+            .skip(HEADER_SIZE / SECTOR_SIZE)
+            .filter(|(_, owned)| !**owned)
+        {
+            let start = sector * SECTOR_SIZE;
+            let end = (start + SECTOR_SIZE).min(self.data.len());
+
+            match gaps.last_mut() {
+                Some(last) if last.end == start => last.end = end,
+                _ => gaps.push(start..end),
+            }
+        }
+
+        gaps
+    }
+
+    pub fn iter(&self) -> RegionIter<'_> {
+        self.iter_from_index(0)
+    }
+
+    /// Iterates over the chunks starting at header index `start`.  
+    /// Yields the same items as `iter().skip(start)`, an index past [`RegionIter::MAX`] yields nothing.
+    ///
+    /// Pairs with [`RegionIter::position`] to resume an interrupted iteration.
+    pub fn iter_from_index(&self, start: usize) -> RegionIter<'_> {
         RegionIter {
             region: self,
-            index: 0,
+            index: start,
+        }
+    }
+
+    /// Iterates over all generated chunks, nearest to the given chunk coordinate first.  
+    /// Chunks are ordered by Chebyshev distance (square rings), then by Euclidean distance,
+    /// with any remaining ties broken by header order.
+    ///
+    /// The order is computed from the location header alone, payloads are only read when yielded.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// // ...
+    ///
+    /// for chunk in region.iter_from(16, 16) {
+    ///     let ((x, z), chunk) = chunk?;
+    ///     // render the middle of the region first
+    /// }
+    /// ```
+    pub fn iter_from(&self, center_x: usize, center_z: usize) -> RegionCenterIter<'_> {
+        // a center outside the region starts from its closest edge
+        let (center_x, center_z) = (center_x.min(31), center_z.min(31));
+
+        let mut order: Vec<(usize, usize)> = (0..RegionIter::MAX)
+            .map(RegionIter::get_chunk_coordinate)
+            .filter(|&(x, z)| self.get_location(layout::header_offset_for(x, z)).is_some())
+            .collect();
+
+        order.sort_by_key(|&(x, z)| {
+            let dx = x.abs_diff(center_x);
+            let dz = z.abs_diff(center_z);
+
+            (dx.max(dz), dx * dx + dz * dz, z, x)
+        });
+
+        RegionCenterIter {
+            region: self,
+            order: order.into_iter(),
         }
     }
 }
 
+/// A 32x32 bitset of generated chunk slots inside a region.  
+/// Each row is one `z` coordinate, with bit `x` set when that chunk exists.
+///
+/// Its [`Display`](fmt::Display) impl prints an ASCII map, `#` for generated and `.` for empty.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Occupancy {
+    rows: [u32; 32],
+}
+
+impl Occupancy {
+    /// Returns true if the chunk at the given coordinate has been generated,
+    /// false for coordinates outside the region
+    #[inline]
+    pub fn get(&self, x: usize, z: usize) -> bool {
+        if layout::check_coordinate(x, z).is_err() {
+            return false;
+        }
+
+        self.rows.get(z).is_some_and(|row| row & (1 << x) != 0)
+    }
+
+    /// The amount of generated chunks
+    pub fn count(&self) -> usize {
+        self.rows.iter().map(|row| row.count_ones() as usize).sum()
+    }
+
+    /// Get the raw rows, indexed by `z` with bit `x` set for generated chunks
+    pub fn rows(&self) -> [u32; 32] {
+        self.rows
+    }
+
+    /// Converts the bitset into a `[z][x]` grid of booleans
+    pub fn to_grid(&self) -> [[bool; 32]; 32] {
+        let mut grid = [[false; 32]; 32];
+
+        for (z, row) in grid.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                *cell = self.get(x, z);
+            }
+        }
+
+        grid
+    }
+}
+
+impl fmt::Display for Occupancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for z in 0..32 {
+            for x in 0..32 {
+                f.write_str(if self.get(x, z) { "#" } else { "." })?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
 /// An iterator over all chunks inside a region
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct RegionIter<'a> {
@@ -205,7 +538,13 @@ pub struct RegionIter<'a> {
 
 impl RegionIter<'_> {
     /// The max size of chunks inside one region
-    pub const MAX: usize = 32 * 32;
+    pub const MAX: usize = CHUNK_COUNT;
+
+    /// The header index of the next chunk this iterator will yield.  
+    /// Save it to resume later with [`RegionReader::iter_from_index`]
+    pub fn position(&self) -> usize {
+        self.index
+    }
 
     /// Get the chunk coordinate based off (index / [`RegionIter::MAX`])
     pub fn get_chunk_coordinate(index: usize) -> (usize, usize) {
@@ -229,3 +568,31 @@ impl<'a> Iterator for RegionIter<'a> {
         }
     }
 }
+
+/// An iterator over all generated chunks inside a region, ordered outwards from a center chunk.  
+/// Created by [`RegionReader::iter_from`]
+#[derive(Debug, Clone)]
+pub struct RegionCenterIter<'a> {
+    region: &'a RegionReader<'a>,
+    order: std::vec::IntoIter<(usize, usize)>,
+}
+
+impl<'a> Iterator for RegionCenterIter<'a> {
+    type Item = Result<((usize, usize), RawChunk<'a>), McaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (x, z) in self.order.by_ref() {
+            match self.region.get_chunk(x, z) {
+                Ok(Some(chunk)) => return Some(Ok(((x, z), chunk))),
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.order.len()))
+    }
+}
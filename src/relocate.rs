@@ -0,0 +1,116 @@
+use crate::{McaError, PendingChunk, RegionIter, RegionPos, RegionReader, RegionWriter};
+
+/// Rewrites a region that moved from `from` to `to`, so vanilla accepts its chunks at the new position.
+/// Every chunk's `xPos` & `zPos` and its block entity positions are shifted, then it's recompressed
+/// with its original compression type. Timestamps are kept.
+///
+/// Fails on the first chunk that can't be decompressed or parsed, the error names its coordinate.
+/// Fails with [`McaError::RelocationOverflow`] if a position would leave the `i32` range.
+///
+/// ## Example
+/// ```ignore
+/// let data = std::fs::read("r.0.0.mca")?;
+/// let moved = mca::relocate_region(&data, RegionPos::new(0, 0), RegionPos::new(4, -2))?;
+///
+/// std::fs::write("r.4.-2.mca", &moved)?;
+/// ```
+pub fn relocate_region(data: &[u8], from: RegionPos, to: RegionPos) -> Result<Vec<u8>, McaError> {
+    let region = RegionReader::new(data)?;
+    let chunks = |from: i32, to: i32| to.checked_sub(from)?.checked_mul(32);
+    let Some(offset) = chunks(from.x, to.x).zip(chunks(from.z, to.z)) else {
+        return Err(McaError::RelocationOverflow {
+            from: (from.x, from.z),
+            to: (to.x, to.z),
+        });
+    };
+    let mut writer = RegionWriter::with_capacity(RegionIter::MAX);
+
+    for idx in 0..RegionIter::MAX {
+        let (x, z) = RegionIter::get_chunk_coordinate(idx);
+        let Some(chunk) = region.get_chunk(x, z)? else {
+            continue;
+        };
+
+        let relocated = chunk
+            .decompress()
+            .and_then(|raw| crate::nbt::relocate(&raw, offset))
+            .map_err(|e| McaError::InvalidChunkPayload(format!("chunk {x} {z}: {e}")))?;
+        writer.push_pending_chunk(PendingChunk::new(
+            &relocated,
+            chunk.get_compression_type(),
+            region.get_chunk_timestamp(x, z),
+            (x as u8, z as u8),
+        )?)?;
+    }
+
+    writer.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGION: &[u8] = include_bytes!("../benches/r.0.0.mca");
+
+    #[test]
+    fn relocate_bundled_region() {
+        let region = RegionReader::new(REGION).unwrap();
+        let raw = region
+            .get_chunk(8, 3)
+            .unwrap()
+            .unwrap()
+            .decompress()
+            .unwrap();
+        let (x, z) = crate::nbt::chunk_position(&raw).unwrap();
+        let from = RegionPos::new(x.div_euclid(32), z.div_euclid(32));
+        let to = RegionPos::new(from.x + 3, from.z - 2);
+
+        let moved = relocate_region(REGION, from, to).unwrap();
+        let moved = RegionReader::new(&moved).unwrap();
+        assert_eq!(moved.occupancy(), region.occupancy());
+
+        let mut validated = RegionWriter::new();
+        validated.validate_positions(to);
+        for idx in 0..RegionIter::MAX {
+            let (x, z) = RegionIter::get_chunk_coordinate(idx);
+            let Some(chunk) = moved.get_chunk(x, z).unwrap() else {
+                continue;
+            };
+            let original = region.get_chunk(x, z).unwrap().unwrap();
+            assert_eq!(
+                chunk.get_compression_type(),
+                original.get_compression_type()
+            );
+            assert_eq!(
+                moved.get_chunk_timestamp(x, z),
+                region.get_chunk_timestamp(x, z)
+            );
+
+            validated
+                .push_chunk(&chunk.decompress().unwrap(), (x as u8, z as u8))
+                .unwrap();
+        }
+
+        // moving it back restores the original positions
+        let back = relocate_region(moved.inner(), to, from).unwrap();
+        let back = RegionReader::new(&back).unwrap();
+        let raw = back.get_chunk(8, 3).unwrap().unwrap().decompress().unwrap();
+        assert_eq!(crate::nbt::chunk_position(&raw).unwrap(), (x, z));
+
+        let mut broken = RegionWriter::new();
+        broken.push_chunk(&[10, 0, 0, 0], (4, 5)).unwrap();
+        let mut buf = vec![];
+        broken.write(&mut buf).unwrap();
+        let err = relocate_region(&buf, from, to).unwrap_err();
+        assert!(err.to_string().contains("chunk 4 5"), "{err}");
+
+        let far = RegionPos::new(i32::MAX / 32, 0);
+        assert!(matches!(
+            relocate_region(REGION, RegionPos::new(-1, 0), far),
+            Err(McaError::RelocationOverflow { .. })
+        ));
+        // the region offset fits, the block entity positions 16 times further out don't
+        let err = relocate_region(REGION, from, RegionPos::new(far.x / 8, 0)).unwrap_err();
+        assert!(err.to_string().contains("overflows"), "{err}");
+    }
+}
@@ -0,0 +1,221 @@
+use std::io::{Seek, SeekFrom, Write};
+
+use crate::{
+    chunk::PendingChunk,
+    layout::{self, CHUNK_COUNT, HEADER_SIZE, MAX_CHUNK_SECTORS, SECTOR_SIZE},
+    writer::{write_payload, RegionWriter},
+    CompressionLevel, CompressionType, McaError,
+};
+
+/// A writer that writes chunks to a region (`mca`) file as soon as they're pushed.  
+///
+/// Unlike [`RegionWriter`] no compressed payloads are held on to, only the header tables,
+/// so memory stays at roughly the size of the largest chunk.  
+/// Payloads are laid out in push order and the header is written by [`finish`](RegionStreamWriter::finish).
+///
+/// A coordinate can only be pushed once, a second push fails with [`McaError::DuplicateChunk`]
+/// since the first payload has already been written.
+///
+/// ## Example
+/// ```ignore
+/// use mca::RegionStreamWriter;
+///
+/// let file = std::fs::File::create("r.0.0.mca")?;
+/// let mut writer = RegionStreamWriter::new(file)?;
+///
+/// for (coordinate, data) in chunks {
+///     writer.push_chunk(&data, coordinate)?;
+/// }
+///
+/// writer.finish()?;
+/// ```
+#[derive(Debug)]
+pub struct RegionStreamWriter<W: Write + Seek> {
+    w: W,
+    start: u64,
+    locations: [[u8; 4]; CHUNK_COUNT],
+    timestamps: [u32; CHUNK_COUNT],
+    // byte offset of the next payload, relative to the start of the region
+    offset: usize,
+}
+
+impl<W: Write + Seek> RegionStreamWriter<W> {
+    /// Creates a new stream writer, reserving the header at the sink's current position
+    pub fn new(mut w: W) -> Result<RegionStreamWriter<W>, McaError> {
+        let start = w.stream_position()?;
+        w.write_all(&[0; HEADER_SIZE])?;
+
+        Ok(RegionStreamWriter {
+            w,
+            start,
+            locations: [[0; 4]; CHUNK_COUNT],
+            timestamps: [0; CHUNK_COUNT],
+            offset: HEADER_SIZE,
+        })
+    }
+
+    /// Compresses a raw chunk with `Zlib` and writes it  
+    /// Timestamp will be current time since `UNIX_EPOCH`
+    pub fn push_chunk(&mut self, raw_data: &[u8], coordinate: (u8, u8)) -> Result<(), McaError> {
+        self.push_chunk_with_compression(raw_data, coordinate, CompressionType::Zlib)
+    }
+
+    /// Compresses a raw chunk with the given compression type and writes it  
+    /// Timestamp will be current time since `UNIX_EPOCH`
+    pub fn push_chunk_with_compression(
+        &mut self,
+        raw_data: &[u8],
+        coordinate: (u8, u8),
+        compression_type: CompressionType,
+    ) -> Result<(), McaError> {
+        let chunk = PendingChunk::new(
+            raw_data,
+            compression_type,
+            RegionWriter::get_current_timestamp()?,
+            coordinate,
+        )?;
+
+        self.push_pending_chunk(&chunk)
+    }
+
+    /// Writes a [`PendingChunk`], one with deferred compression is compressed at the default level first
+    pub fn push_pending_chunk(&mut self, chunk: &PendingChunk) -> Result<(), McaError> {
+        let (x, z) = chunk.coordinate;
+        layout::check_coordinate(x as usize, z as usize)?;
+        let index = layout::header_offset_for(x as usize, z as usize) / 4;
+
+        if self
+            .locations
+            .get(index)
+            .is_some_and(|entry| *entry != [0; 4])
+        {
+            return Err(McaError::DuplicateChunk { x, z });
+        }
+
+        let mut compressed = None;
+        if !chunk.is_compressed() {
+            let mut copy = chunk.clone();
+            copy.compress(CompressionLevel::default())?;
+            compressed = Some(copy);
+        }
+        let chunk = compressed.as_ref().unwrap_or(chunk);
+
+        let sector_count = layout::sectors_for_payload(chunk.payload().len());
+        if sector_count > MAX_CHUNK_SECTORS {
+            return Err(McaError::ChunkTooLarge {
+                x,
+                z,
+                bytes: chunk.payload().len(),
+            });
+        }
+
+        let entry = RegionWriter::location_entry(self.offset, sector_count as u8)?;
+        write_payload(&mut self.w, chunk.compression.to_u8(), chunk.payload())?;
+
+        if let (Some(location), Some(timestamp)) = (
+            self.locations.get_mut(index),
+            self.timestamps.get_mut(index),
+        ) {
+            *location = entry;
+            *timestamp = chunk.timestamp;
+        }
+        self.offset += sector_count as usize * SECTOR_SIZE;
+
+        Ok(())
+    }
+
+    /// The amount of chunks written so far
+    pub fn len(&self) -> usize {
+        self.locations.iter().filter(|l| **l != [0; 4]).count()
+    }
+
+    /// Returns true if no chunks have been written yet
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes the header tables, flushes and returns the sink positioned at the end of the region
+    pub fn finish(mut self) -> Result<W, McaError> {
+        let end = self.w.stream_position()?;
+        self.w.seek(SeekFrom::Start(self.start))?;
+
+        for entry in self.locations.iter() {
+            self.w.write_all(entry)?;
+        }
+        for timestamp in self.timestamps.iter() {
+            self.w.write_all(&timestamp.to_be_bytes())?;
+        }
+
+        self.w.seek(SeekFrom::Start(end))?;
+        self.w.flush()?;
+
+        Ok(self.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::RegionReader;
+
+    const REGION: &[u8] = include_bytes!("../benches/r.0.0.mca");
+
+    #[test]
+    fn matches_region_writer() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut writer = RegionWriter::new();
+        let mut stream = RegionStreamWriter::new(Cursor::new(vec![])).unwrap();
+
+        for (idx, chunk) in region.iter().enumerate() {
+            if let Some(chunk) = chunk.unwrap() {
+                let (x, z) = ((idx % 32) as u8, (idx / 32) as u8);
+                let timestamp = region.get_chunk_timestamp(x as usize, z as usize);
+                let pending = PendingChunk::from_raw_chunk(&chunk, (x, z), timestamp);
+
+                stream.push_pending_chunk(&pending).unwrap();
+                writer.push_pending_chunk(pending).unwrap();
+            }
+        }
+
+        assert_eq!(stream.len(), writer.len());
+        let streamed = stream.finish().unwrap().into_inner();
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        assert_eq!(streamed, buf);
+
+        let new_region = RegionReader::new(&streamed).unwrap();
+        for (old, new) in region.iter().zip(new_region.iter()) {
+            assert_eq!(old.unwrap(), new.unwrap());
+        }
+    }
+
+    #[test]
+    fn duplicate_and_compressed_pushes() {
+        let mut stream = RegionStreamWriter::new(Cursor::new(vec![])).unwrap();
+        assert!(stream.is_empty());
+
+        stream.push_chunk(&[10, 1, 2, 3], (2, 7)).unwrap();
+        stream
+            .push_chunk_with_compression(&[10, 4, 5, 6], (3, 7), CompressionType::LZ4)
+            .unwrap();
+
+        let err = stream.push_chunk(&[10, 0, 0, 0], (2, 7)).unwrap_err();
+        assert!(matches!(err, McaError::DuplicateChunk { x: 2, z: 7 }));
+
+        let buf = stream.finish().unwrap().into_inner();
+        assert_eq!(buf.len(), HEADER_SIZE + SECTOR_SIZE * 2);
+
+        let region = RegionReader::new(&buf).unwrap();
+        let first = region.get_chunk(2, 7).unwrap().unwrap();
+        assert_eq!(first.get_compression_type(), CompressionType::Zlib);
+        assert_eq!(first.decompress().unwrap(), vec![10, 1, 2, 3]);
+
+        let second = region.get_chunk(3, 7).unwrap().unwrap();
+        assert_eq!(second.get_compression_type(), CompressionType::LZ4);
+        assert_eq!(second.decompress().unwrap(), vec![10, 4, 5, 6]);
+        assert_ne!(region.get_chunk_timestamp(3, 7), 0);
+    }
+}
@@ -0,0 +1,391 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+};
+
+use crate::{McaError, RegionPos, RegionReader, RegionWriter};
+
+/// Writes chunks given in world coordinates into the `r.x.z.mca` files of a region directory.
+///
+/// One [`RegionWriter`] is kept per region being written, up to a limit after which the
+/// least recently used one is written out. Pushing to a region again later picks its file back up.
+/// Every region file is written atomically, see [`RegionWriter::write_to_path`].
+///
+/// Region files already in `dir` are loaded on the first push to them,
+/// their chunks are kept unless pushed over.
+///
+/// ## Example
+/// ```ignore
+/// let mut world = WorldWriter::new(Path::new("world/region"));
+///
+/// for (x, z, nbt) in generated {
+///     world.push_chunk(x, z, &nbt)?;
+/// }
+/// world.finish()?;
+/// ```
+#[derive(Debug)]
+pub struct WorldWriter {
+    dir: PathBuf,
+    regions: HashMap<RegionPos, RegionWriter>,
+    // least recently used region first
+    recent: VecDeque<RegionPos>,
+    max_open: usize,
+}
+
+impl WorldWriter {
+    /// The amount of region writers kept open by default
+    pub const DEFAULT_MAX_OPEN: usize = 16;
+
+    /// Creates a world writer putting region files into `dir`, which is created if missing
+    pub fn new(dir: &Path) -> WorldWriter {
+        WorldWriter {
+            dir: dir.to_path_buf(),
+            regions: HashMap::new(),
+            recent: VecDeque::new(),
+            max_open: Self::DEFAULT_MAX_OPEN,
+        }
+    }
+
+    /// Sets how many region writers are kept in memory at once, at least 1  
+    /// Defaults to [`DEFAULT_MAX_OPEN`](WorldWriter::DEFAULT_MAX_OPEN)
+    pub fn set_max_open(&mut self, max_open: usize) {
+        self.max_open = max_open.max(1);
+    }
+
+    /// Pushes a raw chunk at the world chunk position `world_x` `world_z` into the region owning it  
+    /// Compressed with `Zlib`, timestamp will be current time since [`UNIX_EPOCH`](std::time::UNIX_EPOCH)
+    pub fn push_chunk(
+        &mut self,
+        world_x: i32,
+        world_z: i32,
+        raw_nbt: &[u8],
+    ) -> Result<(), McaError> {
+        let (region, coordinate) = RegionPos::from_chunk(world_x, world_z);
+        self.open(region)?.push_chunk(raw_nbt, coordinate)
+    }
+
+    /// Roughly how many bytes the region writers kept open hold, see [`RegionWriter::memory_usage`]
+    pub fn memory_usage(&self) -> usize {
+        self.regions.values().map(RegionWriter::memory_usage).sum()
+    }
+
+    /// Writes out the regions holding the most memory until the ones left hold at most `bytes`
+    ///
+    /// ## Example
+    /// ```ignore
+    /// for (x, z, nbt) in generated {
+    ///     world.push_chunk(x, z, &nbt)?;
+    ///     world.flush_largest_until(512 * 1024 * 1024)?;
+    /// }
+    /// ```
+    pub fn flush_largest_until(&mut self, bytes: usize) -> Result<(), McaError> {
+        let mut usage = self
+            .regions
+            .iter()
+            .map(|(region, writer)| (writer.memory_usage(), *region))
+            .collect::<Vec<_>>();
+        usage.sort_unstable();
+
+        let mut total = usage.iter().map(|(size, _)| size).sum::<usize>();
+        while total > bytes {
+            let Some((size, region)) = usage.pop() else {
+                break;
+            };
+            self.recent.retain(|r| *r != region);
+            self.flush(region)?;
+            total -= size;
+        }
+
+        Ok(())
+    }
+
+    /// Writes every region still held in memory
+    pub fn finish(mut self) -> Result<(), McaError> {
+        while let Some(region) = self.recent.pop_front() {
+            self.flush(region)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes every region still held in memory, up to `parallelism` files at once  
+    /// `0` uses as many threads as rayon does by default.
+    ///
+    /// Every region is written even if some fail, the failures are reported together
+    /// as [`McaError::FlushFailed`]. Each file is still written atomically.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// // keep the disk busy without taking every core
+    /// world.finish_parallel(4)?;
+    /// ```
+    #[cfg(feature = "rayon")]
+    pub fn finish_parallel(mut self, parallelism: usize) -> Result<(), McaError> {
+        use rayon::prelude::*;
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(parallelism)
+            .build()
+            .map_err(std::io::Error::other)?;
+        let regions = self
+            .recent
+            .drain(..)
+            .filter_map(|region| Some((region, self.regions.remove(&region)?)))
+            .collect::<Vec<_>>();
+
+        let mut failed = pool.install(|| {
+            regions
+                .into_par_iter()
+                .filter_map(|(region, writer)| {
+                    let path = self.dir.join(region.file_name());
+                    writer.write_to_path_all(&path).err().map(|e| (region, e))
+                })
+                .collect::<Vec<_>>()
+        });
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            failed.sort_by_key(|(region, _)| *region);
+            Err(McaError::FlushFailed(failed))
+        }
+    }
+
+    /// The writer for `region`, writing out the least recently used one if too many are open
+    fn open(&mut self, region: RegionPos) -> Result<&mut RegionWriter, McaError> {
+        if let Some(at) = self.recent.iter().position(|r| *r == region) {
+            self.recent.remove(at);
+        } else {
+            if self.recent.len() >= self.max_open {
+                if let Some(oldest) = self.recent.pop_front() {
+                    self.flush(oldest)?;
+                }
+            }
+
+            // existing & already flushed regions are picked back up, vanilla leaves empty files around too
+            let path = self.path_for(region);
+            let data = match path.is_file() {
+                true => std::fs::read(path)?,
+                false => vec![],
+            };
+            let writer = match data.is_empty() {
+                true => RegionWriter::new(),
+                false => RegionWriter::from_region(&RegionReader::new(&data)?)?,
+            };
+            self.regions.insert(region, writer);
+        }
+        self.recent.push_back(region);
+
+        Ok(self.regions.entry(region).or_default())
+    }
+
+    fn flush(&mut self, region: RegionPos) -> Result<(), McaError> {
+        if let Some(writer) = self.regions.remove(&region) {
+            writer.write_to_path_all(&self.path_for(region))?;
+        }
+
+        Ok(())
+    }
+
+    fn path_for(&self, region: RegionPos) -> PathBuf {
+        self.dir.join(region.file_name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_across_regions() {
+        let dir = std::env::temp_dir().join(format!("mca-world-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let chunks = [(5, 5), (-5, 5), (5, -40), (-33, -1), (31, 0), (-1, -32)];
+        let mut world = WorldWriter::new(&dir);
+        world.set_max_open(2);
+        for (i, (x, z)) in chunks.into_iter().enumerate() {
+            world.push_chunk(x, z, &[10, i as u8, 0, 0]).unwrap();
+        }
+        world.finish().unwrap();
+
+        let mut files = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .filter(|name| !name.ends_with(".lock"))
+            .collect::<Vec<_>>();
+        files.sort();
+        let mut expected = [
+            "r.0.0.mca",
+            "r.-1.0.mca",
+            "r.0.-2.mca",
+            "r.-2.-1.mca",
+            "r.-1.-1.mca",
+        ];
+        expected.sort();
+        assert_eq!(files, expected);
+
+        for (i, (x, z)) in chunks.into_iter().enumerate() {
+            let (region, (local_x, local_z)) = RegionPos::from_chunk(x, z);
+            let data = std::fs::read(dir.join(region.file_name())).unwrap();
+            let region = RegionReader::new(&data).unwrap();
+            let chunk = region
+                .get_chunk(local_x as usize, local_z as usize)
+                .unwrap()
+                .unwrap();
+            assert_eq!(chunk.decompress().unwrap(), [10, i as u8, 0, 0]);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn existing_regions() {
+        let dir = std::env::temp_dir().join(format!("mca-world-existing-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut existing = RegionWriter::new();
+        existing.push_chunk(&[10, 1, 0, 0], (1, 0)).unwrap();
+        existing.push_chunk(&[10, 2, 0, 0], (2, 0)).unwrap();
+        existing
+            .write_to_path(&dir.join(RegionPos::new(0, 0).file_name()))
+            .unwrap();
+        std::fs::write(dir.join(RegionPos::new(-1, 0).file_name()), []).unwrap();
+
+        let mut world = WorldWriter::new(&dir);
+        world.push_chunk(2, 0, &[10, 22, 0, 0]).unwrap();
+        world.push_chunk(3, 0, &[10, 3, 0, 0]).unwrap();
+        world.push_chunk(-1, 0, &[10, 4, 0, 0]).unwrap();
+        world.finish().unwrap();
+
+        let data = std::fs::read(dir.join(RegionPos::new(0, 0).file_name())).unwrap();
+        let region = RegionReader::new(&data).unwrap();
+        let chunks = (0..4)
+            .map(|x| {
+                let chunk = region.get_chunk(x, 0).unwrap()?;
+                Some(chunk.decompress().unwrap()[1])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(chunks, [None, Some(1), Some(22), Some(3)]);
+
+        let data = std::fs::read(dir.join(RegionPos::new(-1, 0).file_name())).unwrap();
+        assert_eq!(RegionReader::new(&data).unwrap().occupancy().count(), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn flush_largest() {
+        let dir = std::env::temp_dir().join(format!("mca-world-largest-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut world = WorldWriter::new(&dir);
+        assert_eq!(world.memory_usage(), 0);
+        // region (1, 0) gets by far the most chunks
+        for i in 0..3 {
+            world.push_chunk(i, 0, &[10, i as u8, 0, 0]).unwrap();
+            world.push_chunk(-1 - i, 0, &[10, i as u8, 0, 0]).unwrap();
+        }
+        for i in 0..200 {
+            world
+                .push_chunk(32 + i % 32, i / 32, &[10, 0, 0, 0])
+                .unwrap();
+        }
+        let usage = world.memory_usage();
+        let largest = world.regions[&RegionPos::new(1, 0)].memory_usage();
+
+        world.flush_largest_until(usage - 1).unwrap();
+        assert_eq!(world.memory_usage(), usage - largest);
+        assert_eq!(world.regions.len(), 2);
+        assert!(dir.join("r.1.0.mca").exists());
+
+        world.flush_largest_until(usage).unwrap();
+        assert_eq!(world.regions.len(), 2);
+        world.flush_largest_until(0).unwrap();
+        assert_eq!(world.memory_usage(), 0);
+        assert!(world.recent.is_empty());
+        for region in ["r.0.0.mca", "r.-1.0.mca"] {
+            let data = std::fs::read(dir.join(region)).unwrap();
+            assert_eq!(RegionReader::new(&data).unwrap().occupancy().count(), 3);
+        }
+
+        // pushing to a flushed region picks its file back up
+        world.push_chunk(3, 0, &[10, 3, 0, 0]).unwrap();
+        world.finish().unwrap();
+        let data = std::fs::read(dir.join("r.0.0.mca")).unwrap();
+        assert_eq!(RegionReader::new(&data).unwrap().occupancy().count(), 4);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_flush() {
+        let dir = std::env::temp_dir().join(format!("mca-world-parallel-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let write = |dir: &Path| {
+            let mut world = WorldWriter::new(dir);
+            world.set_max_open(64);
+            for rx in -3..3 {
+                for rz in -3..3 {
+                    for i in 0..4 {
+                        let (x, z) = (rx * 32 + i * 7, rz * 32 + i * 3);
+                        world
+                            .push_chunk(x, z, &[10, i as u8, rx as u8, rz as u8])
+                            .unwrap();
+                    }
+                }
+            }
+            world.finish_parallel(3)
+        };
+        write(&dir).unwrap();
+
+        for rx in -3..3 {
+            for rz in -3..3 {
+                let data = std::fs::read(dir.join(RegionPos::new(rx, rz).file_name())).unwrap();
+                let region = RegionReader::new(&data).unwrap();
+                assert_eq!(region.occupancy().count(), 4);
+                for i in 0..4 {
+                    let chunk = region.get_chunk(i * 7, i * 3).unwrap().unwrap();
+                    assert_eq!(
+                        chunk.decompress().unwrap(),
+                        [10, i as u8, rx as u8, rz as u8]
+                    );
+                }
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // a directory in the way of two regions fails only those, the rest are still written
+        std::fs::create_dir_all(dir.join(RegionPos::new(0, 0).file_name())).unwrap();
+        std::fs::create_dir_all(dir.join(RegionPos::new(-3, 2).file_name())).unwrap();
+        let Err(error) = write(&dir) else {
+            panic!("expected the blocked regions to fail");
+        };
+        assert!(error
+            .to_string()
+            .starts_with("Writing 2 region files failed, the first at"));
+        assert_eq!(
+            McaError::FlushFailed(vec![]).to_string(),
+            "Writing 0 region files failed"
+        );
+        let McaError::FlushFailed(failed) = error else {
+            panic!("expected FlushFailed, got {error}");
+        };
+        let failed = failed.iter().map(|(region, _)| *region).collect::<Vec<_>>();
+        assert_eq!(failed, [RegionPos::new(-3, 2), RegionPos::new(0, 0)]);
+        let written = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter(|e| e.as_ref().unwrap().path().is_file())
+            .filter(|e| {
+                let name = e.as_ref().unwrap().file_name();
+                !name.to_string_lossy().ends_with(".lock")
+            })
+            .count();
+        assert_eq!(written, 34);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,23 +1,228 @@
 use std::{
-    collections::HashMap,
-    io::Write,
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use crate::{chunk::PendingChunk, CompressionType, McaError, SECTOR_SIZE};
+use crate::{
+    chunk::{ExternalChunk, PendingChunk},
+    compat::{self, MinVersion, TargetVersion},
+    compression::CompressScratch,
+    layout::{
+        self, Layout, SectorMap, CHUNK_COUNT, EXTERNAL_FLAG, HEADER_SIZE, MAX_CHUNK_SECTORS,
+        MAX_SECTOR_OFFSET, SECTOR_SIZE,
+    },
+    ChunkSource, CompressionLevel, CompressionType, Lz4Framing, McaError, MccFile, RegionPos,
+    RegionReader, RegionStreamWriter,
+};
+
+#[cfg(feature = "fs-lock")]
+use crate::RegionLock;
+
+#[cfg(feature = "tokio")]
+mod async_io;
+
+/// What a [`RegionWriter`] does when a chunk is pushed to a coordinate that already has one
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DuplicatePolicy {
+    /// The new chunk replaces the old one, only the latest push is written
+    #[default]
+    Replace,
+    /// The push fails with [`McaError::DuplicateChunk`] and the old chunk is kept
+    Error,
+}
+
+/// Where a [`RegionWriter`] places chunk payloads in the data section  
+/// The header always points at the right payload, only the on-disk order changes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LayoutStrategy {
+    /// Payloads follow header order (z-major), so output only depends on the chunks themselves
+    #[default]
+    CoordinateOrder,
+    /// Payloads are written in the order chunks were pushed
+    InsertionOrder,
+    /// Largest payloads first, ties in coordinate order  
+    /// Smaller chunks at the end have the most room to grow in place later
+    SizeDescending,
+}
+
+/// Totals of a finished [`RegionWriter::write`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WriteSummary {
+    /// Bytes written, header included
+    pub bytes_written: usize,
+    /// Chunks written into the region
+    pub chunks_written: usize,
+    /// Sectors used by chunk payloads, the 2 header sectors not included
+    pub sectors_used: usize,
+    /// Zero bytes padding payloads out to whole sectors
+    pub padding_bytes: usize,
+}
+
+/// What [`RegionWriter::write_with_report`] wrote for one chunk
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChunkWriteRecord {
+    pub coordinate: (u8, u8),
+    /// Size of the uncompressed chunk, `None` for chunks pushed already compressed
+    pub raw_size: Option<usize>,
+    /// Size of the compressed data, the 5 byte payload header not included
+    pub compressed_size: usize,
+    pub compression: CompressionType,
+    /// Sectors the payload takes, a deduplicated chunk reports the sectors it shares
+    pub sector_count: u8,
+}
+
+/// Something that would make the output of a [`RegionWriter`] unloadable, see [`RegionWriter::validate`]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WriterIssue {
+    /// More than one chunk is queued for the coordinate
+    DuplicateCoordinate { x: u8, z: u8 },
+    /// The coordinate is outside the region
+    InvalidCoordinate { x: u8, z: u8 },
+    /// The chunk needs more than 255 sectors, it can only be written with [`RegionWriter::write_with_external`]
+    ChunkTooLarge { x: u8, z: u8, sectors: usize },
+    /// The region would need more sectors than a location entry can point to
+    RegionTooLarge { sectors: usize },
+    /// The chunk has a timestamp of 0 while [`RegionWriter::set_forbid_zero_timestamps`] is on
+    ZeroTimestamp { x: u8, z: u8 },
+    /// The `xPos` & `zPos` in the chunk NBT don't match the slot it's queued at
+    #[cfg(feature = "fastnbt")]
+    PositionMismatch {
+        x: u8,
+        z: u8,
+        expected: (i32, i32),
+        found: (i32, i32),
+    },
+    /// The chunk couldn't be decompressed or its NBT position couldn't be read
+    #[cfg(feature = "fastnbt")]
+    UnreadableNbt { x: u8, z: u8, reason: String },
+}
+
+/// Where [`RegionWriter::push_chunk`] gets its timestamps from
+#[derive(Clone, Default)]
+enum TimestampSource {
+    #[default]
+    SystemClock,
+    Fixed(u32),
+    Provider(Arc<dyn Fn() -> u32 + Send + Sync>),
+}
+
+impl TimestampSource {
+    fn now(&self) -> Result<u32, McaError> {
+        match self {
+            TimestampSource::SystemClock => RegionWriter::get_current_timestamp(),
+            TimestampSource::Fixed(timestamp) => Ok(*timestamp),
+            TimestampSource::Provider(provider) => Ok(provider()),
+        }
+    }
+}
+
+impl std::fmt::Debug for TimestampSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimestampSource::SystemClock => write!(f, "SystemClock"),
+            TimestampSource::Fixed(timestamp) => write!(f, "Fixed({timestamp})"),
+            TimestampSource::Provider(_) => write!(f, "Provider"),
+        }
+    }
+}
+
+// a provider's behaviour can't be compared, so writers using one never equal another
+impl PartialEq for TimestampSource {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (TimestampSource::SystemClock, TimestampSource::SystemClock) => true,
+            (TimestampSource::Fixed(a), TimestampSource::Fixed(b)) => a == b,
+            _ => false,
+        }
+    }
+}
 
 /// A writer used to write chunks to a region (`mca`) file.  
-#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RegionWriter {
     chunks: Vec<PendingChunk>,
+    duplicate_policy: DuplicatePolicy,
+    default_compression: CompressionType,
+    compression_level: CompressionLevel,
+    layout: LayoutStrategy,
+    // region whose layout unchanged chunks keep, see `preserve_layout`
+    base: Option<Vec<u8>>,
+    // changed chunks that still fit stay in their old sectors, see `preserve_offsets_from`
+    in_place: bool,
+    // pushed chunks keep their raw data until written, see `set_deferred_compression`
+    defer_compression: bool,
+    deduplicate: bool,
+    // empty sectors allocated after a chunk's payload, see `reserve_chunk`
+    slack: BTreeMap<(u8, u8), u8>,
+    timestamps: TimestampSource,
+    scratch: CompressScratch,
+    // which region this writer represents, see `for_region`
+    region: Option<RegionPos>,
+    forbid_zero_timestamps: bool,
+    strict_vanilla: bool,
+    // every chunk compressed is decompressed again & compared, see `set_verify_writes`
+    verify_writes: bool,
+    // compression types & external chunks are limited to what it loads, see `for_target`
+    target: Option<TargetVersion>,
+    #[cfg(feature = "fastnbt")]
+    position_check: Option<RegionPos>,
+}
+
+impl Default for RegionWriter {
+    fn default() -> Self {
+        RegionWriter {
+            chunks: vec![],
+            duplicate_policy: DuplicatePolicy::default(),
+            default_compression: CompressionType::Zlib,
+            compression_level: CompressionLevel::default(),
+            layout: LayoutStrategy::default(),
+            base: None,
+            in_place: false,
+            defer_compression: false,
+            deduplicate: false,
+            slack: BTreeMap::new(),
+            timestamps: TimestampSource::default(),
+            scratch: CompressScratch::default(),
+            region: None,
+            forbid_zero_timestamps: false,
+            strict_vanilla: false,
+            verify_writes: false,
+            target: None,
+            #[cfg(feature = "fastnbt")]
+            position_check: None,
+        }
+    }
 }
 
 impl RegionWriter {
-    /// Gets the current time in unix epoch
-    fn get_current_timestamp() -> u32 {
+    /// Gets the current time in unix epoch.  
+    /// Kept in host byte order, it's only converted to big endian when written
+    pub(crate) fn get_current_timestamp() -> Result<u32, McaError> {
         let start = SystemTime::now();
-        let since_the_epoch = start.duration_since(UNIX_EPOCH).unwrap().as_secs() as u32;
-        since_the_epoch.to_be()
+        let since = start
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| McaError::ClockError)?;
+
+        Ok(since.as_secs() as u32)
+    }
+
+    /// Encodes a location header entry for a payload starting at the byte `offset`
+    pub(crate) fn location_entry(offset: usize, sector_count: u8) -> Result<[u8; 4], McaError> {
+        let sector = offset / SECTOR_SIZE;
+        if sector > MAX_SECTOR_OFFSET {
+            return Err(McaError::RegionTooLarge { sector });
+        }
+
+        let be = (sector as u32).to_be_bytes();
+        Ok([be[1], be[2], be[3], sector_count])
     }
 
     /// Creates a new region writer
@@ -25,184 +230,4586 @@ impl RegionWriter {
         Self::default()
     }
 
-    /// Pushes a raw chunk into the writer  
-    /// Defaults to `LZ4` compression, use [`push_chunk_with_compression`] for other compression types.  
+    /// Creates a writer holding every chunk of an existing region  
+    /// Chunks keep their compressed payload and timestamp as is, nothing is recompressed.
     ///
-    /// Timestamp will be current time since [`UNIX_EPOCH`], use [`push_pending_chunk`] to override it.  
-    pub fn push_chunk(&mut self, raw_data: &[u8], coordinate: (u8, u8)) -> Result<(), McaError> {
-        let chunk = PendingChunk::new(
-            raw_data,
-            CompressionType::LZ4,
-            RegionWriter::get_current_timestamp(),
-            coordinate,
-        )?;
-        self.chunks.push(chunk);
+    /// Fails on externally stored chunks since their data lives in a separate `.mcc` file.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let region = RegionReader::new(&data)?;
+    /// let mut writer = RegionWriter::from_region(&region)?;
+    ///
+    /// writer.remove_chunk((4, 6));
+    /// writer.write(&mut buf)?;
+    /// ```
+    pub fn from_region(region: &RegionReader) -> Result<RegionWriter, McaError> {
+        let mut writer = RegionWriter::new();
 
-        Ok(())
+        for z in 0..32 {
+            for x in 0..32 {
+                let Some(chunk) = region.get_chunk(x, z)? else {
+                    continue;
+                };
+
+                if chunk.is_external() {
+                    return Err(McaError::InvalidChunkPayload(format!(
+                        "chunk {x} {z} is stored externally"
+                    )));
+                }
+
+                let timestamp = region.get_chunk_timestamp(x, z);
+                writer.insert(PendingChunk::from_raw_chunk(
+                    &chunk,
+                    (x as u8, z as u8),
+                    timestamp,
+                ))?;
+            }
+        }
+
+        Ok(writer)
     }
 
-    /// Pushes a raw chunk into the writer  
-    /// This specifies the compression type used  
-    pub fn push_chunk_with_compression(
-        &mut self,
-        raw_data: &[u8],
-        coordinate: (u8, u8),
-        compression_type: CompressionType,
-    ) -> Result<(), McaError> {
-        let chunk = PendingChunk::new(
-            raw_data,
-            compression_type,
-            RegionWriter::get_current_timestamp(),
-            coordinate,
-        )?;
-        self.chunks.push(chunk);
+    /// Creates a new region writer with room for `chunks` chunks before reallocating
+    pub fn with_capacity(chunks: usize) -> RegionWriter {
+        RegionWriter {
+            chunks: Vec::with_capacity(chunks.min(CHUNK_COUNT)),
+            ..Self::default()
+        }
+    }
 
-        Ok(())
+    /// Creates a new region writer where [`push_chunk`](RegionWriter::push_chunk) uses `compression`
+    pub fn with_default_compression(compression: CompressionType) -> RegionWriter {
+        RegionWriter {
+            default_compression: compression,
+            ..Self::default()
+        }
     }
 
-    /// Just pushes a [`PendingChunk`] to the writer
-    pub fn push_pending_chunk(&mut self, chunk: PendingChunk) {
-        self.chunks.push(chunk)
+    /// Creates a new region writer where [`push_chunk`](RegionWriter::push_chunk) & friends
+    /// take their timestamp from `provider` instead of the system clock
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let writer = RegionWriter::with_timestamp_provider(|| game_clock.unix_secs());
+    /// ```
+    pub fn with_timestamp_provider<F>(provider: F) -> RegionWriter
+    where
+        F: Fn() -> u32 + Send + Sync + 'static,
+    {
+        RegionWriter {
+            timestamps: TimestampSource::Provider(Arc::new(provider)),
+            ..Self::default()
+        }
     }
 
-    /// Writes all chunks into one region file.  
+    /// Creates a new region writer where every chunk pushed without an explicit timestamp gets `timestamp`  
+    /// Useful for reproducible output
+    pub fn with_fixed_timestamp(timestamp: u32) -> RegionWriter {
+        RegionWriter {
+            timestamps: TimestampSource::Fixed(timestamp),
+            ..Self::default()
+        }
+    }
+
+    /// Creates a new region writer for the region at `region`, which enables
+    /// [`push_chunk_absolute`](RegionWriter::push_chunk_absolute) & [`write_to_dir`](RegionWriter::write_to_dir)
     ///
     /// ## Example
     /// ```ignore
-    /// use mca::{RegionWriter};
+    /// let mut writer = RegionWriter::for_region(RegionPos::new(-1, 2));
     ///
-    /// let mut writer = RegionWriter::new();
+    /// writer.push_chunk_absolute(-20, 70, &nbt)?;
+    /// writer.write_to_dir(Path::new("world/region"))?; // world/region/r.-1.2.mca
+    /// ```
+    pub fn for_region(region: RegionPos) -> RegionWriter {
+        RegionWriter {
+            region: Some(region),
+            ..Self::default()
+        }
+    }
+
+    /// Creates a writer limited to what the `target` release can load, using its default compression.  
+    /// Pushing a chunk or changing one to a compression type it doesn't load fails with [`McaError::UnsupportedCompression`].
+    /// Targets without external chunk support make [`write_with_external`](RegionWriter::write_with_external) fail like [`write`](RegionWriter::write) does.
     ///
-    /// // Push some chunk data
-    /// // ...
+    /// ## Example
+    /// ```ignore
+    /// let mut writer = RegionWriter::for_target(TargetVersion::V1_17);
     ///
-    /// let mut buf: Vec<u8> = vec![];
-    /// writer.write(&mut buf).unwrap();
+    /// // fails, LZ4 needs 1.20.5
+    /// writer.push_chunk_with_compression(&nbt, (0, 0), CompressionType::LZ4)?;
+    /// ```
+    pub fn for_target(target: TargetVersion) -> RegionWriter {
+        RegionWriter {
+            target: Some(target),
+            default_compression: target.default_compression(),
+            ..Self::default()
+        }
+    }
+
+    /// The release this writer targets, if created with [`for_target`](RegionWriter::for_target)
+    pub fn target(&self) -> Option<TargetVersion> {
+        self.target
+    }
+
+    /// Fails if the writer's target doesn't load chunks compressed with `compression`
+    fn check_target(&self, compression: &CompressionType) -> Result<(), McaError> {
+        match self.target {
+            Some(target) if !target.allows(compression) => Err(McaError::UnsupportedCompression {
+                compression: compression.clone(),
+                required: MinVersion::for_compression_byte(compression.to_u8()),
+                target,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The region this writer represents, if created with [`for_region`](RegionWriter::for_region)
+    pub fn region(&self) -> Option<RegionPos> {
+        self.region
+    }
+
+    /// Sets the region this writer represents, like [`for_region`](RegionWriter::for_region) does  
+    /// Lets writers created otherwise, e.g. with [`for_target`](RegionWriter::for_target), name their external chunks.
+    pub fn set_region(&mut self, region: RegionPos) {
+        self.region = Some(region);
+    }
+
+    /// Sets the compression type used by [`push_chunk`](RegionWriter::push_chunk).  
+    /// Defaults to `Zlib`, which is what vanilla writes & every version can read.
+    pub fn set_default_compression(&mut self, compression: CompressionType) {
+        self.default_compression = compression;
+    }
+
+    /// Sets the compression level used for all chunks pushed after this  
+    /// Chunks with deferred compression use the level set when they're written
+    pub fn set_compression_level(&mut self, level: CompressionLevel) {
+        self.compression_level = level;
+    }
+
+    /// Sets the block size & checksum LZ4 chunks compressed after this are framed with, see [`Lz4Framing`]  
+    /// Chunks with deferred compression use the framing set when they're written.
+    /// Copied & precompressed chunks keep the framing they came with.
+    pub fn set_lz4_framing(&mut self, framing: Lz4Framing) {
+        self.scratch.lz4 = framing;
+    }
+
+    /// The framing LZ4 chunks are compressed with
+    pub fn lz4_framing(&self) -> Lz4Framing {
+        self.scratch.lz4
+    }
+
+    /// Keeps pushed chunks raw & compresses them when written instead of when pushed, defaults to false.  
+    /// Changing a chunk's codec with [`set_compression`](RegionWriter::set_compression) before writing then
+    /// only compresses it once, and with the `rayon` feature chunks are compressed in parallel while writing.
     ///
-    /// std::fs::File::write("r.0.0.mca", &buf).unwrap();
+    /// Chunks keep their raw data in memory until written, which costs more memory than compressed chunks.
+    /// The output is identical to compressing them when pushed.
+    pub fn set_deferred_compression(&mut self, deferred: bool) {
+        self.defer_compression = deferred;
+    }
+
+    /// Sets the order chunk payloads are written in  
+    /// Defaults to [`LayoutStrategy::CoordinateOrder`]
+    pub fn set_layout(&mut self, layout: LayoutStrategy) {
+        self.layout = layout;
+    }
+
+    /// When enabled, chunks with byte-identical compressed payloads share the same sectors  
+    /// Only the first one is written, the location entries of the rest point at it.
+    ///
+    /// Saves a lot of space on superflat or ocean heavy regions, off by default.
+    pub fn set_deduplicate(&mut self, deduplicate: bool) {
+        self.deduplicate = deduplicate;
+    }
+
+    /// Keeps the layout of `base` when writing, for deduplicating backups & minimal diffs  
+    ///
+    /// Chunks whose payload is identical to the one `base` has at the same coordinate keep their exact sector offset,
+    /// every other chunk goes into the first free sector run or is appended at the end.  
+    /// Everything else of `base`, sector gaps & padding included, is written back unchanged, only the header tables are rewritten.
+    ///
+    /// [`LayoutStrategy`] then only decides the order changed chunks are placed in.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let region = RegionReader::new(&data)?;
+    /// let mut writer = RegionWriter::from_region(&region)?;
+    /// writer.preserve_layout(&region);
+    ///
+    /// writer.push_chunk(&nbt, (4, 6))?;
+    /// writer.write(&mut buf)?;
     /// ```
-    pub fn write<W>(&self, w: &mut W) -> Result<(), McaError>
-    where
-        W: Write,
-    {
-        // payload prepping, needed for location header, hence it first
-        let mut chunk_offsets: HashMap<(u8, u8), usize> = HashMap::new();
-        // don't know the perf hit for this but this can for sure be removed
-        let mut chunk_map: HashMap<(u8, u8), &PendingChunk> = HashMap::new();
+    pub fn preserve_layout(&mut self, base: &RegionReader) {
+        self.base = Some(base.inner().to_vec());
+        self.in_place = false;
+    }
 
-        let mut curr_chunk_offset: usize = SECTOR_SIZE * 2; // init pos for chunks
-        let mut payloads: Vec<u8> = vec![];
+    /// Like [`preserve_layout`](RegionWriter::preserve_layout), but changed chunks keep their sector offset too
+    /// as long as they still fit the sectors `base` gave them  
+    /// Only chunks that grew past their old allocation are moved, so the location entries of everything else stay put.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let region = RegionReader::new(&data)?;
+    /// let mut writer = RegionWriter::from_region(&region)?;
+    /// writer.preserve_offsets_from(&region);
+    ///
+    /// writer.push_chunk(&nbt, (4, 6))?;
+    /// writer.write(&mut buf)?;
+    /// ```
+    pub fn preserve_offsets_from(&mut self, base: &RegionReader) {
+        self.base = Some(base.inner().to_vec());
+        self.in_place = true;
+    }
 
-        for chunk in self.chunks.iter() {
-            let len_b = (chunk.compressed_data.len() as u32 + 1).to_be_bytes(); // this little +1 accounts for the compression byte
-            let len = [len_b[0], len_b[1], len_b[2], len_b[3]];
+    /// Allocates `extra_sectors` empty sectors after the payload of the chunk at `coordinate`, 0 removes the reservation.  
+    /// The location entry counts them, so a chunk that grows a little can later be rewritten in place
+    /// with [`preserve_offsets_from`](RegionWriter::preserve_offsets_from) instead of being moved.
+    /// A payload never gets more than 255 sectors, slack included.
+    ///
+    /// The reservation belongs to the coordinate and stays when its chunk is replaced or removed.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// // spawn chunks are rewritten often, leave them room to grow
+    /// writer.reserve_chunk((0, 0), 2)?;
+    /// writer.write(&mut buf)?;
+    ///
+    /// let region = RegionReader::new(&buf)?;
+    /// let mut update = RegionWriter::from_region(&region)?;
+    /// update.preserve_offsets_from(&region);
+    /// update.reserve_chunk((0, 0), 2)?;
+    /// update.push_chunk(&grown, (0, 0))?;
+    /// ```
+    pub fn reserve_chunk(
+        &mut self,
+        coordinate: (u8, u8),
+        extra_sectors: u8,
+    ) -> Result<(), McaError> {
+        layout::check_coordinate(coordinate.0 as usize, coordinate.1 as usize)?;
+        match extra_sectors {
+            0 => self.slack.remove(&coordinate),
+            extra => self.slack.insert(coordinate, extra),
+        };
 
-            let compression = chunk.compression.to_u8();
+        Ok(())
+    }
 
-            let mut payload_len = 0;
-            payload_len += payloads.write(&len)?;
-            payload_len += payloads.write(&[compression])?;
-            payload_len += payloads.write(&chunk.compressed_data)?;
+    /// Sectors allocated for a payload of `len` bytes at `coordinate`, its reserved slack included
+    fn sectors_for(&self, coordinate: (u8, u8), len: usize) -> usize {
+        let sectors = layout::sectors_for_payload(len) as usize;
+        let slack = self.slack.get(&coordinate).copied().unwrap_or(0) as usize;
 
-            // pad the chunk so It's always in sector chunks
-            let remaining = SECTOR_SIZE - (payload_len % SECTOR_SIZE);
-            let padding = std::iter::repeat(0u8).take(remaining).collect::<Vec<u8>>();
-            payload_len += payloads.write(&padding)?;
+        (sectors + slack)
+            .min(MAX_CHUNK_SECTORS as usize)
+            .max(sectors)
+    }
 
-            chunk_offsets.insert(chunk.coordinate, curr_chunk_offset);
-            chunk_map.insert(chunk.coordinate, chunk);
+    /// Sets what happens when a chunk is pushed to an already used coordinate.  
+    /// Defaults to [`DuplicatePolicy::Replace`]
+    pub fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicate_policy = policy;
+    }
 
-            // offset it by current + how many bytes we just wrote
-            curr_chunk_offset += payload_len;
-        }
+    /// Makes [`validate`](RegionWriter::validate) report chunks with a timestamp of 0, off by default  
+    /// Writing itself is unaffected, vanilla loads those chunks fine but treats them as never saved.
+    pub fn set_forbid_zero_timestamps(&mut self, forbid: bool) {
+        self.forbid_zero_timestamps = forbid;
+    }
 
-        // location header
-        for x in 0..32 {
-            for z in 0..32 {
-                let offset = match chunk_offsets.get(&(z as u8, x as u8)) {
-                    Some(offset) => offset,
-                    None => {
-                        w.write_all(&[0, 0, 0, 0])?;
-                        continue;
-                    }
-                };
+    /// Makes writing fail with [`McaError::NotVanilla`] instead of emitting a region vanilla might choke on, off by default.  
+    /// Chunks using custom compression and payloads shared through [`set_deduplicate`](RegionWriter::set_deduplicate) are rejected,
+    /// and a [preserved](RegionWriter::preserve_layout) base region has to hold up as well:
+    /// payloads starting after the header and fitting their sectors, no overlapping sectors,
+    /// zero timestamps on empty slots and a length of whole sectors.
+    pub fn set_strict_vanilla(&mut self, strict: bool) {
+        self.strict_vanilla = strict;
+    }
 
-                // handle this unwrap but this shouldn't be possible when we have the above statement
-                let chunk = chunk_map.get(&(z as u8, x as u8)).unwrap();
+    /// Decompresses every chunk right after the writer compresses it and compares it with the raw data, off by default.  
+    /// A mismatch fails the push or write with [`McaError::VerifyFailed`] naming the chunk, before its payload is used.
+    /// Roughly doubles the time spent compressing, it's meant for catching hardware corrupting data in archival jobs.
+    /// `Uncompressed` chunks are checked the same way after being copied,
+    /// chunks pushed already compressed are taken as they are.
+    pub fn set_verify_writes(&mut self, verify: bool) {
+        self.verify_writes = verify;
+    }
 
-                let offset_bytes = {
-                    let be = ((*offset / SECTOR_SIZE) as u32).to_be_bytes();
-                    [be[1], be[2], be[3]]
-                };
+    /// Checks everything that could make the written region unloadable without writing anything  
+    /// Returns every issue found, in push order with region wide issues last.
+    ///
+    /// With [`validate_positions`](RegionWriter::validate_positions) on, every chunk is decompressed to check its NBT position too.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// if let Err(issues) = writer.validate() {
+    ///     for issue in issues {
+    ///         eprintln!("{issue:?}");
+    ///     }
+    /// }
+    /// ```
+    pub fn validate(&self) -> Result<(), Vec<WriterIssue>> {
+        let mut issues = vec![];
+        let mut seen = std::collections::HashSet::new();
 
-                w.write_all(&offset_bytes)?;
+        for chunk in self.chunks.iter() {
+            let (x, z) = chunk.coordinate;
+            if layout::check_coordinate(x as usize, z as usize).is_err() {
+                issues.push(WriterIssue::InvalidCoordinate { x, z });
+            } else if !seen.insert(chunk.coordinate) {
+                issues.push(WriterIssue::DuplicateCoordinate { x, z });
+            }
 
-                let sector_count = ((chunk.compressed_data.len() + 4 + 1) as f32
-                    / SECTOR_SIZE as f32)
-                    .ceil() as u8;
+            // the size of a deferred chunk isn't known before it's compressed
+            let sectors = layout::sectors_for_payload(chunk.payload().len());
+            if chunk.is_compressed() && sectors > MAX_CHUNK_SECTORS {
+                issues.push(WriterIssue::ChunkTooLarge {
+                    x,
+                    z,
+                    sectors: sectors as usize,
+                });
+            }
 
-                w.write_all(&[sector_count])?;
+            if self.forbid_zero_timestamps && chunk.timestamp == 0 {
+                issues.push(WriterIssue::ZeroTimestamp { x, z });
             }
+
+            issues.extend(self.position_issue(chunk));
         }
 
-        // timestamp header
-        for x in 0..32 {
-            for z in 0..32 {
-                match &self.chunks.get(x * 32 + z) {
-                    Some(chunk) => {
-                        let timestamp = {
-                            let b = chunk.timestamp.to_be_bytes();
-                            [b[0], b[1], b[2], b[3]]
-                        };
-                        w.write(&timestamp)?
-                    }
-                    None => w.write(&[0, 0, 0, 0])?,
-                };
+        // a preserved base can only grow, changed chunks may be appended past its end
+        let base = self.base.as_ref().map_or(0, Vec::len);
+        let sectors = (base + self.estimated_len()).div_ceil(SECTOR_SIZE);
+        if sectors > MAX_SECTOR_OFFSET {
+            issues.push(WriterIssue::RegionTooLarge { sectors });
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+
+    #[cfg(feature = "fastnbt")]
+    fn position_issue(&self, chunk: &PendingChunk) -> Option<WriterIssue> {
+        self.position_check?;
+
+        let (x, z) = chunk.coordinate;
+        let checked = match chunk.deferred_data() {
+            Some(raw) => self.check_position(raw, chunk.coordinate),
+            None => chunk
+                .compression
+                .decompress(chunk.payload())
+                .and_then(|raw| self.check_position(&raw, chunk.coordinate)),
+        };
+
+        match checked {
+            Ok(()) => None,
+            Err(McaError::PositionMismatch { expected, found }) => {
+                Some(WriterIssue::PositionMismatch {
+                    x,
+                    z,
+                    expected,
+                    found,
+                })
             }
+            Err(e) => Some(WriterIssue::UnreadableNbt {
+                x,
+                z,
+                reason: e.to_string(),
+            }),
         }
+    }
 
-        w.write_all(&payloads)?;
-        w.flush()?;
+    #[cfg(not(feature = "fastnbt"))]
+    fn position_issue(&self, _: &PendingChunk) -> Option<WriterIssue> {
+        None
+    }
+
+    /// Queues a chunk, following the writers [`DuplicatePolicy`].  
+    /// A replaced chunk keeps the payload position of the chunk it replaced.
+    fn insert(&mut self, chunk: PendingChunk) -> Result<(), McaError> {
+        layout::check_coordinate(chunk.coordinate.0 as usize, chunk.coordinate.1 as usize)?;
+        self.check_target(&chunk.compression)?;
+
+        let existing = self
+            .chunks
+            .iter_mut()
+            .find(|c| c.coordinate == chunk.coordinate);
+
+        match (existing, self.duplicate_policy) {
+            (None, _) => self.chunks.push(chunk),
+            (Some(existing), DuplicatePolicy::Replace) => *existing = chunk,
+            (Some(_), DuplicatePolicy::Error) => {
+                return Err(McaError::DuplicateChunk {
+                    x: chunk.coordinate.0,
+                    z: chunk.coordinate.1,
+                })
+            }
+        }
 
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::RegionReader;
+    /// Makes raw chunk pushes check the `xPos` & `zPos` in the chunk NBT against the slot it's pushed to,
+    /// vanilla silently discards chunks where they disagree  
+    /// Mismatches fail with [`McaError::PositionMismatch`], precompressed pushes are never checked.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let mut writer = RegionWriter::new();
+    /// writer.validate_positions(RegionPos::new(-1, 2));
+    ///
+    /// // errors unless the NBT says xPos -28 & zPos 70
+    /// writer.push_chunk(&nbt, (4, 6))?;
+    /// ```
+    #[cfg(feature = "fastnbt")]
+    pub fn validate_positions(&mut self, region_pos: RegionPos) {
+        self.position_check = Some(region_pos);
+    }
 
-    const REGION: &[u8] = include_bytes!("../benches/r.0.0.mca");
+    /// Turns [`validate_positions`](RegionWriter::validate_positions) off again, for intentionally relocated chunks
+    #[cfg(feature = "fastnbt")]
+    pub fn skip_position_validation(&mut self) {
+        self.position_check = None;
+    }
 
-    #[test]
-    fn round_trip() {
-        let region = RegionReader::new(REGION).unwrap();
-        let mut writer = RegionWriter::new();
+    #[cfg(feature = "fastnbt")]
+    fn check_position(&self, raw_data: &[u8], coordinate: (u8, u8)) -> Result<(), McaError> {
+        match self.position_check {
+            Some(region_pos) => {
+                crate::nbt::check_position(raw_data, region_pos.chunk_pos(coordinate))
+            }
+            None => Ok(()),
+        }
+    }
 
-        for (idx, chunk) in region.iter().enumerate() {
-            let chunk = match chunk.unwrap() {
-                Some(data) => data,
-                None => continue,
-            };
+    #[cfg(not(feature = "fastnbt"))]
+    fn check_position(&self, _: &[u8], _: (u8, u8)) -> Result<(), McaError> {
+        Ok(())
+    }
 
-            let data = chunk.decompress().unwrap();
-            writer
-                .push_chunk_with_compression(
-                    &data,
-                    ((idx % 32) as u8, (idx / 32) as u8),
-                    CompressionType::Zlib,
-                )
-                .unwrap();
-        }
+    /// Pushes a raw chunk into the writer  
+    /// Uses the writers default compression (`Zlib` unless changed), use [`push_chunk_with_compression`] for other compression types.  
+    ///
+    /// Timestamp will be current time since [`UNIX_EPOCH`] (or from the writer's timestamp provider), use [`push_chunk_with_timestamp`] to override it.  
+    pub fn push_chunk(&mut self, raw_data: &[u8], coordinate: (u8, u8)) -> Result<(), McaError> {
+        self.push_chunk_with_timestamp(
+            raw_data,
+            coordinate,
+            self.default_compression.clone(),
+            self.timestamps.now()?,
+        )
+    }
 
-        let mut buf = vec![];
-        writer.write(&mut buf).unwrap();
+    /// Pushes a raw chunk into the writer  
+    /// This specifies the compression type used  
+    pub fn push_chunk_with_compression(
+        &mut self,
+        raw_data: &[u8],
+        coordinate: (u8, u8),
+        compression_type: CompressionType,
+    ) -> Result<(), McaError> {
+        self.push_chunk_with_timestamp(
+            raw_data,
+            coordinate,
+            compression_type,
+            self.timestamps.now()?,
+        )
+    }
 
-        let new_region = RegionReader::new(&buf).unwrap();
-        let chunk = new_region.get_chunk(0, 0).unwrap().unwrap();
+    /// Pushes a raw chunk into the writer  
+    /// This specifies both the compression type and the timestamp (unix epoch seconds) stored for it.  
+    ///
+    /// The timestamp is written to the region exactly as given.
+    pub fn push_chunk_with_timestamp(
+        &mut self,
+        raw_data: &[u8],
+        coordinate: (u8, u8),
+        compression_type: CompressionType,
+        timestamp: u32,
+    ) -> Result<(), McaError> {
+        self.push_cow(
+            Cow::Borrowed(raw_data),
+            coordinate,
+            compression_type,
+            timestamp,
+        )
+    }
 
-        let data = chunk.decompress().unwrap();
-        let _ = sculk::chunk::Chunk::from_bytes(&data).unwrap();
+    /// Pushes a raw chunk at the world chunk position `world_x` `world_z`, like [`push_chunk`](RegionWriter::push_chunk)  
+    /// Fails with [`McaError::ChunkOutsideRegion`] if the chunk belongs to another region,
+    /// and [`McaError::UnknownRegion`] if the writer wasn't created with [`for_region`](RegionWriter::for_region)
+    pub fn push_chunk_absolute(
+        &mut self,
+        world_x: i32,
+        world_z: i32,
+        raw_data: &[u8],
+    ) -> Result<(), McaError> {
+        let region = self.region.ok_or(McaError::UnknownRegion)?;
+        let (owner, coordinate) = RegionPos::from_chunk(world_x, world_z);
+        if owner != region {
+            return Err(McaError::ChunkOutsideRegion {
+                chunk: (world_x, world_z),
+                region: (region.x, region.z),
+            });
+        }
+
+        self.push_chunk(raw_data, coordinate)
+    }
+
+    /// Pushes an owned raw chunk into the writer, compressed with the writers default compression  
+    /// Timestamp will be current time since [`UNIX_EPOCH`] (or from the writer's timestamp provider)
+    ///
+    /// With `Uncompressed` the buffer is moved straight into the pending chunk without copying.
+    pub fn push_chunk_owned(
+        &mut self,
+        raw_data: Vec<u8>,
+        coordinate: (u8, u8),
+    ) -> Result<(), McaError> {
+        self.push_chunk_owned_with_timestamp(
+            raw_data,
+            coordinate,
+            self.default_compression.clone(),
+            self.timestamps.now()?,
+        )
+    }
+
+    /// Same as [`push_chunk_with_timestamp`](RegionWriter::push_chunk_with_timestamp) but takes ownership of the raw data,
+    /// see [`push_chunk_owned`](RegionWriter::push_chunk_owned)
+    pub fn push_chunk_owned_with_timestamp(
+        &mut self,
+        raw_data: Vec<u8>,
+        coordinate: (u8, u8),
+        compression_type: CompressionType,
+        timestamp: u32,
+    ) -> Result<(), McaError> {
+        self.push_cow(
+            Cow::Owned(raw_data),
+            coordinate,
+            compression_type,
+            timestamp,
+        )
+    }
+
+    fn push_cow(
+        &mut self,
+        raw_data: Cow<'_, [u8]>,
+        coordinate: (u8, u8),
+        compression_type: CompressionType,
+        timestamp: u32,
+    ) -> Result<(), McaError> {
+        let chunk = self.prepare_chunk(raw_data, coordinate, compression_type, timestamp)?;
+        self.insert(chunk)
+    }
+
+    /// Same as [`prepare_chunk`](RegionWriter::prepare_chunk), timestamped by the writer's timestamp provider
+    pub(crate) fn prepare_chunk_now(
+        &mut self,
+        raw_data: &[u8],
+        coordinate: (u8, u8),
+        compression_type: CompressionType,
+    ) -> Result<PendingChunk, McaError> {
+        let timestamp = self.timestamps.now()?;
+        self.prepare_chunk(
+            Cow::Borrowed(raw_data),
+            coordinate,
+            compression_type,
+            timestamp,
+        )
+    }
+
+    /// The chunk a raw push would queue, checked & compressed with the writer's settings but not queued yet
+    fn prepare_chunk(
+        &mut self,
+        raw_data: Cow<'_, [u8]>,
+        coordinate: (u8, u8),
+        compression_type: CompressionType,
+        timestamp: u32,
+    ) -> Result<PendingChunk, McaError> {
+        self.check_position(&raw_data, coordinate)?;
+        self.check_target(&compression_type)?;
+
+        if self.defer_compression && compression_type != CompressionType::Uncompressed {
+            return PendingChunk::deferred(
+                raw_data.into_owned(),
+                compression_type,
+                timestamp,
+                coordinate,
+            );
+        }
+
+        let raw_len = raw_data.len();
+        let compressed_data = match compression_type {
+            // verified copies go through the codec too, memory can corrupt a plain copy as well
+            CompressionType::Uncompressed if !self.verify_writes => raw_data.into_owned(),
+            _ => Self::compress_raw(
+                &mut self.scratch,
+                &compression_type,
+                &raw_data,
+                self.compression_level,
+                self.verify_writes.then_some(coordinate),
+            )?,
+        };
+        Ok(
+            PendingChunk::from_compressed(compressed_data, compression_type, timestamp, coordinate)
+                .with_raw_len(raw_len),
+        )
+    }
+
+    /// Pushes many raw chunks at once, compressed with the writers default compression  
+    /// Timestamp will be current time since [`UNIX_EPOCH`] (or from the writer's timestamp provider)
+    ///
+    /// With the `rayon` feature the chunks are compressed in parallel,
+    /// the result is identical to pushing them one by one in the same order.
+    pub fn push_chunks(&mut self, chunks: &[(&[u8], (u8, u8))]) -> Result<(), McaError> {
+        self.push_chunks_with_timestamp(
+            chunks,
+            self.default_compression.clone(),
+            self.timestamps.now()?,
+        )
+    }
+
+    /// Pushes many raw chunks at once with the given compression type and timestamp  
+    /// See [`push_chunks`](RegionWriter::push_chunks)
+    pub fn push_chunks_with_timestamp(
+        &mut self,
+        chunks: &[(&[u8], (u8, u8))],
+        compression_type: CompressionType,
+        timestamp: u32,
+    ) -> Result<(), McaError> {
+        self.check_target(&compression_type)?;
+        for (raw_data, coordinate) in chunks {
+            self.check_position(raw_data, *coordinate)?;
+        }
+
+        if self.defer_compression {
+            for (raw_data, coordinate) in chunks {
+                self.push_cow(
+                    Cow::Borrowed(raw_data),
+                    *coordinate,
+                    compression_type.clone(),
+                    timestamp,
+                )?;
+            }
+            return Ok(());
+        }
+
+        let (level, verify) = (self.compression_level, self.verify_writes);
+        let compress = |scratch: &mut CompressScratch,
+                        (raw_data, coordinate): &(&[u8], (u8, u8))| {
+            let compressed_data = Self::compress_raw(
+                scratch,
+                &compression_type,
+                raw_data,
+                level,
+                verify.then_some(*coordinate),
+            )?;
+            Ok(PendingChunk::from_compressed(
+                compressed_data,
+                compression_type.clone(),
+                timestamp,
+                *coordinate,
+            )
+            .with_raw_len(raw_data.len()))
+        };
+
+        // every rayon job gets its own scratch buffers
+        #[cfg(feature = "rayon")]
+        let compressed: Result<Vec<PendingChunk>, McaError> = {
+            use rayon::prelude::*;
+            chunks
+                .par_iter()
+                .map_init(|| self.scratch.clone(), compress)
+                .collect()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let compressed: Result<Vec<PendingChunk>, McaError> = chunks
+            .iter()
+            .map(|chunk| compress(&mut self.scratch, chunk))
+            .collect();
+
+        for chunk in compressed? {
+            self.insert(chunk)?;
+        }
+
+        Ok(())
+    }
+
+    /// Pushes every raw chunk of an iterator like [`push_chunk`](RegionWriter::push_chunk) would  
+    /// Stops at the first chunk that fails, the chunks before it stay pushed.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let mut writer = RegionWriter::new();
+    /// writer.try_extend(raw.iter().map(|(coordinate, data)| (*coordinate, data.as_slice())))?;
+    /// ```
+    pub fn try_extend<'a, I>(&mut self, chunks: I) -> Result<(), McaError>
+    where
+        I: IntoIterator<Item = ((u8, u8), &'a [u8])>,
+    {
+        for (coordinate, raw_data) in chunks {
+            self.push_chunk(raw_data, coordinate)?;
+        }
+
+        Ok(())
+    }
+
+    /// Pushes already compressed chunk data into the writer, skipping compression entirely.  
+    /// The bytes must be valid for `compression_type`, they are written verbatim.
+    ///
+    /// Useful when copying chunks between regions without a decompress & recompress cycle.
+    pub fn push_precompressed_chunk(
+        &mut self,
+        compressed_data: Vec<u8>,
+        compression_type: CompressionType,
+        coordinate: (u8, u8),
+        timestamp: u32,
+    ) -> Result<(), McaError> {
+        self.insert(PendingChunk::from_compressed(
+            compressed_data,
+            compression_type,
+            timestamp,
+            coordinate,
+        ))
+    }
+
+    /// Just pushes a [`PendingChunk`] to the writer
+    pub fn push_pending_chunk(&mut self, chunk: PendingChunk) -> Result<(), McaError> {
+        self.insert(chunk)
+    }
+
+    /// Copies a chunk from another region into `dst_coord`, its compressed payload, compression & timestamp are taken verbatim  
+    /// Returns `false` if the source slot has no chunk, nothing is pushed then.
+    ///
+    /// Fails on externally stored chunks since their data lives in a separate `.mcc` file.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let source = RegionReader::new(&data)?;
+    /// let mut writer = RegionWriter::new();
+    ///
+    /// // move chunk (3, 4) over to (10, 12)
+    /// writer.copy_chunk_from(&source, (3, 4), (10, 12))?;
+    /// ```
+    pub fn copy_chunk_from(
+        &mut self,
+        src: &RegionReader,
+        src_coord: (usize, usize),
+        dst_coord: (u8, u8),
+    ) -> Result<bool, McaError> {
+        let (x, z) = src_coord;
+        layout::check_coordinate(x, z)?;
+
+        let Some(chunk) = src.get_chunk(x, z)? else {
+            return Ok(false);
+        };
+        if chunk.is_external() {
+            return Err(McaError::InvalidChunkPayload(format!(
+                "chunk {x} {z} is stored externally"
+            )));
+        }
+
+        let timestamp = src.get_chunk_timestamp(x, z);
+        self.insert(PendingChunk::from_raw_chunk(&chunk, dst_coord, timestamp))?;
+
+        Ok(true)
+    }
+
+    /// Returns true if a chunk has been pushed to the coordinate
+    pub fn contains(&self, coordinate: (u8, u8)) -> bool {
+        self.chunks.iter().any(|c| c.coordinate == coordinate)
+    }
+
+    /// The amount of chunks that will be written
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Returns true if no chunks have been pushed
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Roughly how many bytes the writer holds in memory  
+    /// Counts the pushed chunks' buffers, a [preserved layout](RegionWriter::preserve_layout)'s region
+    /// and the compression scratch space, including buffers kept by [`clear_and_reuse`](RegionWriter::clear_and_reuse).
+    ///
+    /// ## Example
+    /// ```ignore
+    /// if writer.memory_usage() > 256 * 1024 * 1024 {
+    ///     writer.write_to_path(&path)?;
+    ///     writer.clear();
+    /// }
+    /// ```
+    pub fn memory_usage(&self) -> usize {
+        self.chunks.capacity() * std::mem::size_of::<PendingChunk>()
+            + self
+                .chunks
+                .iter()
+                .map(PendingChunk::heap_size)
+                .sum::<usize>()
+            + self.base.as_ref().map_or(0, Vec::capacity)
+            + self.scratch.heap_size()
+    }
+
+    /// Iterates over the pushed chunks in push order
+    ///
+    /// ## Example
+    /// ```ignore
+    /// for chunk in writer.chunks() {
+    ///     if let Some(data) = chunk.compressed_data() {
+    ///         println!("{:?}: {} bytes", chunk.coordinate, data.len());
+    ///     }
+    /// }
+    /// ```
+    pub fn chunks(&self) -> impl Iterator<Item = &PendingChunk> {
+        self.chunks.iter()
+    }
+
+    /// Iterates over the pushed chunks in push order for adjusting them before writing  
+    /// Coordinates must stay unique & inside the region, [`compressed_data_mut`](PendingChunk::compressed_data_mut) must stay valid for `compression`.  
+    /// Deferred chunks are adjusted through [`deferred_data_mut`](PendingChunk::deferred_data_mut), they're compressed from it when written.
+    pub fn chunks_mut(&mut self) -> impl Iterator<Item = &mut PendingChunk> {
+        self.chunks.iter_mut()
+    }
+
+    /// The pushed chunk at the coordinate, if any
+    pub fn get_chunk(&self, coordinate: (u8, u8)) -> Option<&PendingChunk> {
+        self.chunks.iter().find(|c| c.coordinate == coordinate)
+    }
+
+    /// The pushed chunk at the coordinate for adjusting it before writing  
+    /// [`compressed_data_mut`](PendingChunk::compressed_data_mut) must stay valid for `compression`, use [`set_compression`](RegionWriter::set_compression) to change codecs
+    pub fn get_chunk_mut(&mut self, coordinate: (u8, u8)) -> Option<&mut PendingChunk> {
+        self.chunks.iter_mut().find(|c| c.coordinate == coordinate)
+    }
+
+    /// Sets the timestamp of the pushed chunk at the coordinate  
+    /// Returns `false` if no chunk was pushed there
+    pub fn set_timestamp(&mut self, coordinate: (u8, u8), unix_secs: u32) -> bool {
+        match self.get_chunk_mut(coordinate) {
+            Some(chunk) => {
+                chunk.timestamp = unix_secs;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets the timestamp of every pushed chunk, e.g. `0` for reproducible output
+    pub fn set_all_timestamps(&mut self, unix_secs: u32) {
+        for chunk in self.chunks.iter_mut() {
+            chunk.timestamp = unix_secs;
+        }
+    }
+
+    /// Transcodes the pushed chunk at the coordinate to `compression`, with the writer's compression level  
+    /// A chunk with deferred compression is only retagged, it's compressed once when written.  
+    /// Fails with [`McaError::NotGenerated`] if no chunk was pushed there
+    ///
+    /// ## Example
+    /// ```ignore
+    /// // store one chunk uncompressed
+    /// writer.push_chunk(&nbt, (4, 6))?;
+    /// writer.set_compression((4, 6), CompressionType::Uncompressed)?;
+    /// ```
+    pub fn set_compression(
+        &mut self,
+        coordinate: (u8, u8),
+        compression: CompressionType,
+    ) -> Result<(), McaError> {
+        self.check_target(&compression)?;
+        let level = self.compression_level;
+        let chunk = self
+            .chunks
+            .iter_mut()
+            .find(|c| c.coordinate == coordinate)
+            .ok_or(McaError::NotGenerated)?;
+        if chunk.compression == compression {
+            return Ok(());
+        }
+        if !chunk.is_compressed() {
+            chunk.retag(compression);
+            return Ok(());
+        }
+
+        let raw = chunk.compression.decompress(chunk.payload())?;
+        *chunk = PendingChunk::from_compressed(
+            Self::compress_raw(
+                &mut self.scratch,
+                &compression,
+                &raw,
+                level,
+                self.verify_writes.then_some(coordinate),
+            )?,
+            compression,
+            chunk.timestamp,
+            chunk.coordinate,
+        )
+        .with_raw_len(raw.len());
+
+        Ok(())
+    }
+
+    /// Removes the chunk at the coordinate so it won't be written, returning it if there was one
+    pub fn remove_chunk(&mut self, coordinate: (u8, u8)) -> Option<PendingChunk> {
+        let pos = self
+            .chunks
+            .iter()
+            .position(|c| c.coordinate == coordinate)?;
+
+        Some(self.chunks.remove(pos))
+    }
+
+    /// Keeps only the chunks `f` returns true for, the rest won't be written  
+    /// `f` gets the coordinate & the pending chunk, its stored timestamp included
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let mut writer = RegionWriter::from_region(&region)?;
+    /// writer.retain(|(x, _), _| x < 16);
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut((u8, u8), &PendingChunk) -> bool,
+    {
+        self.chunks.retain(|c| f(c.coordinate, c));
+    }
+
+    /// Removes every chunk with a timestamp before `unix_secs`
+    ///
+    /// ## Example
+    /// ```ignore
+    /// // prune chunks not saved since 2024
+    /// let mut writer = RegionWriter::from_region(&region)?;
+    /// writer.trim_older_than(1704067200);
+    /// writer.write(&mut buf)?;
+    /// ```
+    pub fn trim_older_than(&mut self, unix_secs: u32) {
+        self.retain(|_, c| c.timestamp >= unix_secs);
+    }
+
+    /// Removes all pushed chunks
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    /// Removes all pushed chunks & any [preserved layout](RegionWriter::preserve_layout), ready to write another region.  
+    /// Unlike [`clear`](RegionWriter::clear) the chunks' data buffers are kept and reused for chunks compressed later,
+    /// so one writer can convert a whole world without allocating per region. Every other setting is kept as well.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let mut writer = RegionWriter::new();
+    /// let mut buf = vec![];
+    ///
+    /// for (pos, chunks) in regions {
+    ///     writer.clear_and_reuse();
+    ///     writer.push_chunks(&chunks)?;
+    ///
+    ///     buf.clear();
+    ///     writer.write(&mut buf)?;
+    ///     std::fs::write(pos.file_name(), &buf)?;
+    /// }
+    /// ```
+    pub fn clear_and_reuse(&mut self) {
+        self.base = None;
+        self.in_place = false;
+        for chunk in self.chunks.drain(..) {
+            for buffer in chunk.into_buffers() {
+                self.scratch.recycle(buffer);
+            }
+        }
+    }
+
+    /// Writes all chunks into one region file.  
+    /// Fails with [`McaError::ChunkTooLarge`] before writing anything if a chunk needs more than 255 sectors.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// use mca::{RegionWriter};
+    ///
+    /// let mut writer = RegionWriter::new();
+    ///
+    /// // Push some chunk data
+    /// // ...
+    ///
+    /// let mut buf: Vec<u8> = vec![];
+    /// writer.write(&mut buf).unwrap();
+    ///
+    /// std::fs::File::write("r.0.0.mca", &buf).unwrap();
+    /// ```
+    pub fn write<W>(&self, w: &mut W) -> Result<WriteSummary, McaError>
+    where
+        W: Write,
+    {
+        self.write_inner(w, None, None)
+    }
+
+    /// Writes all chunks into the start of `buf`, returning how many bytes were used.  
+    /// Fails with [`McaError::BufferTooSmall`] before writing anything if the region doesn't fit.
+    ///
+    /// Nothing is allocated, unless the writer deduplicates, holds chunks with deferred compression,
+    /// preserves a layout or is [strict](RegionWriter::set_strict_vanilla), those are planned like [`write`](RegionWriter::write) does.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let mut buf = [0; 1 << 20];
+    /// let len = writer.write_into(&mut buf)?;
+    ///
+    /// std::fs::write("r.0.0.mca", &buf[..len])?;
+    /// ```
+    pub fn write_into(&self, buf: &mut [u8]) -> Result<usize, McaError> {
+        let provided = buf.len();
+        let too_small = |needed: usize| McaError::BufferTooSmall { needed, provided };
+
+        if self.base.is_some()
+            || self.deduplicate
+            || self.strict_vanilla
+            || !self.chunks.iter().all(PendingChunk::is_compressed)
+        {
+            let compressed = self.compress_deferred()?;
+            if let Some(base) = &self.base {
+                let (region, _) = self.write_preserved(&compressed, base, None)?;
+                buf.get_mut(..region.len())
+                    .ok_or_else(|| too_small(region.len()))?
+                    .copy_from_slice(&region);
+                return Ok(region.len());
+            }
+
+            let header = self.plan_payloads(&compressed, None)?;
+            let needed = header.summary.bytes_written;
+            let mut w = buf.get_mut(..needed).ok_or_else(|| too_small(needed))?;
+            header.write(&mut w)?;
+            for (compression, data, sectors) in header.payloads.iter() {
+                write_reserved(&mut w, *compression, data, *sectors)?;
+            }
+
+            return Ok(needed);
+        }
+
+        self.check_coordinates()?;
+        let needed = self.estimated_len();
+        if provided < needed {
+            return Err(too_small(needed));
+        }
+
+        // the layout order is sorted on the stack, unique coordinates cap it at a full region
+        let mut header = Header::new();
+        if let Some(first) = self.chunks.first() {
+            let mut order = [first; CHUNK_COUNT];
+            // never cut short, `check_coordinates` caps the chunks at a full region
+            let order = order
+                .get_mut(..self.chunks.len().min(CHUNK_COUNT))
+                .unwrap_or_default();
+            for (slot, chunk) in order.iter_mut().zip(self.chunks.iter()) {
+                *slot = chunk;
+            }
+            self.sort_for_layout(order);
+
+            let mut offset = HEADER_SIZE;
+            for chunk in order.iter() {
+                let (compression, data) = Self::payload_for(chunk, None)?;
+                let sector_count = self.sectors_for(chunk.coordinate, data.len()) as u8;
+                let mut w = buf.get_mut(offset..).ok_or_else(|| too_small(needed))?;
+                write_reserved(&mut w, compression, data, sector_count as usize)?;
+
+                header.place(chunk, RegionWriter::location_entry(offset, sector_count)?);
+                offset += sector_count as usize * SECTOR_SIZE;
+            }
+        }
+        header.write(
+            &mut buf
+                .get_mut(..HEADER_SIZE)
+                .ok_or_else(|| too_small(needed))?,
+        )?;
+
+        Ok(needed)
+    }
+
+    /// Writes all chunks into a new buffer, allocated once at the exact size of the region.  
+    /// With [`preserve_layout`](RegionWriter::preserve_layout) the patched copy of the base region is returned as is.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let data = writer.into_bytes()?;
+    /// std::fs::write("r.0.0.mca", &data)?;
+    /// ```
+    pub fn into_bytes(self) -> Result<Vec<u8>, McaError> {
+        let compressed = self.compress_deferred()?;
+        if let Some(base) = &self.base {
+            let (region, _) = self.write_preserved(&compressed, base, None)?;
+            return Ok(region);
+        }
+
+        let header = self.plan_payloads(&compressed, None)?;
+        let mut buf = Vec::with_capacity(header.summary.bytes_written);
+        header.write(&mut buf)?;
+        for (compression, data, sectors) in header.payloads.iter() {
+            write_reserved(&mut buf, *compression, data, *sectors)?;
+        }
+
+        Ok(buf)
+    }
+
+    /// Writes all chunks into one region file, like [`write`](RegionWriter::write),
+    /// but chunks needing more than 255 sectors are stored externally the way vanilla does it.  
+    /// The region gets a 1 sector stub with [`EXTERNAL_FLAG`] set on its compression byte,
+    /// and the chunk data is returned as its [`MccFile`] for the caller to write next to the region.  
+    /// Fails with [`McaError::UnknownRegion`] if the writer wasn't created with [`for_region`](RegionWriter::for_region),
+    /// the `.mcc` file names depend on the region's position.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let mut writer = RegionWriter::for_region(RegionPos::new(-1, 2));
+    /// // ...
+    ///
+    /// let mut buf = vec![];
+    /// for mcc in writer.write_with_external(&mut buf)? {
+    ///     mcc.write_to_dir(Path::new("world/region"))?;
+    /// }
+    /// ```
+    pub fn write_with_external<W>(&self, w: &mut W) -> Result<Vec<MccFile>, McaError>
+    where
+        W: Write,
+    {
+        let region = self.region.ok_or(McaError::UnknownRegion)?;
+        let mut external = vec![];
+        let allowed = self.target.is_none_or(|t| t.allows_external());
+        let _ = self.write_inner(w, allowed.then_some(&mut external), None)?;
+
+        Ok(external.iter().map(|c| c.to_mcc(region)).collect())
+    }
+
+    /// Writes all chunks like [`write`](RegionWriter::write), also returning a [`ChunkWriteRecord`] for every chunk in header order
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let (_, records) = writer.write_with_report(&mut buf)?;
+    ///
+    /// for record in records.iter().filter(|r| r.sector_count > 64) {
+    ///     println!("{:?} takes {} sectors", record.coordinate, record.sector_count);
+    /// }
+    /// ```
+    pub fn write_with_report<W>(
+        &self,
+        w: &mut W,
+    ) -> Result<(WriteSummary, Vec<ChunkWriteRecord>), McaError>
+    where
+        W: Write,
+    {
+        let mut report = vec![];
+        let summary = self.write_inner(w, None, Some(&mut report))?;
+
+        Ok((summary, report))
+    }
+
+    /// Writes all chunks into one region file, streaming payloads straight into a seekable sink.  
+    /// The header is reserved first and only filled in once every payload is written, by seeking back to it.
+    /// A write cut off midway leaves an empty header behind, never one pointing at missing payloads.
+    ///
+    /// Writing starts at the sink's current position and the output is identical to [`write`](RegionWriter::write).  
+    /// Returns the same [`WriteSummary`] as well, the sink is left positioned after the last payload.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let mut file = std::fs::File::create("r.0.0.mca")?;
+    /// writer.write_seek(&mut file)?;
+    /// ```
+    pub fn write_seek<W>(&self, w: &mut W) -> Result<WriteSummary, McaError>
+    where
+        W: Write + Seek,
+    {
+        let start = w.stream_position()?;
+        w.write_all(&ZERO_SECTOR)?;
+        w.write_all(&ZERO_SECTOR)?;
+
+        let compressed = self.compress_deferred()?;
+        let mut header_bytes = [0; HEADER_SIZE];
+        let summary = match &self.base {
+            Some(base) => {
+                let (region, header) = self.write_preserved(&compressed, base, None)?;
+                let (head, payloads) = region
+                    .split_at_checked(HEADER_SIZE)
+                    .ok_or(McaError::MissingHeader)?;
+                w.write_all(payloads)?;
+                header_bytes.copy_from_slice(head);
+                header.summary
+            }
+            None => {
+                let header = self.plan_payloads(&compressed, None)?;
+                for (compression, data, sectors) in header.payloads.iter() {
+                    write_reserved(w, *compression, data, *sectors)?;
+                }
+                header.write(&mut &mut header_bytes[..])?;
+                header.summary
+            }
+        };
+
+        let end = w.stream_position()?;
+        w.seek(SeekFrom::Start(start))?;
+        w.write_all(&header_bytes)?;
+        w.seek(SeekFrom::Start(end))?;
+        w.flush()?;
+
+        Ok(summary)
+    }
+
+    /// Writes a region generated slot by slot, calling `generate` for every coordinate in header order.  
+    /// Each chunk is compressed & written before the next one is generated, so only one is ever held in memory.
+    /// The sink has to be seekable, the header is only known once every chunk is written.
+    ///
+    /// `None` leaves the slot empty. An error from `generate` aborts the write,
+    /// it's returned as [`McaError::GenerateFailed`] naming the coordinate.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let mut file = std::fs::File::create("r.0.0.mca")?;
+    /// RegionWriter::write_with(&mut file, |x, z| {
+    ///     let nbt = generator.chunk(x, z)?;
+    ///     Ok(Some(ChunkSource::new(nbt)?))
+    /// })?;
+    /// ```
+    pub fn write_with<W, F>(w: &mut W, mut generate: F) -> Result<WriteSummary, McaError>
+    where
+        W: Write + Seek,
+        F: FnMut(u8, u8) -> Result<Option<ChunkSource>, McaError>,
+    {
+        let mut stream = RegionStreamWriter::new(w)?;
+        let mut summary = WriteSummary {
+            bytes_written: HEADER_SIZE,
+            ..WriteSummary::default()
+        };
+
+        for index in 0..CHUNK_COUNT {
+            let (x, z) = ((index % 32) as u8, (index / 32) as u8);
+            let source = generate(x, z).map_err(|e| McaError::GenerateFailed {
+                x,
+                z,
+                source: Box::new(e),
+            })?;
+            let Some(source) = source else {
+                continue;
+            };
+
+            let chunk =
+                PendingChunk::new(&source.data, source.compression, source.timestamp, (x, z))?;
+            stream.push_pending_chunk(&chunk)?;
+
+            let sectors = layout::sectors_for_payload(chunk.payload().len()) as usize;
+            summary.chunks_written += 1;
+            summary.sectors_used += sectors;
+            summary.padding_bytes += layout::padding_for(chunk.payload().len());
+            summary.bytes_written += sectors * SECTOR_SIZE;
+        }
+        stream.finish()?;
+
+        Ok(summary)
+    }
+
+    /// Writes the region to `path` without ever leaving a half written file there  
+    ///
+    /// The region is written to a temporary file next to `path` which is then renamed over it,
+    /// so the destination is either the old or the new complete file.  
+    /// Permissions of an existing destination are kept, the temporary file is removed on error.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// writer.write_to_path(Path::new("world/region/r.0.0.mca"))?;
+    /// ```
+    pub fn write_to_path(&self, path: &Path) -> Result<(), McaError> {
+        #[cfg(feature = "fs-lock")]
+        let _lock = RegionLock::lock(path)?;
+        write_atomic(path, |w| self.write(w).map(|_| ()))
+    }
+
+    /// Same as [`write_to_path`](RegionWriter::write_to_path) but creates any missing parent directories first
+    pub fn write_to_path_all(&self, path: &Path) -> Result<(), McaError> {
+        create_parent_dirs(path)?;
+        #[cfg(feature = "fs-lock")]
+        let _lock = RegionLock::lock(path)?;
+        write_atomic(path, |w| self.write(w).map(|_| ()))
+    }
+
+    /// Same as [`write_to_path`](RegionWriter::write_to_path) but fails with [`McaError::Locked`]
+    /// instead of waiting when another writer holds the region's [`RegionLock`]
+    #[cfg(feature = "fs-lock")]
+    pub fn try_write_to_path(&self, path: &Path) -> Result<(), McaError> {
+        let _lock = RegionLock::try_lock(path)?;
+        write_atomic(path, |w| self.write(w).map(|_| ()))
+    }
+
+    /// Writes the region to its `r.x.z.mca` file inside `dir` the same way as [`write_to_path_all`](RegionWriter::write_to_path_all)  
+    /// Returns the path written to, fails with [`McaError::UnknownRegion`] if the writer wasn't created with [`for_region`](RegionWriter::for_region)
+    pub fn write_to_dir(&self, dir: &Path) -> Result<PathBuf, McaError> {
+        let region = self.region.ok_or(McaError::UnknownRegion)?;
+        let path = dir.join(region.file_name());
+        self.write_to_path_all(&path)?;
+
+        Ok(path)
+    }
+
+    /// Computes where [`write`](RegionWriter::write) will place every chunk without writing anything  
+    /// Fails like `write` does, with [`McaError::ChunkTooLarge`] if a chunk needs more than 255 sectors.  
+    /// Pass the layout to [`write_with_layout`](RegionWriter::write_with_layout) to write exactly what was planned.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let layout = writer.layout()?;
+    /// file.set_len(layout.file_len() as u64)?;
+    ///
+    /// for ((x, z), placement) in layout.iter() {
+    ///     println!("{x} {z} at sector {}", placement.sector_offset);
+    /// }
+    /// writer.write_with_layout(&layout, &mut file)?;
+    /// ```
+    pub fn layout(&self) -> Result<Layout, McaError> {
+        let compressed = self.compress_deferred()?;
+        if let Some(base) = &self.base {
+            let (region, _) = self.write_preserved(&compressed, base, None)?;
+            return Ok(Layout::from_locations(
+                (0..CHUNK_COUNT).map(|i| layout::bytes_at(&region, i * 4).unwrap_or_default()),
+                region.len(),
+            ));
+        }
+
+        let header = self.plan_payloads(&compressed, None)?;
+        Ok(Layout::from_locations(
+            header.locations.into_iter(),
+            header.summary.bytes_written,
+        ))
+    }
+
+    /// Writes all chunks at the placements of a [`Layout`], usually one from [`layout`](RegionWriter::layout)
+    /// computed earlier, without planning the region again. Sectors no chunk is placed in are zeroed.
+    ///
+    /// Fails with [`McaError::LayoutMismatch`] before writing anything if a chunk has no placement,
+    /// doesn't fit its placement or a placement has no chunk, and with [`McaError::InvalidAllocation`]
+    /// if placements overlap, reach into the header or past the layout's file length.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let layout = writer.layout()?;
+    /// file.set_len(layout.file_len() as u64)?;
+    ///
+    /// writer.write_with_layout(&layout, &mut BufWriter::new(&file))?;
+    /// ```
+    pub fn write_with_layout<W>(&self, layout: &Layout, w: &mut W) -> Result<WriteSummary, McaError>
+    where
+        W: Write,
+    {
+        let compressed = self.compress_deferred()?;
+        let mismatch = |(x, z): (u8, u8)| McaError::LayoutMismatch { x, z };
+
+        let mut header = Header::new();
+        for chunk in self.ordered_chunks(&compressed) {
+            let (_, data) = Self::payload_for(chunk, None)?;
+            let (x, z) = chunk.coordinate;
+            let placement = layout
+                .get(x as usize, z as usize)
+                .filter(|p| layout::sectors_for_payload(data.len()) as usize <= p.sector_count)
+                .ok_or_else(|| mismatch(chunk.coordinate))?;
+
+            header.place(chunk, placement.location_entry());
+            header.summary.chunks_written += 1;
+        }
+        if let Some(((x, z), _)) = layout
+            .iter()
+            .find(|((x, z), _)| header.chunk(*x, *z).is_none())
+        {
+            return Err(mismatch((x as u8, z as u8)));
+        }
+
+        // payloads in file order, deduplicated chunks share one
+        let mut payloads = header
+            .chunks
+            .iter()
+            .zip(header.locations.iter())
+            .filter_map(|(chunk, entry)| Some((layout::decode_location(*entry), (*chunk)?)))
+            .collect::<Vec<_>>();
+        payloads.sort_unstable_by_key(|((sector, _), _)| *sector);
+        payloads.dedup_by(|(a, a_chunk), (b, b_chunk)| {
+            a == b && a_chunk.payload() == b_chunk.payload()
+        });
+
+        let sectors = layout.file_len().div_ceil(SECTOR_SIZE);
+        let mut end = HEADER_SIZE / SECTOR_SIZE;
+        for ((start, count), _) in payloads.iter() {
+            if *start < end || start + count > sectors {
+                return Err(McaError::InvalidAllocation {
+                    start: *start,
+                    count: *count,
+                });
+            }
+            end = start + count;
+        }
+
+        header.write(w)?;
+        let mut written = HEADER_SIZE / SECTOR_SIZE;
+        for ((start, count), chunk) in payloads.iter() {
+            for _ in written..*start {
+                w.write_all(&ZERO_SECTOR)?;
+            }
+            let (compression, data) = Self::payload_for(chunk, None)?;
+            write_reserved(w, compression, data, *count)?;
+
+            header.summary.sectors_used += count;
+            header.summary.padding_bytes +=
+                count * SECTOR_SIZE - data.len() - layout::PAYLOAD_HEADER_SIZE;
+            written = start + count;
+        }
+        for _ in written..sectors {
+            w.write_all(&ZERO_SECTOR)?;
+        }
+        w.flush()?;
+
+        header.summary.bytes_written = sectors * SECTOR_SIZE;
+        Ok(header.summary)
+    }
+
+    /// The exact size in bytes [`write`](RegionWriter::write) will produce for the current chunks  
+    /// Chunks too large for the region count as the 1 sector stub [`write_with_external`](RegionWriter::write_with_external) leaves behind.  
+    /// Doesn't account for [`preserve_layout`](RegionWriter::preserve_layout), sector gaps kept from the base region aren't known up front.  
+    /// Chunks with deferred compression count with their raw size, an upper bound rather than the exact size.
+    pub fn estimated_len(&self) -> usize {
+        let mut seen = std::collections::HashSet::new();
+        let sectors = self
+            .chunks
+            .iter()
+            .map(|c| (c, c.payload()))
+            .filter(|(c, data)| !self.deduplicate || seen.insert((c.compression.to_u8(), *data)))
+            .map(|(c, data)| match layout::sectors_for_payload(data.len()) {
+                s if s > MAX_CHUNK_SECTORS => self.sectors_for(c.coordinate, 0),
+                _ => self.sectors_for(c.coordinate, data.len()),
+            })
+            .sum::<usize>();
+
+        HEADER_SIZE + sectors * SECTOR_SIZE
+    }
+
+    fn write_inner<W>(
+        &self,
+        w: &mut W,
+        external: Option<&mut Vec<ExternalChunk>>,
+        report: Option<&mut Vec<ChunkWriteRecord>>,
+    ) -> Result<WriteSummary, McaError>
+    where
+        W: Write,
+    {
+        let compressed = self.compress_deferred()?;
+        if let Some(base) = &self.base {
+            let (region, header) = self.write_preserved(&compressed, base, external)?;
+            w.write_all(&region)?;
+            w.flush()?;
+
+            if let Some(report) = report {
+                *report = header.records();
+            }
+            return Ok(header.summary);
+        }
+
+        // the layout is known up front, so payloads stream straight after the header
+        let header = self.plan_payloads(&compressed, external)?;
+
+        header.write(w)?;
+        for (compression, data, sectors) in header.payloads.iter() {
+            write_reserved(w, *compression, data, *sectors)?;
+        }
+        w.flush()?;
+
+        if let Some(report) = report {
+            *report = header.records();
+        }
+        Ok(header.summary)
+    }
+
+    /// Fails on the first chunk a write can't place, the coordinate & duplicate checks of [`validate`](RegionWriter::validate)  
+    /// Pushes already check these, [`chunks_mut`](RegionWriter::chunks_mut) can break them afterwards.
+    fn check_coordinates(&self) -> Result<(), McaError> {
+        let mut seen = [false; CHUNK_COUNT];
+        for chunk in self.chunks.iter() {
+            let (x, z) = chunk.coordinate;
+            layout::check_coordinate(x as usize, z as usize)?;
+
+            match seen.get_mut(Header::index(chunk.coordinate)) {
+                Some(true) => return Err(McaError::DuplicateChunk { x, z }),
+                Some(slot) => *slot = true,
+                None => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compressed copies of the chunks whose compression is deferred, indexed like `self.chunks`  
+    /// Empty when no chunk is deferred, so nothing gets allocated then.  
+    /// Every write goes through here, so it also runs [`check_coordinates`](RegionWriter::check_coordinates).
+    fn compress_deferred(&self) -> Result<Vec<Option<PendingChunk>>, McaError> {
+        self.check_coordinates()?;
+        if self.chunks.iter().all(PendingChunk::is_compressed) {
+            return Ok(vec![]);
+        }
+
+        let compress = |scratch: &mut CompressScratch, chunk: &PendingChunk| {
+            chunk.compressed(scratch, self.compression_level, self.verify_writes)
+        };
+
+        // every rayon job gets its own scratch buffers
+        #[cfg(feature = "rayon")]
+        let compressed = {
+            use rayon::prelude::*;
+            self.chunks
+                .par_iter()
+                .map_init(|| self.scratch.clone(), compress)
+                .collect()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let compressed = {
+            let mut scratch = self.scratch.clone();
+            self.chunks
+                .iter()
+                .map(|chunk| compress(&mut scratch, chunk))
+                .collect()
+        };
+
+        compressed
+    }
+
+    /// Compresses `raw`, checking the result decompresses back to it when a `verify` coordinate is given
+    fn compress_raw(
+        scratch: &mut CompressScratch,
+        compression: &CompressionType,
+        raw: &[u8],
+        level: CompressionLevel,
+        verify: Option<(u8, u8)>,
+    ) -> Result<Vec<u8>, McaError> {
+        match verify {
+            Some(coordinate) => scratch.compress_verified(compression, raw, level, coordinate),
+            None => scratch.compress(compression, raw, level),
+        }
+    }
+
+    /// The pending chunks in the order the layout strategy writes them  
+    /// Deferred chunks are swapped for their copy in `compressed`, see [`compress_deferred`](RegionWriter::compress_deferred)
+    fn ordered_chunks<'a>(
+        &'a self,
+        compressed: &'a [Option<PendingChunk>],
+    ) -> Vec<&'a PendingChunk> {
+        let mut chunks = self
+            .chunks
+            .iter()
+            .enumerate()
+            .map(|(i, c)| compressed.get(i).and_then(Option::as_ref).unwrap_or(c))
+            .collect::<Vec<_>>();
+        self.sort_for_layout(&mut chunks);
+
+        chunks
+    }
+
+    /// Sorts chunks in place into the order the layout strategy writes them  
+    /// Coordinates are unique so every key is too, an unstable sort gives the same order without a sort buffer.
+    fn sort_for_layout(&self, chunks: &mut [&PendingChunk]) {
+        match self.layout {
+            LayoutStrategy::CoordinateOrder => {
+                chunks.sort_unstable_by_key(|c| (c.coordinate.1, c.coordinate.0))
+            }
+            LayoutStrategy::InsertionOrder => {}
+            LayoutStrategy::SizeDescending => chunks.sort_unstable_by_key(|c| {
+                (
+                    std::cmp::Reverse(c.payload().len()),
+                    c.coordinate.1,
+                    c.coordinate.0,
+                )
+            }),
+        }
+    }
+
+    /// The compression byte & data written for a chunk  
+    /// Chunks too large for the region become an external stub when `external` is given, an error otherwise
+    fn payload_for<'c>(
+        chunk: &'c PendingChunk,
+        external: Option<&mut Vec<ExternalChunk>>,
+    ) -> Result<(u8, &'c [u8]), McaError> {
+        let data = chunk.payload();
+        if layout::sectors_for_payload(data.len()) <= MAX_CHUNK_SECTORS {
+            return Ok((chunk.compression.to_u8(), data));
+        }
+
+        match external {
+            Some(external) => {
+                external.push(ExternalChunk {
+                    coordinate: chunk.coordinate,
+                    compression: chunk.compression.clone(),
+                    data: chunk.payload().to_vec(),
+                });
+
+                Ok((chunk.compression.to_u8() | EXTERNAL_FLAG, &[]))
+            }
+            None => Err(McaError::ChunkTooLarge {
+                x: chunk.coordinate.0,
+                z: chunk.coordinate.1,
+                bytes: data.len(),
+            }),
+        }
+    }
+
+    /// Writes the whole region on top of a copy of `base`, see [`preserve_layout`](RegionWriter::preserve_layout)
+    fn write_preserved<'a>(
+        &'a self,
+        compressed: &'a [Option<PendingChunk>],
+        base: &[u8],
+        mut external: Option<&mut Vec<ExternalChunk>>,
+    ) -> Result<(Vec<u8>, Header<'a>), McaError> {
+        let region = RegionReader::new(base)?;
+        let mut out = base.to_vec();
+        let mut sectors = SectorMap::new(out.len());
+        let mut header = Header::new();
+        let mut changed = vec![];
+        let mut shared = HashMap::new();
+
+        // unchanged chunks claim their old sectors first
+        for chunk in self.ordered_chunks(compressed) {
+            let (x, z) = (chunk.coordinate.0 as usize, chunk.coordinate.1 as usize);
+            let entry = layout::bytes_at(base, layout::header_offset_for(x, z)).unwrap_or_default();
+            let (sector, count) = layout::decode_location(entry);
+            // a corrupt location can claim fewer sectors than the payload spans
+            let unchanged = matches!(
+                region.get_chunk(x, z),
+                Ok(Some(raw)) if !raw.is_external()
+                    && raw.get_compression_type() == chunk.compression
+                    && raw.raw_data == chunk.payload()
+                    && layout::sectors_for_payload(raw.raw_data.len()) as usize <= count
+            );
+            if !unchanged {
+                changed.push(chunk);
+                continue;
+            }
+
+            sectors.mark(sector, count, true);
+            if self.deduplicate {
+                shared.insert((chunk.compression.to_u8(), chunk.payload()), entry);
+            }
+
+            header.place(chunk, entry);
+            header.summary.chunks_written += 1;
+            header.summary.sectors_used += count;
+            header.summary.padding_bytes += count * SECTOR_SIZE - chunk.payload().len() - 5;
+        }
+
+        // changed chunks claim their old sectors when they still fit, before anything gets allocated
+        let mut placed = Vec::with_capacity(changed.len());
+        for chunk in changed {
+            let (compression, data) = Self::payload_for(chunk, external.as_deref_mut())?;
+            let needed = layout::sectors_for_payload(data.len()) as usize;
+            let count = self.sectors_for(chunk.coordinate, data.len());
+            let offset =
+                layout::header_offset_for(chunk.coordinate.0 as usize, chunk.coordinate.1 as usize);
+            let (old_sector, old_count) =
+                layout::decode_location(layout::bytes_at(base, offset).unwrap_or_default());
+
+            // slack that no longer fits the old sectors is given up rather than moving the chunk
+            let count = count.min(old_count);
+            // a corrupt location far past the end would blow the region up to gigabytes
+            let in_place = self.in_place
+                && old_sector >= HEADER_SIZE / SECTOR_SIZE
+                && old_sector * SECTOR_SIZE < base.len()
+                && needed <= old_count
+                && sectors.is_free(old_sector, count);
+            if in_place {
+                sectors.mark(old_sector, count, true);
+            }
+            placed.push((
+                chunk,
+                compression,
+                data,
+                in_place.then_some((old_sector, count)),
+            ));
+        }
+
+        let mut payload = vec![];
+        for (chunk, compression, data, old_place) in placed {
+            if old_place.is_none() {
+                if let Some(entry) = self.shared_entry(&mut shared, compression, data) {
+                    header.place(chunk, entry);
+                    header.summary.chunks_written += 1;
+                    continue;
+                }
+            }
+
+            let (sector, count) = old_place.unwrap_or_else(|| {
+                let count = self.sectors_for(chunk.coordinate, data.len());
+                (sectors.allocate(count), count)
+            });
+            let entry = RegionWriter::location_entry(sector * SECTOR_SIZE, count as u8)?;
+            sectors.mark(sector, count, true);
+
+            payload.clear();
+            write_reserved(&mut payload, compression, data, count)?;
+            let start = sector * SECTOR_SIZE;
+            if out.len() < start + payload.len() {
+                out.resize(start + payload.len(), 0);
+            }
+            if let Some(sectors) = out.get_mut(start..start + payload.len()) {
+                sectors.copy_from_slice(&payload);
+            }
+            if self.deduplicate {
+                shared.insert((compression, data), entry);
+            }
+
+            header.place(chunk, entry);
+            header.summary.chunks_written += 1;
+            header.summary.sectors_used += count;
+            header.summary.padding_bytes += count * SECTOR_SIZE - data.len() - 5;
+        }
+
+        header.write(&mut out.get_mut(..HEADER_SIZE).ok_or(McaError::MissingHeader)?)?;
+        header.summary.bytes_written = out.len();
+
+        // the base region's own layout ends up in the output as well, so all of it is checked
+        if self.strict_vanilla {
+            if let Some(issue) = compat::conformance_issue(&out) {
+                return Err(McaError::NotVanilla(issue));
+            }
+        }
+
+        Ok((out, header))
+    }
+
+    /// The location entry of an already written identical payload, when deduplicating
+    fn shared_entry(
+        &self,
+        shared: &mut HashMap<(u8, &[u8]), [u8; 4]>,
+        compression: u8,
+        data: &[u8],
+    ) -> Option<[u8; 4]> {
+        if !self.deduplicate {
+            return None;
+        }
+        shared.get(&(compression, data)).copied()
+    }
+
+    /// Places every chunk payload in layout order without writing anything.  
+    /// Returns the header describing where each one goes, holding the payloads to write in file order.
+    fn plan_payloads<'a>(
+        &'a self,
+        compressed: &'a [Option<PendingChunk>],
+        mut external: Option<&mut Vec<ExternalChunk>>,
+    ) -> Result<Header<'a>, McaError> {
+        let mut header = Header::new();
+        header.payloads.reserve(self.chunks.len());
+        let mut curr_chunk_offset: usize = HEADER_SIZE; // init pos for chunks
+        let mut shared = HashMap::new();
+
+        for chunk in self.ordered_chunks(compressed) {
+            let (compression, data) = Self::payload_for(chunk, external.as_deref_mut())?;
+
+            if let Some(entry) = self.shared_entry(&mut shared, compression, data) {
+                header.place(chunk, entry);
+                header.summary.chunks_written += 1;
+                continue;
+            }
+
+            let sector_count = self.sectors_for(chunk.coordinate, data.len()) as u8;
+            let entry = RegionWriter::location_entry(curr_chunk_offset, sector_count)?;
+            if self.deduplicate {
+                shared.insert((compression, data), entry);
+            }
+            header.place(chunk, entry);
+            header.summary.chunks_written += 1;
+            header.summary.sectors_used += sector_count as usize;
+            header.summary.padding_bytes += sector_count as usize * SECTOR_SIZE - data.len() - 5;
+            header.summary.bytes_written += sector_count as usize * SECTOR_SIZE;
+            header
+                .payloads
+                .push((compression, data, sector_count as usize));
+
+            // offset it by current + how many bytes this payload takes
+            curr_chunk_offset += sector_count as usize * SECTOR_SIZE;
+        }
+
+        if self.strict_vanilla {
+            if let Some(issue) = header.vanilla_issue() {
+                return Err(McaError::NotVanilla(issue));
+            }
+        }
+
+        Ok(header)
+    }
+}
+
+/// Chunks are queued following the writers [`DuplicatePolicy`],  
+/// ones the writer rejects (out of range, or a duplicate with [`DuplicatePolicy::Error`]) are skipped.  
+/// Use [`RegionWriter::push_pending_chunk`] to see why a chunk was rejected.
+impl Extend<PendingChunk> for RegionWriter {
+    fn extend<I: IntoIterator<Item = PendingChunk>>(&mut self, chunks: I) {
+        for chunk in chunks {
+            let _ = self.insert(chunk);
+        }
+    }
+}
+
+impl FromIterator<PendingChunk> for RegionWriter {
+    fn from_iter<I: IntoIterator<Item = PendingChunk>>(chunks: I) -> Self {
+        let mut writer = RegionWriter::new();
+        writer.extend(chunks);
+        writer
+    }
+}
+
+// keeps temporary names unique between writes in the same process
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+fn temp_path_for(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let count = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    path.with_file_name(format!(".{name}.{}.{count}.tmp", std::process::id()))
+}
+
+// padding is always less than a sector, so it's sliced from here instead of allocated
+static ZERO_SECTOR: [u8; SECTOR_SIZE] = [0; SECTOR_SIZE];
+
+fn create_parent_dirs(path: &Path) -> Result<(), McaError> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+
+    Ok(())
+}
+
+/// Runs `f` against a temporary file next to `path` and renames it over `path` once it succeeded
+pub(crate) fn write_atomic<F>(path: &Path, f: F) -> Result<(), McaError>
+where
+    F: FnOnce(&mut BufWriter<&File>) -> Result<(), McaError>,
+{
+    let temp = temp_path_for(path);
+    let file = File::create_new(&temp)?;
+
+    let result = (|| {
+        let mut w = BufWriter::new(&file);
+        f(&mut w)?;
+        w.flush()?;
+        drop(w);
+
+        if let Ok(meta) = fs::metadata(path) {
+            file.set_permissions(meta.permissions())?;
+        }
+        file.sync_all()?;
+
+        fs::rename(&temp, path)?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+
+    result
+}
+
+/// Writes one chunk payload, its length, compression byte & data, padded to whole sectors
+pub(crate) fn write_payload<W>(w: &mut W, compression: u8, data: &[u8]) -> Result<(), McaError>
+where
+    W: Write,
+{
+    // this little +1 accounts for the compression byte
+    w.write_all(&(data.len() as u32 + 1).to_be_bytes())?;
+    w.write_all(&[compression])?;
+    w.write_all(data)?;
+
+    // pad the chunk so It's always in sector chunks
+    w.write_all(
+        ZERO_SECTOR
+            .get(..layout::padding_for(data.len()))
+            .unwrap_or_default(),
+    )?;
+
+    Ok(())
+}
+
+/// Writes a payload like [`write_payload`], followed by empty sectors until it takes `sector_count`
+fn write_reserved<W>(
+    w: &mut W,
+    compression: u8,
+    data: &[u8],
+    sector_count: usize,
+) -> Result<(), McaError>
+where
+    W: Write,
+{
+    write_payload(w, compression, data)?;
+    for _ in layout::sectors_for_payload(data.len()) as usize..sector_count {
+        w.write_all(&ZERO_SECTOR)?;
+    }
+
+    Ok(())
+}
+
+/// The location & timestamp tables of a region being written  
+/// Both are indexed in header slot order, see [`layout::header_offset_for`]
+#[derive(Debug)]
+struct Header<'a> {
+    // holds the encoded location entry of each chunk
+    locations: [[u8; 4]; CHUNK_COUNT],
+    chunks: [Option<&'a PendingChunk>; CHUNK_COUNT],
+    // compression byte, data & sector count of every payload, in the order they're written
+    payloads: Vec<(u8, &'a [u8], usize)>,
+    summary: WriteSummary,
+}
+
+impl<'a> Header<'a> {
+    fn new() -> Header<'a> {
+        Header {
+            locations: [[0; 4]; CHUNK_COUNT],
+            chunks: [None; CHUNK_COUNT],
+            payloads: vec![],
+            summary: WriteSummary {
+                bytes_written: HEADER_SIZE,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn index((x, z): (u8, u8)) -> usize {
+        layout::header_offset_for(x as usize, z as usize) / 4
+    }
+
+    /// Points the chunk's header slot at `entry`
+    fn place(&mut self, chunk: &'a PendingChunk, entry: [u8; 4]) {
+        let index = Self::index(chunk.coordinate);
+        if let (Some(location), Some(slot)) =
+            (self.locations.get_mut(index), self.chunks.get_mut(index))
+        {
+            *location = entry;
+            *slot = Some(chunk);
+        }
+    }
+
+    /// The chunk placed in the slot at `x` `z`, if any
+    fn chunk(&self, x: usize, z: usize) -> Option<&'a PendingChunk> {
+        self.chunks
+            .get(layout::header_offset_for(x, z) / 4)
+            .copied()
+            .flatten()
+    }
+
+    /// The first chunk vanilla can't load as placed, see [`RegionWriter::set_strict_vanilla`]
+    fn vanilla_issue(&self) -> Option<String> {
+        let mut seen = std::collections::HashSet::new();
+        for (chunk, entry) in self.chunks.iter().zip(self.locations.iter()) {
+            let Some(chunk) = chunk else {
+                continue;
+            };
+
+            let (x, z) = chunk.coordinate;
+            let compression = chunk.compression.to_u8();
+            if MinVersion::for_compression_byte(compression) == MinVersion::NotVanilla {
+                return Some(format!("chunk ({x}, {z}) uses compression {compression}"));
+            }
+            if !seen.insert(*entry) {
+                return Some(format!(
+                    "chunk ({x}, {z}) shares sectors with another chunk"
+                ));
+            }
+        }
+
+        None
+    }
+
+    /// A record of every placed chunk, in header order
+    fn records(&self) -> Vec<ChunkWriteRecord> {
+        self.chunks
+            .iter()
+            .zip(self.locations.iter())
+            .filter_map(|(chunk, entry)| {
+                let chunk = (*chunk)?;
+                Some(ChunkWriteRecord {
+                    coordinate: chunk.coordinate,
+                    raw_size: chunk.raw_len(),
+                    compressed_size: chunk.payload().len(),
+                    compression: chunk.compression.clone(),
+                    sector_count: entry[3],
+                })
+            })
+            .collect()
+    }
+
+    fn write<W>(&self, w: &mut W) -> Result<(), McaError>
+    where
+        W: Write,
+    {
+        for entry in self.locations.iter() {
+            w.write_all(entry)?;
+        }
+
+        for chunk in self.chunks.iter() {
+            match chunk {
+                Some(chunk) => w.write_all(&chunk.timestamp.to_be_bytes())?,
+                None => w.write_all(&[0, 0, 0, 0])?,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::HEADER_SIZE;
+
+    const REGION: &[u8] = include_bytes!("../benches/r.0.0.mca");
+
+    #[test]
+    fn round_trip() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut writer = RegionWriter::new();
+
+        for (idx, chunk) in region.iter().enumerate() {
+            let chunk = match chunk.unwrap() {
+                Some(data) => data,
+                None => continue,
+            };
+
+            let data = chunk.decompress().unwrap();
+            writer
+                .push_chunk_with_compression(
+                    &data,
+                    ((idx % 32) as u8, (idx / 32) as u8),
+                    CompressionType::Zlib,
+                )
+                .unwrap();
+        }
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+
+        let new_region = RegionReader::new(&buf).unwrap();
+        let chunk = new_region.get_chunk(0, 0).unwrap().unwrap();
+
+        let data = chunk.decompress().unwrap();
+        let _ = sculk::chunk::Chunk::from_bytes(&data).unwrap();
+
+        for (old, new) in region.iter().zip(new_region.iter()) {
+            match (old.unwrap(), new.unwrap()) {
+                (Some(old), Some(new)) => {
+                    assert_eq!(old.decompress().unwrap(), new.decompress().unwrap())
+                }
+                (None, None) => {}
+                _ => panic!("chunk presence differs"),
+            }
+        }
+    }
+
+    #[test]
+    fn full_region_round_trip() {
+        let mut writer = RegionWriter::new();
+
+        // push in an order that isn't header order, with content unique to each slot
+        for x in (0..32u8).rev() {
+            for z in 0..32u8 {
+                let data = vec![x, z, x ^ z, 10];
+                writer
+                    .push_chunk_with_timestamp(
+                        &data,
+                        (x, z),
+                        CompressionType::Uncompressed,
+                        x as u32 * 32 + z as u32 + 1,
+                    )
+                    .unwrap();
+            }
+        }
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        let region = RegionReader::new(&buf).unwrap();
+
+        for (idx, chunk) in region.iter().enumerate() {
+            let (x, z) = crate::RegionIter::get_chunk_coordinate(idx);
+            let chunk = chunk.unwrap().unwrap();
+
+            assert_eq!(chunk.raw_data, &[x as u8, z as u8, (x ^ z) as u8, 10]);
+            assert_eq!(region.get_chunk_timestamp(x, z), (x * 32 + z + 1) as u32);
+        }
+    }
+
+    #[test]
+    fn push_with_timestamp() {
+        let mut writer = RegionWriter::new();
+        writer
+            .push_chunk_with_timestamp(&[10, 0, 0, 0], (0, 0), CompressionType::Zlib, 1724372177)
+            .unwrap();
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+
+        let region = RegionReader::new(&buf).unwrap();
+        assert_eq!(region.get_chunk_timestamp(0, 0), 1724372177);
+        assert_eq!(region.get_chunk_timestamp(1, 0), 0);
+
+        let chunk = region.get_chunk(0, 0).unwrap().unwrap();
+        assert_eq!(chunk.get_compression_type(), CompressionType::Zlib);
+        assert_eq!(chunk.decompress().unwrap(), vec![10, 0, 0, 0]);
+    }
+
+    #[test]
+    fn precompressed_copy() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut writer = RegionWriter::new();
+
+        for (idx, chunk) in region.iter().enumerate() {
+            let chunk = match chunk.unwrap() {
+                Some(data) => data,
+                None => continue,
+            };
+
+            writer
+                .push_precompressed_chunk(
+                    chunk.raw_data.to_vec(),
+                    chunk.get_compression_type(),
+                    ((idx % 32) as u8, (idx / 32) as u8),
+                    0,
+                )
+                .unwrap();
+        }
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        let new_region = RegionReader::new(&buf).unwrap();
+
+        for (old, new) in region.iter().zip(new_region.iter()) {
+            match (old.unwrap(), new.unwrap()) {
+                (Some(old), Some(new)) => {
+                    assert_eq!(old.raw_data, new.raw_data);
+                    assert_eq!(old.decompress().unwrap(), new.decompress().unwrap());
+                }
+                (None, None) => {}
+                _ => panic!("chunk presence differs"),
+            }
+        }
+    }
+
+    #[test]
+    fn raw_chunk_pass_through() {
+        let region = RegionReader::new(REGION).unwrap();
+        let chunk = region.get_chunk(18, 17).unwrap().unwrap();
+        let timestamp = region.get_chunk_timestamp(18, 17);
+
+        let mut writer = RegionWriter::new();
+        writer
+            .push_pending_chunk(PendingChunk::from_raw_chunk(&chunk, (0, 0), timestamp))
+            .unwrap();
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+
+        // length field, then the compression byte exactly as it was in the source
+        assert_eq!(buf[HEADER_SIZE + 4], CompressionType::Zlib.to_u8());
+
+        let new_region = RegionReader::new(&buf).unwrap();
+        let copied = new_region.get_chunk(0, 0).unwrap().unwrap();
+        assert_eq!(copied.get_compression_type(), chunk.get_compression_type());
+        assert_eq!(copied.raw_data, chunk.raw_data);
+        assert_eq!(new_region.get_chunk_timestamp(0, 0), timestamp);
+    }
+
+    #[test]
+    fn duplicate_replaces() {
+        let mut writer = RegionWriter::new();
+        writer
+            .push_chunk_with_compression(&[1; 5000], (3, 3), CompressionType::Uncompressed)
+            .unwrap();
+        writer
+            .push_chunk_with_compression(&[2; 10], (3, 3), CompressionType::Uncompressed)
+            .unwrap();
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+
+        assert_eq!(buf.len(), HEADER_SIZE + SECTOR_SIZE);
+        let region = RegionReader::new(&buf).unwrap();
+        assert_eq!(region.get_chunk(3, 3).unwrap().unwrap().raw_data, &[2; 10]);
+    }
+
+    #[test]
+    fn duplicate_errors() {
+        let mut writer = RegionWriter::new();
+        writer.set_duplicate_policy(DuplicatePolicy::Error);
+        writer
+            .push_chunk_with_compression(&[1; 10], (3, 3), CompressionType::Uncompressed)
+            .unwrap();
+
+        let err = writer
+            .push_chunk_with_compression(&[2; 10], (3, 3), CompressionType::Uncompressed)
+            .unwrap_err();
+        assert!(matches!(err, McaError::DuplicateChunk { x: 3, z: 3 }));
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+
+        assert_eq!(buf.len(), HEADER_SIZE + SECTOR_SIZE);
+        let region = RegionReader::new(&buf).unwrap();
+        assert_eq!(region.get_chunk(3, 3).unwrap().unwrap().raw_data, &[1; 10]);
+    }
+
+    #[test]
+    fn introspection() {
+        let mut writer = RegionWriter::new();
+        assert!(writer.is_empty());
+
+        for x in 0..3 {
+            writer
+                .push_chunk_with_compression(&[x; 10], (x, 0), CompressionType::Uncompressed)
+                .unwrap();
+        }
+        assert_eq!(writer.len(), 3);
+        assert!(writer.contains((1, 0)));
+        assert!(!writer.contains((0, 1)));
+
+        let removed = writer.remove_chunk((1, 0)).unwrap();
+        assert_eq!(removed.payload(), vec![1; 10]);
+        assert!(writer.remove_chunk((1, 0)).is_none());
+        assert!(!writer.contains((1, 0)));
+        assert_eq!(writer.len(), 2);
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+
+        assert_eq!(buf.len(), HEADER_SIZE + SECTOR_SIZE * 2);
+        let region = RegionReader::new(&buf).unwrap();
+        assert!(region.get_chunk(1, 0).unwrap().is_none());
+        assert_eq!(region.get_chunk(2, 0).unwrap().unwrap().raw_data, &[2; 10]);
+
+        writer.clear();
+        assert!(writer.is_empty());
+    }
+
+    #[test]
+    fn clear_and_reuse() {
+        let region = RegionReader::new(REGION).unwrap();
+        let raw = region
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, c)| {
+                let chunk = c.unwrap()?;
+                Some((
+                    crate::RegionIter::get_chunk_coordinate(idx),
+                    chunk.decompress().unwrap(),
+                ))
+            })
+            .collect::<Vec<_>>();
+        let (first, second) = raw.split_at(raw.len() / 2);
+        let write_fresh = |chunks: &[((usize, usize), Vec<u8>)]| {
+            let mut writer = RegionWriter::with_fixed_timestamp(5);
+            for ((x, z), data) in chunks {
+                writer.push_chunk(data, (*x as u8, *z as u8)).unwrap();
+            }
+            writer.into_bytes().unwrap()
+        };
+
+        let mut writer = RegionWriter::with_fixed_timestamp(5);
+        writer.preserve_layout(&region);
+        for ((x, z), data) in first {
+            writer.push_chunk(data, (*x as u8, *z as u8)).unwrap();
+        }
+        let mut first_buf = vec![];
+        writer.write(&mut first_buf).unwrap();
+        let buffers = writer
+            .chunks()
+            .map(|c| c.payload().as_ptr())
+            .collect::<std::collections::HashSet<_>>();
+
+        writer.clear_and_reuse();
+        assert!(writer.is_empty());
+        assert!(writer.chunks.capacity() >= first.len());
+        for ((x, z), data) in second {
+            writer.push_chunk(data, (*x as u8, *z as u8)).unwrap();
+        }
+        // the second region's chunks are compressed into the first one's buffers
+        assert!(writer
+            .chunks()
+            .all(|c| buffers.contains(&c.payload().as_ptr())));
+
+        let mut second_buf = vec![];
+        writer.write(&mut second_buf).unwrap();
+        assert_eq!(second_buf, write_fresh(second));
+
+        let written = RegionReader::new(&second_buf).unwrap();
+        for ((x, z), data) in second {
+            assert_eq!(
+                &written
+                    .get_chunk(*x, *z)
+                    .unwrap()
+                    .unwrap()
+                    .decompress()
+                    .unwrap(),
+                data
+            );
+        }
+        for ((x, z), _) in first {
+            assert!(written.get_chunk(*x, *z).unwrap().is_none());
+        }
+        let preserved = RegionReader::new(&first_buf).unwrap();
+        for ((x, z), data) in first {
+            assert_eq!(
+                &preserved
+                    .get_chunk(*x, *z)
+                    .unwrap()
+                    .unwrap()
+                    .decompress()
+                    .unwrap(),
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn memory_usage() {
+        let mut state = 0x2545_f491_u32;
+        let mut noise = |len: usize| {
+            (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    state as u8
+                })
+                .collect::<Vec<_>>()
+        };
+        let chunks = (0..20).map(|i| noise(1000 * (i + 1))).collect::<Vec<_>>();
+
+        let mut writer = RegionWriter::with_fixed_timestamp(1);
+        let mut usage = writer.memory_usage();
+        let push_all = |writer: &mut RegionWriter| {
+            for (x, data) in chunks.iter().enumerate() {
+                writer.push_chunk(data, (x as u8, 0)).unwrap();
+            }
+        };
+        for (x, data) in chunks.iter().enumerate() {
+            writer.push_chunk(data, (x as u8, 0)).unwrap();
+            assert!(writer.memory_usage() > usage);
+            usage = writer.memory_usage();
+        }
+        let buffers = writer.chunks().map(|c| c.heap_size()).sum::<usize>();
+        assert!(buffers > chunks.iter().map(Vec::len).sum::<usize>());
+
+        // recycled buffers are still held, and reused by chunks of the same size
+        writer.clear_and_reuse();
+        assert_eq!(writer.memory_usage(), usage);
+        push_all(&mut writer);
+        assert_eq!(writer.memory_usage(), usage);
+
+        let mut fresh = RegionWriter::with_fixed_timestamp(1);
+        push_all(&mut fresh);
+        let usage = fresh.memory_usage();
+        fresh.clear();
+        assert!(fresh.memory_usage() <= usage - buffers);
+    }
+
+    #[test]
+    fn default_compression() {
+        let region = RegionReader::new(REGION).unwrap();
+        let data = region
+            .get_chunk(18, 17)
+            .unwrap()
+            .unwrap()
+            .decompress()
+            .unwrap();
+
+        let mut writer = RegionWriter::new();
+        writer.push_chunk(&data, (0, 0)).unwrap();
+
+        let mut lz4 = RegionWriter::with_default_compression(CompressionType::LZ4);
+        lz4.push_chunk(&data, (0, 0)).unwrap();
+
+        let mut best = RegionWriter::new();
+        best.set_compression_level(CompressionLevel::Best);
+        best.push_chunk(&data, (0, 0)).unwrap();
+
+        let mut fastest = RegionWriter::new();
+        fastest.set_compression_level(CompressionLevel::Fastest);
+        fastest.push_chunk(&data, (0, 0)).unwrap();
+
+        for (writer, compression) in [
+            (&writer, CompressionType::Zlib),
+            (&lz4, CompressionType::LZ4),
+            (&best, CompressionType::Zlib),
+            (&fastest, CompressionType::Zlib),
+        ] {
+            let mut buf = vec![];
+            writer.write(&mut buf).unwrap();
+
+            let region = RegionReader::new(&buf).unwrap();
+            let chunk = region.get_chunk(0, 0).unwrap().unwrap();
+            assert_eq!(chunk.get_compression_type(), compression);
+            assert_eq!(chunk.decompress().unwrap(), data);
+        }
+
+        assert!(best.chunks[0].payload().len() <= fastest.chunks[0].payload().len());
+    }
+
+    #[test]
+    fn timestamps_by_coordinate() {
+        let coordinates = [(0, 0), (5, 1), (1, 5), (31, 0), (0, 31), (17, 9)];
+
+        let mut writer = RegionWriter::new();
+        for (i, coordinate) in coordinates.iter().enumerate().rev() {
+            writer
+                .push_chunk_with_timestamp(
+                    &[10, 0, 0, 0],
+                    *coordinate,
+                    CompressionType::Zlib,
+                    1_700_000_000 + i as u32,
+                )
+                .unwrap();
+        }
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        let region = RegionReader::new(&buf).unwrap();
+
+        for (i, (x, z)) in coordinates.iter().enumerate() {
+            assert_eq!(
+                region.get_chunk_timestamp(*x as usize, *z as usize),
+                1_700_000_000 + i as u32
+            );
+        }
+
+        let stamped = (0..32 * 32)
+            .filter(|i| region.get_chunk_timestamp(i % 32, i / 32) != 0)
+            .count();
+        assert_eq!(stamped, coordinates.len());
+    }
+
+    #[test]
+    fn current_timestamp() {
+        let mut writer = RegionWriter::new();
+        writer.push_chunk(&[10, 0, 0, 0], (7, 2)).unwrap();
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        let region = RegionReader::new(&buf).unwrap();
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+        let written = region.get_chunk_timestamp(7, 2);
+        assert!(
+            now.abs_diff(written) <= 5,
+            "{written} is not close to {now}"
+        );
+    }
+
+    #[test]
+    fn aligned_payload_has_no_padding_sector() {
+        let mut writer = RegionWriter::new();
+        // 4 length bytes + 1 compression byte + 4091 bytes of data is exactly one sector
+        writer
+            .push_chunk_with_compression(&[1; 4091], (0, 0), CompressionType::Uncompressed)
+            .unwrap();
+        writer
+            .push_chunk_with_compression(&[2; 10], (1, 0), CompressionType::Uncompressed)
+            .unwrap();
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+
+        assert_eq!(buf.len(), HEADER_SIZE + SECTOR_SIZE * 2);
+        let region = RegionReader::new(&buf).unwrap();
+        assert_eq!(region.get_location(0), Some([0, 0, 2, 1]));
+        assert_eq!(region.get_location(4), Some([0, 0, 3, 1]));
+        assert_eq!(region.get_chunk(1, 0).unwrap().unwrap().raw_data, &[2; 10]);
+    }
+
+    #[test]
+    fn chunk_too_large() {
+        let mut writer = RegionWriter::new();
+        writer
+            .push_chunk_with_compression(&[1; 10], (0, 0), CompressionType::Uncompressed)
+            .unwrap();
+
+        // the largest payload that still fits in 255 sectors
+        let max = MAX_CHUNK_SECTORS as usize * SECTOR_SIZE - 5;
+        writer
+            .push_chunk_with_compression(&vec![2; max], (1, 0), CompressionType::Uncompressed)
+            .unwrap();
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        assert_eq!(buf.len(), HEADER_SIZE + SECTOR_SIZE * 256);
+
+        writer
+            .push_chunk_with_compression(&vec![3; max + 1], (2, 0), CompressionType::Uncompressed)
+            .unwrap();
+        let mut buf = vec![];
+        let err = writer.write(&mut buf).unwrap_err();
+
+        assert!(matches!(
+            err,
+            McaError::ChunkTooLarge { x: 2, z: 0, bytes } if bytes == max + 1
+        ));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn external_chunks() {
+        let max = MAX_CHUNK_SECTORS as usize * SECTOR_SIZE - 5;
+        let big: Vec<u8> = (0..max + 100).map(|i| (i % 251) as u8).collect();
+
+        let mut writer = RegionWriter::for_region(RegionPos::new(0, 0));
+        writer
+            .push_chunk_with_compression(&[1; 10], (0, 0), CompressionType::Uncompressed)
+            .unwrap();
+        writer
+            .push_chunk_with_compression(&big, (4, 9), CompressionType::Uncompressed)
+            .unwrap();
+        writer
+            .push_chunk_with_compression(&[3; 10], (1, 0), CompressionType::Uncompressed)
+            .unwrap();
+
+        let mut buf = vec![];
+        let external = writer.write_with_external(&mut buf).unwrap();
+
+        assert_eq!(buf.len(), HEADER_SIZE + SECTOR_SIZE * 3);
+        assert_eq!(external.len(), 1);
+        assert_eq!((external[0].chunk_x, external[0].chunk_z), (4, 9));
+
+        let region = RegionReader::new(&buf).unwrap();
+        assert_eq!(
+            region.get_location(RegionReader::chunk_offset(4, 9).unwrap()),
+            Some([0, 0, 4, 1])
+        );
+
+        let stub = region.get_chunk(4, 9).unwrap().unwrap();
+        assert!(stub.is_external());
+        assert!(stub.raw_data.is_empty());
+
+        let data = stub
+            .get_compression_type()
+            .decompress(&external[0].data)
+            .unwrap();
+        assert_eq!(data, big);
+
+        let small = region.get_chunk(1, 0).unwrap().unwrap();
+        assert!(!small.is_external());
+        assert_eq!(small.raw_data, &[3; 10]);
+    }
+
+    #[test]
+    fn sector_offset_range() {
+        assert_eq!(
+            RegionWriter::location_entry(HEADER_SIZE, 3).unwrap(),
+            [0, 0, 2, 3]
+        );
+
+        let last = MAX_SECTOR_OFFSET * SECTOR_SIZE;
+        assert_eq!(
+            RegionWriter::location_entry(last, 1).unwrap(),
+            [0xFF, 0xFF, 0xFF, 1]
+        );
+
+        let err = RegionWriter::location_entry(last + SECTOR_SIZE, 1).unwrap_err();
+        assert!(matches!(
+            err,
+            McaError::RegionTooLarge { sector } if sector == MAX_SECTOR_OFFSET + 1
+        ));
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mca-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn region_aware_writer() {
+        let dir = temp_dir("write-to-dir");
+        let mut writer = RegionWriter::for_region(RegionPos::new(-2, 1));
+        assert_eq!(writer.region(), Some(RegionPos::new(-2, 1)));
+
+        // world chunk -64 is the first of region -2, -33 the last
+        writer.push_chunk_absolute(-64, 32, &[10, 1, 0, 0]).unwrap();
+        writer.push_chunk_absolute(-33, 63, &[10, 2, 0, 0]).unwrap();
+        assert!(writer.contains((0, 0)));
+        assert!(writer.contains((31, 31)));
+
+        let err = writer
+            .push_chunk_absolute(-32, 40, &[10, 3, 0, 0])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            McaError::ChunkOutsideRegion {
+                chunk: (-32, 40),
+                region: (-2, 1)
+            }
+        ));
+        assert_eq!(err.to_string(), "Chunk (-32, 40) is outside region (-2, 1)");
+        assert!(matches!(
+            RegionWriter::new().push_chunk_absolute(0, 0, &[10, 0, 0, 0]),
+            Err(McaError::UnknownRegion)
+        ));
+
+        let path = writer.write_to_dir(&dir).unwrap();
+        assert_eq!(path, dir.join("r.-2.1.mca"));
+        let data = fs::read(&path).unwrap();
+        let region = RegionReader::new(&data).unwrap();
+        let chunk = region.get_chunk(31, 31).unwrap().unwrap();
+        assert_eq!(chunk.decompress().unwrap(), [10, 2, 0, 0]);
+        assert!(RegionWriter::new().write_to_dir(&dir).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn write_to_path_atomic() {
+        let dir = temp_dir("write-to-path");
+        let path = dir.join("region").join("r.0.0.mca");
+
+        let mut writer = RegionWriter::new();
+        writer.push_chunk(&[10, 1, 2, 3], (0, 0)).unwrap();
+
+        // parent directories are only created when asked for
+        assert!(writer.write_to_path(&path).is_err());
+        writer.write_to_path_all(&path).unwrap();
+        let old = fs::read(&path).unwrap();
+        assert!(RegionReader::new(&old)
+            .unwrap()
+            .get_chunk(0, 0)
+            .unwrap()
+            .is_some());
+
+        // a write failing partway leaves the old file untouched and no temp file behind
+        let result = write_atomic(&path, |w| {
+            w.write_all(&[0; HEADER_SIZE])?;
+            Err(McaError::NotGenerated)
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path).unwrap(), old);
+        let files = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .filter(|e| e.as_ref().unwrap().path().extension() != Some("lock".as_ref()))
+            .count();
+        assert_eq!(files, 1);
+
+        writer.push_chunk(&[10, 4, 5, 6], (1, 0)).unwrap();
+        writer.write_to_path(&path).unwrap();
+        let new = fs::read(&path).unwrap();
+        let region = RegionReader::new(&new).unwrap();
+        assert!(region.get_chunk(1, 0).unwrap().is_some());
+        let files = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .filter(|e| e.as_ref().unwrap().path().extension() != Some("lock".as_ref()))
+            .count();
+        assert_eq!(files, 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_to_path_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("permissions");
+        let path = dir.join("r.0.0.mca");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, []).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        RegionWriter::new().write_to_path(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn deterministic_output() {
+        let mut coords = (0..32u8)
+            .flat_map(|z| (0..4u8).map(move |x| (x, z)))
+            .collect::<Vec<_>>();
+
+        let build = |coords: &[(u8, u8)], layout: LayoutStrategy| {
+            let mut writer = RegionWriter::new();
+            writer.set_layout(layout);
+            for &(x, z) in coords {
+                let raw = vec![10, x, z, x ^ z];
+                writer
+                    .push_chunk_with_timestamp(&raw, (x, z), CompressionType::Zlib, 1000 + x as u32)
+                    .unwrap();
+            }
+
+            let mut buf = vec![];
+            writer.write(&mut buf).unwrap();
+            buf
+        };
+
+        let sorted = build(&coords, LayoutStrategy::default());
+        // a fixed shuffle, reversed then interleaved
+        coords.reverse();
+        let (even, odd): (Vec<_>, Vec<_>) =
+            coords.iter().enumerate().partition(|(i, _)| i % 2 == 0);
+        let shuffled = odd
+            .into_iter()
+            .chain(even)
+            .map(|(_, c)| *c)
+            .collect::<Vec<_>>();
+
+        assert_eq!(build(&shuffled, LayoutStrategy::CoordinateOrder), sorted);
+        assert_eq!(build(&shuffled, LayoutStrategy::CoordinateOrder), sorted);
+        assert_ne!(build(&shuffled, LayoutStrategy::InsertionOrder), sorted);
+    }
+
+    #[test]
+    fn layout_strategies() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut pushed = vec![];
+        let mut writer = RegionWriter::new();
+
+        // push back to front so insertion order differs from coordinate order
+        for idx in (0..1024).rev() {
+            let (x, z) = (idx % 32, idx / 32);
+            if let Some(chunk) = region.get_chunk(x, z).unwrap() {
+                let coordinate = (x as u8, z as u8);
+                let timestamp = region.get_chunk_timestamp(x, z);
+                writer
+                    .push_pending_chunk(PendingChunk::from_raw_chunk(&chunk, coordinate, timestamp))
+                    .unwrap();
+                pushed.push((coordinate, chunk.raw_data.len()));
+            }
+        }
+
+        let mut coordinate_order = pushed.clone();
+        coordinate_order.sort_by_key(|((x, z), _)| (*z, *x));
+        let mut size_order = coordinate_order.clone();
+        size_order.sort_by_key(|(_, len)| std::cmp::Reverse(*len));
+
+        for (layout, expected) in [
+            (LayoutStrategy::CoordinateOrder, coordinate_order),
+            (LayoutStrategy::InsertionOrder, pushed.clone()),
+            (LayoutStrategy::SizeDescending, size_order),
+        ] {
+            writer.set_layout(layout);
+            let mut buf = vec![];
+            writer.write(&mut buf).unwrap();
+
+            let new_region = RegionReader::new(&buf).unwrap();
+            for (old, new) in region.iter().zip(new_region.iter()) {
+                assert_eq!(old.unwrap(), new.unwrap());
+            }
+
+            let mut by_offset = expected
+                .iter()
+                .map(|((x, z), _)| {
+                    let offset = RegionReader::chunk_offset(*x as usize, *z as usize).unwrap();
+                    let location = new_region.get_location(offset).unwrap();
+                    (
+                        u32::from_be_bytes([0, location[0], location[1], location[2]]),
+                        (*x, *z),
+                    )
+                })
+                .collect::<Vec<_>>();
+            by_offset.sort();
+
+            let order = by_offset.into_iter().map(|(_, c)| c).collect::<Vec<_>>();
+            let expected = expected.into_iter().map(|(c, _)| c).collect::<Vec<_>>();
+            assert_eq!(order, expected, "{layout:?}");
+        }
+    }
+
+    #[test]
+    fn push_chunks_matches_serial() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut raw = vec![];
+        for (idx, chunk) in region.iter().enumerate() {
+            if let Some(chunk) = chunk.unwrap() {
+                raw.push((
+                    chunk.decompress().unwrap(),
+                    ((idx % 32) as u8, (idx / 32) as u8),
+                ));
+            }
+        }
+
+        let mut serial = RegionWriter::new();
+        for (data, coordinate) in raw.iter() {
+            serial
+                .push_chunk_with_timestamp(data, *coordinate, CompressionType::Zlib, 42)
+                .unwrap();
+        }
+
+        let batch = raw
+            .iter()
+            .map(|(d, c)| (d.as_slice(), *c))
+            .collect::<Vec<_>>();
+        let mut parallel = RegionWriter::new();
+        parallel
+            .push_chunks_with_timestamp(&batch, CompressionType::Zlib, 42)
+            .unwrap();
+
+        let (mut a, mut b) = (vec![], vec![]);
+        serial.write(&mut a).unwrap();
+        parallel.write(&mut b).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn deferred_compression_matches_eager() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut raw = vec![];
+        for (idx, chunk) in region.iter().enumerate() {
+            if let Some(chunk) = chunk.unwrap() {
+                raw.push((
+                    chunk.decompress().unwrap(),
+                    ((idx % 32) as u8, (idx / 32) as u8),
+                ));
+            }
+        }
+
+        let mut eager = RegionWriter::new();
+        let mut deferred = RegionWriter::new();
+        deferred.set_deferred_compression(true);
+        for (data, coordinate) in raw.iter() {
+            for writer in [&mut eager, &mut deferred] {
+                writer
+                    .push_chunk_with_timestamp(data, *coordinate, CompressionType::Zlib, 42)
+                    .unwrap();
+            }
+        }
+        assert!(deferred.chunks().all(|c| !c.is_compressed()));
+        assert!(deferred.estimated_len() >= eager.estimated_len());
+        assert_eq!(deferred.layout().unwrap(), eager.layout().unwrap());
+
+        let (mut a, mut b) = (vec![], vec![]);
+        eager.write(&mut a).unwrap();
+        deferred.write(&mut b).unwrap();
+        assert_eq!(a, b);
+
+        // switching codecs before writing only retags the chunk, it's compressed once when written
+        let coordinate = raw[0].1;
+        eager
+            .set_compression(coordinate, CompressionType::LZ4)
+            .unwrap();
+        deferred
+            .set_compression(coordinate, CompressionType::LZ4)
+            .unwrap();
+        let chunk = deferred
+            .chunks()
+            .find(|c| c.coordinate == coordinate)
+            .unwrap();
+        assert!(!chunk.is_compressed());
+        assert_eq!(chunk.deferred_data(), Some(raw[0].0.as_slice()));
+
+        let (mut a, mut b) = (vec![], vec![]);
+        eager.write(&mut a).unwrap();
+        deferred.write(&mut b).unwrap();
+        assert_eq!(a, b);
+
+        let mut chunk = chunk.clone();
+        chunk.compress(CompressionLevel::default()).unwrap();
+        assert!(chunk.is_compressed());
+        assert_eq!(
+            CompressionType::LZ4.decompress(chunk.payload()).unwrap(),
+            raw[0].0
+        );
+    }
+
+    #[test]
+    fn deferred_compresses_once() {
+        static COMPRESSIONS: AtomicUsize = AtomicUsize::new(0);
+        fn counting(
+            scratch: &mut CompressScratch,
+            compression: &CompressionType,
+            data: &[u8],
+            level: CompressionLevel,
+        ) -> Result<Vec<u8>, McaError> {
+            COMPRESSIONS.fetch_add(1, Ordering::Relaxed);
+            CompressScratch::compress_with_scratch(scratch, compression, data, level)
+        }
+
+        let mut writer = RegionWriter::with_fixed_timestamp(1);
+        writer.scratch.codec = counting;
+        writer.set_deferred_compression(true);
+        for x in 0..4 {
+            writer.push_chunk(&[10, 0, 0, x], (x, 0)).unwrap();
+        }
+        writer
+            .set_compression((0, 0), CompressionType::LZ4)
+            .unwrap();
+        assert_eq!(COMPRESSIONS.load(Ordering::Relaxed), 0);
+
+        let layout = writer.layout().unwrap();
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        writer.write_with_layout(&layout, &mut vec![]).unwrap();
+        assert_eq!(COMPRESSIONS.load(Ordering::Relaxed), 4);
+
+        // edits of the raw data are what gets written, retagging compresses that chunk again
+        let chunk = writer.get_chunk_mut((1, 0)).unwrap();
+        assert!(chunk.compressed_data_mut().is_none());
+        chunk.deferred_data_mut().unwrap().push(7);
+        writer
+            .set_compression((2, 0), CompressionType::Uncompressed)
+            .unwrap();
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        assert_eq!(COMPRESSIONS.load(Ordering::Relaxed), 6);
+
+        let region = RegionReader::new(&buf).unwrap();
+        let chunk = region.get_chunk(1, 0).unwrap().unwrap();
+        assert_eq!(chunk.decompress().unwrap(), [10, 0, 0, 1, 7]);
+        let chunk = region.get_chunk(2, 0).unwrap().unwrap();
+        assert_eq!(chunk.get_compression_type(), CompressionType::Uncompressed);
+
+        // compressing the chunk itself takes the copy the writes made
+        let mut chunk = writer.get_chunk((3, 0)).unwrap().clone();
+        chunk.compress(CompressionLevel::default()).unwrap();
+        assert_eq!(COMPRESSIONS.load(Ordering::Relaxed), 6);
+        assert_eq!(
+            chunk.compressed_data(),
+            region.get_chunk(3, 0).unwrap().map(|c| c.raw_data)
+        );
+    }
+
+    #[test]
+    fn estimated_len_and_summary() {
+        let mut writer = RegionWriter::new();
+        assert_eq!(writer.estimated_len(), HEADER_SIZE);
+
+        let region = RegionReader::new(REGION).unwrap();
+        for (idx, chunk) in region.iter().enumerate() {
+            if let Some(chunk) = chunk.unwrap() {
+                let coordinate = ((idx % 32) as u8, (idx / 32) as u8);
+                writer
+                    .push_pending_chunk(PendingChunk::from_raw_chunk(&chunk, coordinate, 0))
+                    .unwrap();
+            }
+        }
+        let aligned = SECTOR_SIZE * 2 - 5;
+        writer
+            .push_chunk_with_compression(&vec![7; aligned], (31, 31), CompressionType::Uncompressed)
+            .unwrap();
+
+        let estimate = writer.estimated_len();
+        let mut buf = vec![];
+        let summary = writer.write(&mut buf).unwrap();
+
+        assert_eq!(estimate, buf.len());
+        assert_eq!(summary.bytes_written, buf.len());
+        assert_eq!(summary.chunks_written, writer.len());
+        assert_eq!(summary.sectors_used * SECTOR_SIZE + HEADER_SIZE, buf.len());
+
+        let payload_bytes = writer
+            .chunks
+            .iter()
+            .map(|c| c.payload().len() + 5)
+            .sum::<usize>();
+        assert_eq!(
+            summary.padding_bytes,
+            buf.len() - HEADER_SIZE - payload_bytes
+        );
+
+        let mut cursor = std::io::Cursor::new(vec![]);
+        assert_eq!(writer.write_seek(&mut cursor).unwrap(), summary);
+    }
+
+    #[test]
+    fn target_versions() {
+        use CompressionType::*;
+
+        for (target, allowed, rejected) in [
+            (TargetVersion::V1_12, Zlib, Uncompressed),
+            (TargetVersion::V1_17, Zlib, LZ4),
+            (TargetVersion::V1_20_4, Uncompressed, LZ4),
+            (TargetVersion::V1_20_5Plus, LZ4, Custom),
+        ] {
+            let mut writer = RegionWriter::for_target(target);
+            assert_eq!(writer.target(), Some(target));
+            writer
+                .push_chunk_with_compression(&[10, 0, 0, 0], (0, 0), allowed)
+                .unwrap();
+
+            let err = writer
+                .push_chunk_with_compression(&[10, 0, 0, 0], (1, 0), rejected.clone())
+                .unwrap_err();
+            assert!(
+                matches!(&err, McaError::UnsupportedCompression { compression, target: t, .. }
+                    if *compression == rejected && *t == target),
+                "{err}"
+            );
+            assert!(writer.set_compression((0, 0), rejected.clone()).is_err());
+            assert!(writer
+                .push_pending_chunk(PendingChunk::from_compressed(vec![0], rejected, 0, (2, 0)))
+                .is_err());
+            assert_eq!(writer.len(), 1);
+        }
+
+        let mut latest = RegionWriter::for_target(TargetVersion::Latest);
+        latest
+            .push_pending_chunk(PendingChunk::from_compressed(vec![0], Custom, 0, (0, 0)))
+            .unwrap();
+
+        // external chunks need 1.15, so an old target fails like `write` does
+        let mut state = 0x2545_f491_u32;
+        let big = (0..SECTOR_SIZE * 256)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect::<Vec<u8>>();
+        let mut old = RegionWriter::for_target(TargetVersion::V1_12);
+        old.set_region(RegionPos::new(0, 0));
+        old.push_chunk_with_compression(&big, (0, 0), Zlib).unwrap();
+        assert!(matches!(
+            old.write_with_external(&mut vec![]),
+            Err(McaError::ChunkTooLarge { .. })
+        ));
+        let mut newer = RegionWriter::for_target(TargetVersion::V1_17);
+        newer.set_region(RegionPos::new(0, 0));
+        newer
+            .push_chunk_with_compression(&big, (0, 0), Zlib)
+            .unwrap();
+        assert_eq!(newer.write_with_external(&mut vec![]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn zopfli_archival() {
+        let region = RegionReader::new(REGION).unwrap();
+        let data = region
+            .get_chunk(0, 0)
+            .unwrap()
+            .unwrap()
+            .decompress()
+            .unwrap();
+
+        let mut writer = RegionWriter::new();
+        writer.set_compression_level(CompressionLevel::Archival);
+        writer.push_chunk(&data, (0, 0)).unwrap();
+        let buf = writer.into_bytes().unwrap();
+        let written = RegionReader::new(&buf).unwrap();
+        let archival = written.get_chunk(0, 0).unwrap().unwrap();
+
+        let best = CompressionType::Zlib
+            .compress_with_level(&data, CompressionLevel::Best)
+            .unwrap();
+        assert!(archival.raw_data.len() <= best.len());
+        assert_eq!(archival.decompress().unwrap(), data);
+    }
+
+    #[test]
+    fn lz4_framing() {
+        use crate::{Lz4Checksum, Lz4Framing};
+
+        let data = (0..300 * 1024)
+            .map(|i: usize| (i / 7 % 13) as u8 ^ (i >> 12) as u8)
+            .collect::<Vec<_>>();
+
+        for block_size in [64 * 1024, 256 * 1024] {
+            for checksum in [Lz4Checksum::XxHash32, Lz4Checksum::Zero] {
+                let framing = Lz4Framing::new(block_size, checksum).unwrap();
+                let mut writer = RegionWriter::with_default_compression(CompressionType::LZ4);
+                writer.set_lz4_framing(framing);
+                writer.set_verify_writes(true);
+                writer.push_chunk(&data, (1, 2)).unwrap();
+                let buf = writer.into_bytes().unwrap();
+
+                let region = RegionReader::new(&buf).unwrap();
+                let chunk = region.get_chunk(1, 2).unwrap().unwrap();
+                assert_eq!(Lz4Framing::detect(chunk.raw_data), Some(framing));
+                assert_eq!(chunk.decompress().unwrap(), data);
+                assert_eq!(framing.compress(&data).unwrap(), chunk.raw_data);
+
+                // copies keep the source framing even though the writer's is the default
+                let mut copy = RegionWriter::new();
+                copy.copy_chunk_from(&region, (1, 2), (1, 2)).unwrap();
+                let copied = copy.into_bytes().unwrap();
+                let copied = RegionReader::new(&copied).unwrap();
+                assert_eq!(copied.get_chunk(1, 2).unwrap(), Some(chunk));
+            }
+        }
+
+        // uncompressed blocks of 256 KiB with zeroed checksums, built by hand
+        let mut fixture = vec![];
+        for block in data.chunks(256 * 1024) {
+            fixture.extend_from_slice(b"LZ4Block");
+            fixture.push(0x10 | 8);
+            fixture.extend_from_slice(&(block.len() as u32).to_le_bytes());
+            fixture.extend_from_slice(&(block.len() as u32).to_le_bytes());
+            fixture.extend_from_slice(&[0; 4]);
+            fixture.extend_from_slice(block);
+        }
+        fixture.extend_from_slice(b"LZ4Block\x10");
+        fixture.extend_from_slice(&[0; 12]);
+        assert_eq!(CompressionType::LZ4.decompress(&fixture).unwrap(), data);
+        assert_eq!(
+            Lz4Framing::detect(&fixture),
+            Some(Lz4Framing::new(256 * 1024, Lz4Checksum::Zero).unwrap())
+        );
+        // a wrong checksum still fails
+        fixture[17] = 1;
+        assert!(CompressionType::LZ4.decompress(&fixture).is_err());
+        assert_eq!(Lz4Framing::detect(&[1, 2, 3]), None);
+
+        let default = RegionWriter::new().lz4_framing();
+        assert_eq!(default, Lz4Framing::default());
+        assert_eq!(default.block_size(), 64 * 1024);
+        assert_eq!(
+            default.compress(&data).unwrap(),
+            CompressionType::LZ4.compress(&data).unwrap()
+        );
+        assert!(matches!(
+            Lz4Framing::new(63, Lz4Checksum::XxHash32),
+            Err(McaError::InvalidLz4BlockSize(63))
+        ));
+        assert!(Lz4Framing::new(Lz4Framing::MAX_BLOCK_SIZE + 1, Lz4Checksum::Zero).is_err());
+    }
+
+    #[test]
+    fn verify_writes() {
+        // flips a bit of every output, standing in for memory corrupting payloads
+        fn corrupting(
+            scratch: &mut CompressScratch,
+            compression: &CompressionType,
+            data: &[u8],
+            level: CompressionLevel,
+        ) -> Result<Vec<u8>, McaError> {
+            let mut out = scratch.compress_with_scratch(compression, data, level)?;
+            if let Some(last) = out.last_mut() {
+                *last ^= 1;
+            }
+            Ok(out)
+        }
+
+        let raw = [10, 1, 2, 3, 4, 5, 6, 7];
+        for (compression, other) in [
+            (CompressionType::Zlib, CompressionType::LZ4),
+            (CompressionType::LZ4, CompressionType::Zlib),
+            (CompressionType::Uncompressed, CompressionType::Zlib),
+        ] {
+            let mut writer = RegionWriter::new();
+            writer.set_verify_writes(true);
+            writer
+                .push_chunk_with_compression(&raw, (0, 0), compression.clone())
+                .unwrap();
+
+            writer.scratch.codec = corrupting;
+            assert!(matches!(
+                writer.push_chunk_with_compression(&raw, (3, 4), compression.clone()),
+                Err(McaError::VerifyFailed { x: 3, z: 4 })
+            ));
+            assert!(matches!(
+                writer.push_chunks_with_timestamp(&[(&raw, (5, 6))], compression.clone(), 1),
+                Err(McaError::VerifyFailed { x: 5, z: 6 })
+            ));
+            assert!(matches!(
+                writer.set_compression((0, 0), other),
+                Err(McaError::VerifyFailed { x: 0, z: 0 })
+            ));
+            assert_eq!(writer.len(), 1);
+
+            // uncompressed chunks are never deferred
+            if compression == CompressionType::Uncompressed {
+                continue;
+            }
+
+            // deferred chunks are only compressed once written
+            writer.set_deferred_compression(true);
+            writer
+                .push_chunk_with_compression(&raw, (7, 8), compression.clone())
+                .unwrap();
+            let mut buf = vec![];
+            assert!(matches!(
+                writer.write(&mut buf),
+                Err(McaError::VerifyFailed { x: 7, z: 8 })
+            ));
+            assert!(buf.is_empty());
+
+            // without verifying the corruption goes unnoticed until read back
+            writer.set_verify_writes(false);
+            writer.write(&mut buf).unwrap();
+            let region = RegionReader::new(&buf).unwrap();
+            assert!(region
+                .get_chunk(7, 8)
+                .unwrap()
+                .unwrap()
+                .decompress()
+                .map_or(true, |data| data != raw));
+        }
+    }
+
+    #[test]
+    fn write_with_generator() {
+        // a compound holding just `xPos` & `zPos`
+        fn nbt(x: u8, z: u8) -> Vec<u8> {
+            let mut nbt = vec![10, 0, 0];
+            for (name, value) in [(b"xPos", x as i32 - 64), (b"zPos", z as i32 + 32)] {
+                nbt.extend_from_slice(&[3, 0, 4]);
+                nbt.extend_from_slice(name);
+                nbt.extend_from_slice(&value.to_be_bytes());
+            }
+            nbt.push(0);
+            nbt
+        }
+        let generate = |x: u8, z: u8| {
+            Ok((!(x + z).is_multiple_of(3)).then(|| ChunkSource {
+                data: nbt(x, z),
+                compression: [CompressionType::Zlib, CompressionType::LZ4][x as usize % 2].clone(),
+                timestamp: x as u32 * 100 + z as u32,
+            }))
+        };
+
+        let mut cursor = std::io::Cursor::new(vec![]);
+        let summary = RegionWriter::write_with(&mut cursor, generate).unwrap();
+        let buf = cursor.into_inner();
+        assert_eq!(summary.bytes_written, buf.len());
+
+        let mut writer = RegionWriter::new();
+        for index in 0..CHUNK_COUNT {
+            let (x, z) = ((index % 32) as u8, (index / 32) as u8);
+            if let Some(source) = generate(x, z).unwrap() {
+                writer
+                    .push_chunk_with_timestamp(
+                        &source.data,
+                        (x, z),
+                        source.compression,
+                        source.timestamp,
+                    )
+                    .unwrap();
+            }
+        }
+        let mut expected = vec![];
+        assert_eq!(writer.write(&mut expected).unwrap(), summary);
+        assert_eq!(buf, expected);
+
+        let region = RegionReader::new(&buf).unwrap();
+        for index in 0..CHUNK_COUNT {
+            let (x, z) = crate::RegionIter::get_chunk_coordinate(index);
+            let chunk = region.get_chunk(x, z).unwrap();
+            if (x + z).is_multiple_of(3) {
+                assert!(chunk.is_none());
+                continue;
+            }
+            assert_eq!(chunk.unwrap().decompress().unwrap(), nbt(x as u8, z as u8));
+            assert_eq!(region.get_chunk_timestamp(x, z), (x * 100 + z) as u32);
+        }
+
+        let err = RegionWriter::write_with(&mut std::io::Cursor::new(vec![]), |x, z| {
+            if (x, z) == (5, 7) {
+                return Err(McaError::NotGenerated);
+            }
+            generate(x, z)
+        })
+        .unwrap_err();
+        assert!(
+            matches!(&err, McaError::GenerateFailed { x: 5, z: 7, source } if matches!(**source, McaError::NotGenerated)),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn write_report() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut writer = RegionWriter::new();
+        let mut raw = None;
+        for (idx, chunk) in region.iter().enumerate() {
+            let Some(chunk) = chunk.unwrap() else {
+                continue;
+            };
+            let coordinate = ((idx % 32) as u8, (idx / 32) as u8);
+            if raw.is_none() {
+                let data = chunk.decompress().unwrap();
+                writer.push_chunk(&data, coordinate).unwrap();
+                raw = Some((coordinate, data.len()));
+            } else {
+                writer
+                    .push_pending_chunk(PendingChunk::from_raw_chunk(&chunk, coordinate, 0))
+                    .unwrap();
+            }
+        }
+
+        let mut buf = vec![];
+        let (summary, records) = writer.write_with_report(&mut buf).unwrap();
+        assert_eq!(records.len(), writer.len());
+
+        let payload_bytes = records
+            .iter()
+            .map(|r| r.compressed_size + layout::PAYLOAD_HEADER_SIZE)
+            .sum::<usize>();
+        assert_eq!(
+            payload_bytes,
+            buf.len() - HEADER_SIZE - summary.padding_bytes
+        );
+        assert_eq!(
+            records
+                .iter()
+                .map(|r| r.sector_count as usize)
+                .sum::<usize>(),
+            summary.sectors_used
+        );
+
+        let (coordinate, raw_len) = raw.unwrap();
+        for record in records.iter() {
+            let expected = (record.coordinate == coordinate).then_some(raw_len);
+            assert_eq!(record.raw_size, expected);
+            assert_eq!(record.compression, CompressionType::Zlib);
+        }
+
+        let mut plain = vec![];
+        writer.write(&mut plain).unwrap();
+        assert_eq!(buf, plain);
+    }
+
+    #[test]
+    fn write_into_slice() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut writer = RegionWriter::from_region(&region).unwrap();
+        writer.set_layout(LayoutStrategy::SizeDescending);
+
+        let mut streamed = vec![];
+        writer.write(&mut streamed).unwrap();
+
+        let mut buf = vec![0xAA; streamed.len() + 100];
+        assert_eq!(writer.write_into(&mut buf).unwrap(), streamed.len());
+        assert_eq!(&buf[..streamed.len()], &streamed[..]);
+        assert_eq!(&buf[streamed.len()..], &[0xAA; 100]);
+
+        let mut short = vec![0; streamed.len() - 1];
+        assert!(matches!(
+            writer.write_into(&mut short),
+            Err(McaError::BufferTooSmall { needed, provided })
+                if needed == streamed.len() && provided == streamed.len() - 1
+        ));
+        assert!(short.iter().all(|b| *b == 0));
+
+        // deduplicating goes through the regular planning
+        writer.set_deduplicate(true);
+        streamed.clear();
+        writer.write(&mut streamed).unwrap();
+        assert_eq!(writer.write_into(&mut buf).unwrap(), streamed.len());
+        assert_eq!(&buf[..streamed.len()], &streamed[..]);
+
+        let mut empty = [0; HEADER_SIZE];
+        assert_eq!(
+            RegionWriter::new().write_into(&mut empty).unwrap(),
+            HEADER_SIZE
+        );
+        assert_eq!(empty, [0; HEADER_SIZE]);
+    }
+
+    #[test]
+    fn into_bytes_matches_write() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut writer = RegionWriter::from_region(&region).unwrap();
+        writer.push_chunk(&[10, 0, 0, 0], (31, 31)).unwrap();
+
+        let estimate = writer.estimated_len();
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+
+        let bytes = writer.clone().into_bytes().unwrap();
+        assert_eq!(bytes.len(), estimate);
+        assert_eq!(bytes.capacity(), estimate);
+        assert_eq!(bytes, buf);
+
+        writer.preserve_layout(&region);
+        buf.clear();
+        writer.write(&mut buf).unwrap();
+        assert_eq!(writer.into_bytes().unwrap(), buf);
+    }
+
+    #[test]
+    fn from_region_round_trip() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut writer = RegionWriter::from_region(&region).unwrap();
+        assert_eq!(writer.len(), region.occupancy().count());
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        let new_region = RegionReader::new(&buf).unwrap();
+
+        for (idx, (old, new)) in region.iter().zip(new_region.iter()).enumerate() {
+            let (x, z) = (idx % 32, idx / 32);
+            assert_eq!(old.unwrap(), new.unwrap());
+            assert_eq!(
+                region.get_chunk_timestamp(x, z),
+                new_region.get_chunk_timestamp(x, z)
+            );
+        }
+
+        writer.remove_chunk((8, 3)).unwrap();
+        writer.push_chunk(&[10, 0, 0, 0], (0, 31)).unwrap();
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+
+        let edited = RegionReader::new(&buf).unwrap();
+        assert!(edited.get_chunk(8, 3).unwrap().is_none());
+        assert_eq!(
+            edited
+                .get_chunk(0, 31)
+                .unwrap()
+                .unwrap()
+                .decompress()
+                .unwrap(),
+            vec![10, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn preserve_layout() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut writer = RegionWriter::from_region(&region).unwrap();
+        writer.set_layout(LayoutStrategy::SizeDescending);
+        writer.preserve_layout(&region);
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        assert_eq!(buf, REGION);
+
+        let (empty_x, empty_z) = (0..1024)
+            .map(|i| ((i % 32) as u8, (i / 32) as u8))
+            .find(|(x, z)| !writer.contains((*x, *z)))
+            .unwrap();
+        writer.remove_chunk((10, 3)).unwrap();
+        writer
+            .push_chunk_with_timestamp(&[10, 1, 2, 3], (8, 3), CompressionType::Zlib, 7)
+            .unwrap();
+        writer
+            .push_chunk_with_timestamp(&[10, 4, 5, 6], (empty_x, empty_z), CompressionType::Zlib, 8)
+            .unwrap();
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        let edited = RegionReader::new(&buf).unwrap();
+
+        // bytes only differ in the header & the sectors the edited chunks now sit in
+        let sectors_of = |x: usize, z: usize| {
+            let offset = RegionReader::chunk_offset(x, z).unwrap();
+            let entry = [
+                buf[offset],
+                buf[offset + 1],
+                buf[offset + 2],
+                buf[offset + 3],
+            ];
+            let (sector, count) = layout::decode_location(entry);
+            sector..sector + count
+        };
+        let edited_sectors = [
+            sectors_of(8, 3),
+            sectors_of(empty_x as usize, empty_z as usize),
+        ];
+        for (i, byte) in buf.iter().enumerate() {
+            if i < HEADER_SIZE || REGION.get(i) == Some(byte) {
+                continue;
+            }
+            let sector = i / SECTOR_SIZE;
+            assert!(
+                edited_sectors.iter().any(|r| r.contains(&sector)),
+                "byte {i} changed"
+            );
+        }
+        assert!(buf.len() >= REGION.len());
+
+        assert!(edited.get_chunk(10, 3).unwrap().is_none());
+        assert_eq!(edited.get_chunk_timestamp(8, 3), 7);
+        assert_eq!(
+            edited
+                .get_chunk(8, 3)
+                .unwrap()
+                .unwrap()
+                .decompress()
+                .unwrap(),
+            vec![10, 1, 2, 3]
+        );
+        for (idx, (old, new)) in region.iter().zip(edited.iter()).enumerate() {
+            let coordinate = ((idx % 32) as u8, (idx / 32) as u8);
+            if [(8, 3), (10, 3), (empty_x, empty_z)].contains(&coordinate) {
+                continue;
+            }
+            assert_eq!(old.unwrap(), new.unwrap());
+        }
+    }
+
+    #[test]
+    fn preserve_offsets_from() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut writer = RegionWriter::from_region(&region).unwrap();
+        writer.preserve_offsets_from(&region);
+
+        let entry_of = |buf: &[u8], (x, z): (u8, u8)| {
+            let offset = RegionReader::chunk_offset(x as usize, z as usize).unwrap();
+            [
+                buf[offset],
+                buf[offset + 1],
+                buf[offset + 2],
+                buf[offset + 3],
+            ]
+        };
+        let present = writer
+            .chunks
+            .iter()
+            .map(|c| c.coordinate)
+            .collect::<Vec<_>>();
+        let (shrunk, grown) = (present[0], present[1]);
+        let (_, grown_count) = layout::decode_location(entry_of(REGION, grown));
+
+        writer
+            .push_chunk_with_compression(&[10, 1, 2, 3], shrunk, CompressionType::Zlib)
+            .unwrap();
+        let large = (0..grown_count * SECTOR_SIZE + 1)
+            .map(|i| (i * 7 % 256) as u8)
+            .collect::<Vec<_>>();
+        writer
+            .push_chunk_with_compression(&large, grown, CompressionType::Uncompressed)
+            .unwrap();
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+
+        for &coordinate in &present[2..] {
+            assert_eq!(entry_of(&buf, coordinate), entry_of(REGION, coordinate));
+        }
+        let (old_sector, _) = layout::decode_location(entry_of(REGION, shrunk));
+        assert_eq!(
+            layout::decode_location(entry_of(&buf, shrunk)),
+            (old_sector, 1)
+        );
+        assert_ne!(entry_of(&buf, grown), entry_of(REGION, grown));
+
+        // every payload has sectors of its own, all inside the file
+        let mut used = SectorMap::new(0);
+        for &coordinate in &present {
+            let (sector, count) = layout::decode_location(entry_of(&buf, coordinate));
+            assert!(used.is_free(sector, count), "{coordinate:?} overlaps");
+            assert!((sector + count) * SECTOR_SIZE <= buf.len());
+            used.mark(sector, count, true);
+        }
+
+        let written = RegionReader::new(&buf).unwrap();
+        let decompressed = |(x, z): (u8, u8)| {
+            written
+                .get_chunk(x as usize, z as usize)
+                .unwrap()
+                .unwrap()
+                .decompress()
+                .unwrap()
+        };
+        assert_eq!(decompressed(shrunk), vec![10, 1, 2, 3]);
+        assert_eq!(decompressed(grown), large);
+    }
+
+    #[test]
+    fn reserve_chunk_slack() {
+        let entry_of = |buf: &[u8], (x, z): (usize, usize)| {
+            let offset = RegionReader::chunk_offset(x, z).unwrap();
+            layout::decode_location([
+                buf[offset],
+                buf[offset + 1],
+                buf[offset + 2],
+                buf[offset + 3],
+            ])
+        };
+        let small = vec![7; 100];
+        let grown = vec![8; SECTOR_SIZE * 2];
+
+        let mut writer = RegionWriter::with_fixed_timestamp(1);
+        writer.set_strict_vanilla(true);
+        writer.reserve_chunk((1, 0), 2).unwrap();
+        assert!(writer.reserve_chunk((32, 0), 1).is_err());
+        for x in 0..3 {
+            writer
+                .push_chunk_with_compression(&small, (x, 0), CompressionType::Uncompressed)
+                .unwrap();
+        }
+        assert_eq!(writer.estimated_len(), HEADER_SIZE + SECTOR_SIZE * 5);
+
+        let mut buf = vec![];
+        let summary = writer.write(&mut buf).unwrap();
+        assert_eq!(buf.len(), writer.estimated_len());
+        assert_eq!(summary.sectors_used, 5);
+        assert_eq!(summary.padding_bytes, SECTOR_SIZE * 5 - 3 * 105);
+        assert_eq!(entry_of(&buf, (1, 0)), (3, 3));
+        assert_eq!(entry_of(&buf, (2, 0)), (6, 1));
+        assert!(buf[3 * SECTOR_SIZE + 105..6 * SECTOR_SIZE]
+            .iter()
+            .all(|b| *b == 0));
+        assert_eq!(compat::conformance_issue(&buf), None);
+
+        let mut into = vec![0xff; buf.len()];
+        assert_eq!(writer.write_into(&mut into).unwrap(), buf.len());
+        assert_eq!(into, buf);
+        assert_eq!(writer.layout().unwrap().get(1, 0).unwrap().sector_count, 3);
+
+        let region = RegionReader::new(&buf).unwrap();
+        assert_eq!(region.get_chunk(1, 0).unwrap().unwrap().raw_data, small);
+
+        // the grown chunk still fits its slack, so it isn't moved
+        let mut update = RegionWriter::from_region(&region).unwrap();
+        update.preserve_offsets_from(&region);
+        update.reserve_chunk((1, 0), 2).unwrap();
+        update
+            .push_chunk_with_compression(&grown, (1, 0), CompressionType::Uncompressed)
+            .unwrap();
+        let mut updated = vec![];
+        update.write(&mut updated).unwrap();
+        assert_eq!(updated.len(), buf.len());
+        assert_eq!(entry_of(&updated, (1, 0)), (3, 3));
+        assert_eq!(compat::conformance_issue(&updated), None);
+        let region = RegionReader::new(&updated).unwrap();
+        assert_eq!(region.get_chunk(1, 0).unwrap().unwrap().raw_data, grown);
+        assert_eq!(region.get_chunk(2, 0).unwrap().unwrap().raw_data, small);
+
+        // growing past the slack gives it up, the chunk is placed like any other
+        update
+            .push_chunk_with_compression(
+                &vec![9; SECTOR_SIZE * 3],
+                (1, 0),
+                CompressionType::Uncompressed,
+            )
+            .unwrap();
+        updated.clear();
+        update.write(&mut updated).unwrap();
+        assert_eq!(entry_of(&updated, (1, 0)), (7, 6));
+        assert_eq!(entry_of(&updated, (2, 0)), (6, 1));
+    }
+
+    #[test]
+    fn trim_older_than() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut writer = RegionWriter::from_region(&region).unwrap();
+
+        let timestamps = (0..1024)
+            .map(|i| (i % 32, i / 32))
+            .filter(|(x, z)| region.get_chunk(*x, *z).unwrap().is_some())
+            .map(|(x, z)| ((x, z), region.get_chunk_timestamp(x, z)))
+            .collect::<Vec<_>>();
+        let min = timestamps.iter().map(|(_, t)| *t).min().unwrap();
+        let max = timestamps.iter().map(|(_, t)| *t).max().unwrap();
+        assert!(min < max);
+        let threshold = min + (max - min) / 2;
+
+        writer.trim_older_than(threshold);
+        let expected = timestamps
+            .iter()
+            .filter(|(_, t)| *t >= threshold)
+            .map(|(c, _)| *c)
+            .collect::<Vec<_>>();
+        assert_eq!(writer.len(), expected.len());
+        assert!(expected.len() < timestamps.len());
+
+        let mut buf = vec![];
+        let summary = writer.write(&mut buf).unwrap();
+        assert_eq!(summary.chunks_written, expected.len());
+
+        let trimmed = RegionReader::new(&buf).unwrap();
+        for z in 0..32 {
+            for x in 0..32 {
+                let kept = expected.contains(&(x, z));
+                assert_eq!(trimmed.get_chunk(x, z).unwrap().is_some(), kept);
+                if kept {
+                    assert_eq!(
+                        trimmed.get_chunk(x, z).unwrap(),
+                        region.get_chunk(x, z).unwrap()
+                    );
+                } else {
+                    assert_eq!(trimmed.get_chunk_timestamp(x, z), 0);
+                }
+            }
+        }
+
+        writer.retain(|(x, _), c| x % 2 == 0 && c.timestamp >= threshold);
+        assert!(writer.chunks.iter().all(|c| c.coordinate.0 % 2 == 0));
+    }
+
+    #[test]
+    fn deduplicate_identical_payloads() {
+        let mut writer = RegionWriter::new();
+        writer.set_deduplicate(true);
+
+        let raw = vec![10; 2000];
+        for i in 0..100u8 {
+            writer
+                .push_chunk_with_timestamp(&raw, (i % 32, i / 32), CompressionType::Zlib, i as u32)
+                .unwrap();
+        }
+        // same bytes under another compression type must not share
+        writer
+            .push_precompressed_chunk(
+                writer.chunks[0].payload().to_vec(),
+                CompressionType::LZ4,
+                (0, 31),
+                0,
+            )
+            .unwrap();
+
+        let estimate = writer.estimated_len();
+        let mut buf = vec![];
+        let summary = writer.write(&mut buf).unwrap();
+        assert_eq!(buf.len(), HEADER_SIZE + SECTOR_SIZE * 2);
+        assert_eq!(estimate, buf.len());
+        assert_eq!(summary.chunks_written, 101);
+        assert_eq!(summary.sectors_used, 2);
+
+        let region = RegionReader::new(&buf).unwrap();
+        for i in 0..100usize {
+            let chunk = region.get_chunk(i % 32, i / 32).unwrap().unwrap();
+            assert_eq!(chunk.decompress().unwrap(), raw);
+            assert_eq!(region.get_chunk_timestamp(i % 32, i / 32), i as u32);
+        }
+        assert_eq!(
+            region
+                .get_chunk(0, 31)
+                .unwrap()
+                .unwrap()
+                .get_compression_type(),
+            CompressionType::LZ4
+        );
+
+        writer.set_deduplicate(false);
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        assert_eq!(buf.len(), HEADER_SIZE + SECTOR_SIZE * 101);
+    }
+
+    #[test]
+    fn timestamp_providers() {
+        let build = |mut writer: RegionWriter| {
+            writer.push_chunk(&[10, 1], (0, 0)).unwrap();
+            writer
+                .push_chunk_with_compression(&[10, 2], (1, 0), CompressionType::LZ4)
+                .unwrap();
+            writer.push_chunks(&[(&[10, 3], (2, 0))]).unwrap();
+
+            let mut buf = vec![];
+            writer.write(&mut buf).unwrap();
+            buf
+        };
+
+        let a = build(RegionWriter::with_fixed_timestamp(1234));
+        let b = build(RegionWriter::with_fixed_timestamp(1234));
+        assert_eq!(a, b);
+        let region = RegionReader::new(&a).unwrap();
+        for x in 0..3 {
+            assert_eq!(region.get_chunk_timestamp(x, 0), 1234);
+        }
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let buf = build(RegionWriter::with_timestamp_provider(move || {
+            counter.fetch_add(1, Ordering::Relaxed) as u32 + 100
+        }));
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+        let region = RegionReader::new(&buf).unwrap();
+        for x in 0..3 {
+            assert_eq!(region.get_chunk_timestamp(x, 0), 100 + x as u32);
+        }
+
+        // providers can't be compared, not even with a clone of themselves
+        assert_eq!(
+            RegionWriter::with_fixed_timestamp(5),
+            RegionWriter::with_fixed_timestamp(5)
+        );
+        let provided = RegionWriter::with_timestamp_provider(|| 5);
+        assert_ne!(provided, provided.clone());
+    }
+
+    #[test]
+    fn per_chunk_overrides() {
+        let mut writer = RegionWriter::new();
+        for x in 0..3 {
+            writer.push_chunk(&[10, x, x, x], (x, 0)).unwrap();
+        }
+
+        writer
+            .set_compression((1, 0), CompressionType::Uncompressed)
+            .unwrap();
+        writer
+            .set_compression((2, 0), CompressionType::LZ4)
+            .unwrap();
+        assert!(matches!(
+            writer.set_compression((5, 5), CompressionType::LZ4),
+            Err(McaError::NotGenerated)
+        ));
+        assert_eq!(
+            writer.get_chunk((1, 0)).unwrap().payload(),
+            vec![10, 1, 1, 1]
+        );
+
+        writer.get_chunk_mut((0, 0)).unwrap().timestamp = 99;
+        assert!(writer.get_chunk_mut((0, 1)).is_none());
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        let region = RegionReader::new(&buf).unwrap();
+
+        let types = [
+            CompressionType::Zlib,
+            CompressionType::Uncompressed,
+            CompressionType::LZ4,
+        ];
+        for (x, compression) in types.into_iter().enumerate() {
+            let chunk = region.get_chunk(x, 0).unwrap().unwrap();
+            assert_eq!(chunk.get_compression_type(), compression);
+            assert_eq!(
+                chunk.decompress().unwrap(),
+                vec![10, x as u8, x as u8, x as u8]
+            );
+        }
+        assert_eq!(region.get_chunk_timestamp(0, 0), 99);
+    }
+
+    #[test]
+    fn scratch_compression() {
+        let region = RegionReader::new(REGION).unwrap();
+        let raw = region
+            .iter()
+            .flatten()
+            .flatten()
+            .take(64)
+            .map(|c| c.decompress().unwrap())
+            .collect::<Vec<_>>();
+
+        let mut writer = RegionWriter::with_capacity(1024);
+        let types = [
+            CompressionType::Zlib,
+            CompressionType::LZ4,
+            CompressionType::Uncompressed,
+        ];
+        for (i, data) in raw.iter().enumerate() {
+            let compression = types[i % 3].clone();
+            let level = CompressionLevel::Custom(i as u8 % 11);
+            assert_eq!(
+                writer.scratch.compress(&compression, data, level).unwrap(),
+                compression.compress_with_level(data, level).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn owned_and_borrowed_pushes() {
+        let region = RegionReader::new(REGION).unwrap();
+        let raw = region
+            .iter()
+            .flatten()
+            .flatten()
+            .take(32)
+            .map(|c| c.decompress().unwrap())
+            .collect::<Vec<_>>();
+        let types = [
+            CompressionType::Zlib,
+            CompressionType::LZ4,
+            CompressionType::Uncompressed,
+        ];
+
+        let mut borrowed = RegionWriter::with_fixed_timestamp(5);
+        let mut owned = RegionWriter::with_fixed_timestamp(5);
+        for (i, data) in raw.iter().enumerate() {
+            let coordinate = (i as u8, 0);
+            let compression = types[i % 3].clone();
+            borrowed
+                .push_chunk_with_timestamp(data, coordinate, compression.clone(), 5)
+                .unwrap();
+            owned
+                .push_chunk_owned_with_timestamp(data.clone(), coordinate, compression, 5)
+                .unwrap();
+        }
+        borrowed.push_chunk(&raw[0], (0, 1)).unwrap();
+        owned.push_chunk_owned(raw[0].clone(), (0, 1)).unwrap();
+
+        let (mut a, mut b) = (vec![], vec![]);
+        borrowed.write(&mut a).unwrap();
+        owned.write(&mut b).unwrap();
+        assert_eq!(a, b);
+
+        // uncompressed data is moved, not copied
+        let data = raw[2].clone();
+        let ptr = data.as_ptr();
+        owned
+            .push_chunk_owned_with_timestamp(data, (5, 5), CompressionType::Uncompressed, 0)
+            .unwrap();
+        assert_eq!(owned.get_chunk((5, 5)).unwrap().payload().as_ptr(), ptr);
+    }
+
+    #[cfg(feature = "fastnbt")]
+    #[test]
+    fn validate_positions() {
+        let region = RegionReader::new(REGION).unwrap();
+        let raw = region
+            .get_chunk(8, 3)
+            .unwrap()
+            .unwrap()
+            .decompress()
+            .unwrap();
+        let (x, z) = crate::nbt::chunk_position(&raw).unwrap();
+        let region_pos = RegionPos::new(x.div_euclid(32), z.div_euclid(32));
+        let coordinate = (x.rem_euclid(32) as u8, z.rem_euclid(32) as u8);
+        assert_eq!(coordinate, (8, 3));
+
+        let mut writer = RegionWriter::new();
+        writer.validate_positions(region_pos);
+        writer.push_chunk(&raw, coordinate).unwrap();
+
+        let err = writer.push_chunk(&raw, (9, 3)).unwrap_err();
+        assert!(matches!(
+            err,
+            McaError::PositionMismatch { expected, found } if expected == (x + 1, z) && found == (x, z)
+        ));
+        assert!(writer.push_chunks(&[(&raw, (0, 0))]).is_err());
+        assert!(matches!(
+            writer.push_chunk(&[10, 0, 0, 0], (0, 0)),
+            Err(McaError::InvalidChunkPayload(_))
+        ));
+
+        // relocated on purpose
+        writer.skip_position_validation();
+        writer.push_chunk(&raw, (9, 3)).unwrap();
+        assert_eq!(writer.len(), 2);
+    }
+
+    #[test]
+    fn pending_chunk_builder() {
+        let raw = [10, 1, 2, 3];
+        let before = RegionWriter::get_current_timestamp().unwrap();
+
+        let defaulted = PendingChunk::builder(&raw)
+            .coordinate(1, 2)
+            .build()
+            .unwrap();
+        assert_eq!(defaulted.compression, CompressionType::Zlib);
+        assert!(defaulted.timestamp >= before);
+
+        let specified = PendingChunk::builder(&raw)
+            .coordinate(3, 4)
+            .compression(CompressionType::Zlib)
+            .level(CompressionLevel::Best)
+            .timestamp(77)
+            .build()
+            .unwrap();
+        assert_eq!(
+            specified.payload(),
+            CompressionType::Zlib
+                .compress_with_level(&raw, CompressionLevel::Best)
+                .unwrap()
+        );
+
+        let precompressed = PendingChunk::precompressed_builder(raw.to_vec())
+            .coordinate(5, 6)
+            .compression(CompressionType::Uncompressed)
+            .timestamp(78)
+            .build()
+            .unwrap();
+        assert_eq!(precompressed.payload(), raw);
+
+        assert!(matches!(
+            PendingChunk::builder(&raw).build(),
+            Err(McaError::InvalidChunkPayload(_))
+        ));
+        assert!(matches!(
+            PendingChunk::builder(&raw).coordinate(32, 0).build(),
+            Err(McaError::InvalidCoordinate { x: 32, z: 0 })
+        ));
+
+        let mut writer = RegionWriter::new();
+        for chunk in [defaulted, specified, precompressed] {
+            writer.push_pending_chunk(chunk).unwrap();
+        }
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+
+        let region = RegionReader::new(&buf).unwrap();
+        for (x, z) in [(1, 2), (3, 4), (5, 6)] {
+            let chunk = region.get_chunk(x, z).unwrap().unwrap();
+            assert_eq!(chunk.decompress().unwrap(), raw);
+        }
+        assert_eq!(region.get_chunk_timestamp(3, 4), 77);
+        assert_eq!(
+            region
+                .get_chunk(5, 6)
+                .unwrap()
+                .unwrap()
+                .get_compression_type(),
+            CompressionType::Uncompressed
+        );
+    }
+
+    #[test]
+    fn collect_and_extend() {
+        let region = RegionReader::new(REGION).unwrap();
+        let writer = region
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, chunk)| {
+                let chunk = chunk.unwrap()?;
+                let coordinate = ((idx % 32) as u8, (idx / 32) as u8);
+                let timestamp = region.get_chunk_timestamp(idx % 32, idx / 32);
+                Some(PendingChunk::from_raw_chunk(&chunk, coordinate, timestamp))
+            })
+            .collect::<RegionWriter>();
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        let written = RegionReader::new(&buf).unwrap();
+        for (idx, chunk) in region.iter().enumerate() {
+            let (x, z) = (idx % 32, idx / 32);
+            let copied = written.get_chunk(x, z).unwrap();
+            match chunk.unwrap() {
+                Some(chunk) => assert_eq!(copied.unwrap().raw_data, chunk.raw_data),
+                None => assert!(copied.is_none()),
+            }
+            assert_eq!(
+                written.get_chunk_timestamp(x, z),
+                region.get_chunk_timestamp(x, z)
+            );
+        }
+
+        let raw = [10, 1, 2, 3];
+        let mut strict = RegionWriter::new();
+        strict.set_duplicate_policy(DuplicatePolicy::Error);
+        strict.extend([
+            PendingChunk::from_compressed(raw.to_vec(), CompressionType::Uncompressed, 1, (0, 0)),
+            PendingChunk::from_compressed(vec![], CompressionType::Uncompressed, 2, (0, 0)),
+            PendingChunk::from_compressed(vec![], CompressionType::Uncompressed, 3, (32, 0)),
+        ]);
+        assert_eq!(strict.len(), 1);
+        assert_eq!(strict.get_chunk((0, 0)).unwrap().timestamp, 1);
+
+        strict
+            .try_extend([((1, 0), &raw[..]), ((2, 0), &raw[..])])
+            .unwrap();
+        assert_eq!(strict.len(), 3);
+        assert!(matches!(
+            strict.try_extend([((3, 0), &raw[..]), ((1, 0), &raw[..]), ((4, 0), &raw[..])]),
+            Err(McaError::DuplicateChunk { x: 1, z: 0 })
+        ));
+        assert!(strict.contains((3, 0)));
+        assert!(!strict.contains((4, 0)));
+    }
+
+    #[test]
+    fn copy_chunk_between_regions() {
+        let region = RegionReader::new(REGION).unwrap();
+        let present = (0..1024)
+            .map(crate::RegionIter::get_chunk_coordinate)
+            .filter(|&(x, z)| region.get_chunk(x, z).unwrap().is_some())
+            .collect::<Vec<_>>();
+        let empty = (0..1024)
+            .map(crate::RegionIter::get_chunk_coordinate)
+            .find(|c| !present.contains(c))
+            .unwrap();
+
+        let mut writer = RegionWriter::new();
+        for (i, &src) in present.iter().take(12).enumerate() {
+            assert!(writer.copy_chunk_from(&region, src, (i as u8, 31)).unwrap());
+        }
+        assert!(!writer.copy_chunk_from(&region, empty, (0, 0)).unwrap());
+        assert!(matches!(
+            writer.copy_chunk_from(&region, (32, 0), (0, 0)),
+            Err(McaError::InvalidCoordinate { x: 32, z: 0 })
+        ));
+        assert_eq!(writer.len(), 12);
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        let copied = RegionReader::new(&buf).unwrap();
+        for (i, &(x, z)) in present.iter().take(12).enumerate() {
+            let source = region.get_chunk(x, z).unwrap().unwrap();
+            let chunk = copied.get_chunk(i, 31).unwrap().unwrap();
+            assert_eq!(chunk.raw_data, source.raw_data);
+            assert_eq!(chunk.get_compression_type(), source.get_compression_type());
+            assert_eq!(
+                copied.get_chunk_timestamp(i, 31),
+                region.get_chunk_timestamp(x, z)
+            );
+        }
+    }
+
+    #[test]
+    fn set_timestamps_after_push() {
+        let mut writer = RegionWriter::with_fixed_timestamp(1);
+        for coordinate in [(0, 0), (31, 0), (4, 17), (9, 9)] {
+            writer.push_chunk(&[10, 0, 0, 0], coordinate).unwrap();
+        }
+
+        assert!(writer.set_timestamp((0, 0), 100));
+        assert!(writer.set_timestamp((31, 0), 200));
+        assert!(writer.set_timestamp((4, 17), 300));
+        assert!(!writer.set_timestamp((5, 5), 400));
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        let region = RegionReader::new(&buf).unwrap();
+        assert_eq!(region.get_chunk_timestamp(0, 0), 100);
+        assert_eq!(region.get_chunk_timestamp(31, 0), 200);
+        assert_eq!(region.get_chunk_timestamp(4, 17), 300);
+        assert_eq!(region.get_chunk_timestamp(9, 9), 1);
+        assert_eq!(region.get_chunk_timestamp(5, 5), 0);
+
+        writer.set_all_timestamps(0);
+        assert!(writer.chunks.iter().all(|c| c.timestamp == 0));
+    }
+
+    #[test]
+    fn inspect_pending_chunks() {
+        let mut writer = RegionWriter::with_fixed_timestamp(1);
+        let coordinates = [(0, 0), (3, 1), (7, 30)];
+        for coordinate in coordinates {
+            writer
+                .push_chunk_with_compression(
+                    &[10, 0, 0, 0],
+                    coordinate,
+                    CompressionType::Uncompressed,
+                )
+                .unwrap();
+        }
+
+        assert_eq!(writer.chunks().count(), 3);
+        assert_eq!(
+            writer.chunks().map(|c| c.coordinate).collect::<Vec<_>>(),
+            coordinates
+        );
+        assert!(writer.chunks().all(|c| c.payload().len() == 4));
+
+        for chunk in writer.chunks_mut() {
+            let x = chunk.coordinate.0;
+            chunk.compressed_data_mut().unwrap().push(x);
+            chunk.timestamp = 50;
+        }
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        let region = RegionReader::new(&buf).unwrap();
+        for (x, z) in coordinates {
+            let chunk = region.get_chunk(x as usize, z as usize).unwrap().unwrap();
+            assert_eq!(chunk.raw_data, [10, 0, 0, 0, x]);
+            assert_eq!(region.get_chunk_timestamp(x as usize, z as usize), 50);
+        }
+    }
+
+    #[test]
+    fn layout_matches_written() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut writer = RegionWriter::from_region(&region).unwrap();
+
+        for layout in [
+            LayoutStrategy::CoordinateOrder,
+            LayoutStrategy::SizeDescending,
+        ] {
+            writer.set_layout(layout);
+            let predicted = writer.layout().unwrap();
+
+            let mut buf = vec![];
+            writer.write(&mut buf).unwrap();
+            assert_eq!(predicted.file_len(), buf.len());
+            assert_eq!(predicted.iter().count(), writer.len());
+            for idx in 0..CHUNK_COUNT {
+                let (x, z) = crate::RegionIter::get_chunk_coordinate(idx);
+                let entry = predicted
+                    .get(x, z)
+                    .map(|p| p.location_entry())
+                    .unwrap_or([0; 4]);
+                assert_eq!(entry, buf[idx * 4..idx * 4 + 4]);
+            }
+        }
+
+        writer.preserve_layout(&region);
+        writer.remove_chunk(writer.chunks[0].coordinate);
+        let predicted = writer.layout().unwrap();
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+        assert_eq!(predicted.file_len(), buf.len());
+        for ((x, z), placement) in predicted.iter() {
+            let offset = layout::header_offset_for(x, z);
+            assert_eq!(placement.location_entry(), buf[offset..offset + 4]);
+            assert_eq!(placement.padded_len, placement.sector_count * SECTOR_SIZE);
+        }
+    }
+
+    #[test]
+    fn write_with_layout() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut writer = RegionWriter::from_region(&region).unwrap();
+        writer.set_deduplicate(true);
+        writer
+            .push_precompressed_chunk(vec![1; 10], CompressionType::Uncompressed, (0, 0), 1)
+            .unwrap();
+        writer
+            .push_precompressed_chunk(vec![1; 10], CompressionType::Uncompressed, (31, 31), 1)
+            .unwrap();
+
+        for strategy in [
+            LayoutStrategy::CoordinateOrder,
+            LayoutStrategy::SizeDescending,
+        ] {
+            writer.set_layout(strategy);
+            let layout = writer.layout().unwrap();
+            let mut expected = vec![];
+            let summary = writer.write(&mut expected).unwrap();
+
+            let mut buf = vec![];
+            assert_eq!(
+                writer.write_with_layout(&layout, &mut buf).unwrap(),
+                summary
+            );
+            assert_eq!(buf, expected);
+        }
+
+        // a layout planned for other chunks is rejected before anything is written
+        let layout = writer.layout().unwrap();
+        let mut buf = vec![];
+        let mut grown = writer.clone();
+        grown
+            .push_precompressed_chunk(
+                vec![1; SECTOR_SIZE],
+                CompressionType::Uncompressed,
+                (0, 0),
+                1,
+            )
+            .unwrap();
+        assert!(matches!(
+            grown.write_with_layout(&layout, &mut buf),
+            Err(McaError::LayoutMismatch { x: 0, z: 0 })
+        ));
+        let mut shrunk = writer.clone();
+        shrunk.remove_chunk((31, 31));
+        assert!(matches!(
+            shrunk.write_with_layout(&layout, &mut buf),
+            Err(McaError::LayoutMismatch { x: 31, z: 31 })
+        ));
+        assert!(buf.is_empty());
+
+        // placements moved around by hand are written where they say, overlaps are rejected
+        let mut writer = RegionWriter::with_fixed_timestamp(1);
+        writer.push_chunk(&[10, 1, 0, 0], (1, 0)).unwrap();
+        writer.push_chunk(&[10, 2, 0, 0], (2, 0)).unwrap();
+        let entries = |first: [u8; 4], second: [u8; 4]| {
+            let mut locations = [[0; 4]; CHUNK_COUNT];
+            locations[1] = first;
+            locations[2] = second;
+            locations
+        };
+        let moved = Layout::from_locations(
+            entries([0, 0, 5, 1], [0, 0, 2, 2]).into_iter(),
+            6 * SECTOR_SIZE,
+        );
+        let mut buf = vec![];
+        writer.write_with_layout(&moved, &mut buf).unwrap();
+        assert_eq!(buf.len(), 6 * SECTOR_SIZE);
+        let written = RegionReader::new(&buf).unwrap();
+        assert_eq!(written.get_location(4), Some([0, 0, 5, 1]));
+        assert_eq!(
+            written
+                .get_chunk(2, 0)
+                .unwrap()
+                .unwrap()
+                .decompress()
+                .unwrap(),
+            [10, 2, 0, 0]
+        );
+        assert!(buf[4 * SECTOR_SIZE..5 * SECTOR_SIZE]
+            .iter()
+            .all(|b| *b == 0));
+
+        for (first, second, len) in [
+            ([0, 0, 2, 1], [0, 0, 2, 1], 3),
+            ([0, 0, 1, 1], [0, 0, 2, 1], 3),
+            ([0, 0, 2, 1], [0, 0, 3, 1], 3),
+        ] {
+            let layout =
+                Layout::from_locations(entries(first, second).into_iter(), len * SECTOR_SIZE);
+            assert!(matches!(
+                writer.write_with_layout(&layout, &mut vec![]),
+                Err(McaError::InvalidAllocation { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn validate_issues() {
+        let raw = [10, 0, 0, 0];
+        let mut writer = RegionWriter::with_fixed_timestamp(0);
+        writer.push_chunk(&raw, (0, 0)).unwrap();
+        writer.push_chunk(&raw, (1, 0)).unwrap();
+        assert_eq!(writer.validate(), Ok(()));
+
+        writer.set_forbid_zero_timestamps(true);
+        assert_eq!(
+            writer.validate(),
+            Err(vec![
+                WriterIssue::ZeroTimestamp { x: 0, z: 0 },
+                WriterIssue::ZeroTimestamp { x: 1, z: 0 },
+            ])
+        );
+        writer.set_all_timestamps(1);
+        assert_eq!(writer.validate(), Ok(()));
+
+        // every write path fails the same way instead of writing two chunks into one slot
+        let writes_fail = |writer: &RegionWriter, expected: fn(&McaError) -> bool| {
+            assert!(expected(&writer.write(&mut vec![]).unwrap_err()));
+            assert!(expected(&writer.write_into(&mut [0; 1 << 16]).unwrap_err()));
+            assert!(expected(&writer.layout().unwrap_err()));
+            assert!(expected(&writer.clone().into_bytes().unwrap_err()));
+            assert!(expected(
+                &writer
+                    .write_seek(&mut std::io::Cursor::new(vec![]))
+                    .unwrap_err()
+            ));
+        };
+
+        writer.chunks_mut().nth(1).unwrap().coordinate = (0, 0);
+        assert_eq!(
+            writer.validate(),
+            Err(vec![WriterIssue::DuplicateCoordinate { x: 0, z: 0 }])
+        );
+        writes_fail(&writer, |e| {
+            matches!(e, McaError::DuplicateChunk { x: 0, z: 0 })
+        });
+
+        writer.chunks_mut().nth(1).unwrap().coordinate = (32, 0);
+        assert_eq!(
+            writer.validate(),
+            Err(vec![WriterIssue::InvalidCoordinate { x: 32, z: 0 }])
+        );
+        writes_fail(&writer, |e| {
+            matches!(e, McaError::InvalidCoordinate { x: 32, z: 0 })
+        });
+        writer.preserve_layout(&RegionReader::new(REGION).unwrap());
+        writes_fail(&writer, |e| {
+            matches!(e, McaError::InvalidCoordinate { x: 32, z: 0 })
+        });
+        writer = RegionWriter::with_fixed_timestamp(1);
+        writer.push_chunk(&raw, (0, 0)).unwrap();
+
+        writer
+            .push_precompressed_chunk(
+                vec![0; 255 * SECTOR_SIZE],
+                CompressionType::Uncompressed,
+                (2, 0),
+                1,
+            )
+            .unwrap();
+        assert_eq!(
+            writer.validate(),
+            Err(vec![WriterIssue::ChunkTooLarge {
+                x: 2,
+                z: 0,
+                sectors: 256
+            }])
+        );
+        assert!(matches!(
+            writer.write(&mut vec![]),
+            Err(McaError::ChunkTooLarge { x: 2, z: 0, .. })
+        ));
+    }
+
+    #[cfg(feature = "fastnbt")]
+    #[test]
+    fn validate_positions_issues() {
+        let region = RegionReader::new(REGION).unwrap();
+        let raw = region
+            .get_chunk(8, 3)
+            .unwrap()
+            .unwrap()
+            .decompress()
+            .unwrap();
+        let (x, z) = crate::nbt::chunk_position(&raw).unwrap();
+
+        let mut writer = RegionWriter::new();
+        writer.push_chunk(&raw, (8, 3)).unwrap();
+        writer.push_chunk(&raw, (9, 3)).unwrap();
+        writer.push_chunk(&[10, 0, 0, 0], (0, 0)).unwrap();
+        assert_eq!(writer.validate(), Ok(()));
+
+        writer.validate_positions(RegionPos::new(x.div_euclid(32), z.div_euclid(32)));
+        let issues = writer.validate().unwrap_err();
+        assert_eq!(issues.len(), 2);
+        assert_eq!(
+            issues[0],
+            WriterIssue::PositionMismatch {
+                x: 9,
+                z: 3,
+                expected: (x + 1, z),
+                found: (x, z)
+            }
+        );
+        assert!(matches!(
+            issues[1],
+            WriterIssue::UnreadableNbt { x: 0, z: 0, .. }
+        ));
+    }
+
+    /// A sink accepting at most one byte per write, with every other call interrupted
+    struct ShortWrites<W> {
+        inner: W,
+        calls: usize,
+    }
+
+    impl<W: Write> Write for ShortWrites<W> {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.calls += 1;
+            if self.calls.is_multiple_of(2) {
+                return Err(std::io::ErrorKind::Interrupted.into());
+            }
+            self.inner.write(&buf[..buf.len().min(1)])
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    impl<W: Seek> Seek for ShortWrites<W> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn short_writes() {
+        let mut writer = RegionWriter::with_fixed_timestamp(1724372177);
+        for (i, len) in [0, 1, 4091, 4092, 9000].into_iter().enumerate() {
+            let data = (0..len).map(|b| (b % 251) as u8).collect::<Vec<_>>();
+            writer
+                .push_chunk_with_compression(&data, (i as u8, 3), CompressionType::Uncompressed)
+                .unwrap();
+        }
+        let mut expected = vec![];
+        writer.write(&mut expected).unwrap();
+
+        let mut short = ShortWrites {
+            inner: vec![],
+            calls: 0,
+        };
+        writer.write(&mut short).unwrap();
+        assert_eq!(short.inner, expected);
+
+        let mut short = ShortWrites {
+            inner: std::io::Cursor::new(vec![]),
+            calls: 0,
+        };
+        writer.write_seek(&mut short).unwrap();
+        assert_eq!(short.inner.into_inner(), expected);
+
+        let mut stream = crate::RegionStreamWriter::new(ShortWrites {
+            inner: std::io::Cursor::new(vec![]),
+            calls: 0,
+        })
+        .unwrap();
+        for chunk in writer.chunks() {
+            stream.push_pending_chunk(chunk).unwrap();
+        }
+        assert_eq!(stream.finish().unwrap().inner.into_inner(), expected);
+    }
+
+    #[test]
+    fn out_of_range_coordinates() {
+        let raw = [10, 1, 2, 3];
+        let mut writer = RegionWriter::new();
+
+        assert!(matches!(
+            writer.push_chunk(&raw, (32, 0)),
+            Err(McaError::InvalidCoordinate { x: 32, z: 0 })
+        ));
+        assert!(matches!(
+            writer.push_precompressed_chunk(
+                raw.to_vec(),
+                CompressionType::Uncompressed,
+                (0, 40),
+                0
+            ),
+            Err(McaError::InvalidCoordinate { x: 0, z: 40 })
+        ));
+        assert!(matches!(
+            PendingChunk::new(&raw, CompressionType::Zlib, 0, (32, 0)),
+            Err(McaError::InvalidCoordinate { x: 32, z: 0 })
+        ));
+        assert!(writer.is_empty());
+    }
+
+    #[test]
+    fn streamed_matches_buffered() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut writer = RegionWriter::new();
+        let mut expected_payloads = vec![];
+        let mut expected = vec![0; HEADER_SIZE];
+
+        // the data section & header built the old way, one buffer with freshly allocated padding
+        for (idx, chunk) in region.iter().enumerate() {
+            if let Some(chunk) = chunk.unwrap() {
+                let timestamp = 1724372177 + idx as u32;
+                let sector = (HEADER_SIZE + expected_payloads.len()) / SECTOR_SIZE;
+                let data = chunk.raw_data;
+                expected_payloads.extend_from_slice(&(data.len() as u32 + 1).to_be_bytes());
+                expected_payloads.push(chunk.get_compression_type().to_u8());
+                expected_payloads.extend_from_slice(data);
+                let padding = vec![0u8; layout::padding_for(data.len())];
+                expected_payloads.extend_from_slice(&padding);
+                let count = (HEADER_SIZE + expected_payloads.len()) / SECTOR_SIZE - sector;
+
+                let offset = idx * 4;
+                expected[offset..offset + 3].copy_from_slice(&(sector as u32).to_be_bytes()[1..]);
+                expected[offset + 3] = count as u8;
+                expected[SECTOR_SIZE + offset..SECTOR_SIZE + offset + 4]
+                    .copy_from_slice(&timestamp.to_be_bytes());
+
+                writer
+                    .push_pending_chunk(PendingChunk::from_raw_chunk(
+                        &chunk,
+                        ((idx % 32) as u8, (idx / 32) as u8),
+                        timestamp,
+                    ))
+                    .unwrap();
+            }
+        }
+        expected.extend_from_slice(&expected_payloads);
+
+        let mut buf = vec![];
+        let summary = writer.write(&mut buf).unwrap();
+        assert_eq!(buf, expected);
+        assert_eq!(summary.bytes_written, expected.len());
+    }
+
+    #[test]
+    fn write_seek_matches_write() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut writer = RegionWriter::new();
+
+        for (idx, chunk) in region.iter().enumerate() {
+            if let Some(chunk) = chunk.unwrap() {
+                writer
+                    .push_pending_chunk(PendingChunk::from_raw_chunk(
+                        &chunk,
+                        ((idx % 32) as u8, (idx / 32) as u8),
+                        1724372177,
+                    ))
+                    .unwrap();
+            }
+        }
+
+        let mut buf = vec![];
+        writer.write(&mut buf).unwrap();
+
+        // start somewhere other than 0 to make sure the header lands relative to it
+        let mut cursor = std::io::Cursor::new(vec![0xAA; 100]);
+        cursor.seek(SeekFrom::End(0)).unwrap();
+        writer.write_seek(&mut cursor).unwrap();
+
+        let seeked = cursor.into_inner();
+        assert_eq!(&seeked[..100], &[0xAA; 100]);
+        assert_eq!(&seeked[100..], &buf[..]);
+
+        // the header is only written once every payload made it, a cut off write leaves it empty
+        let mut cut = vec![0xAA; buf.len() - 1];
+        assert!(writer
+            .write_seek(&mut std::io::Cursor::new(&mut cut[..]))
+            .is_err());
+        assert!(cut[..HEADER_SIZE].iter().all(|b| *b == 0));
+        assert_eq!(cut[HEADER_SIZE..], buf[HEADER_SIZE..buf.len() - 1]);
     }
 }
@@ -0,0 +1,179 @@
+use std::path::Path;
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use super::{temp_path_for, write_reserved, RegionWriter, WriteSummary};
+use crate::McaError;
+
+impl RegionWriter {
+    /// Writes all chunks into one region file on an async sink, the output is identical to [`write`](RegionWriter::write).  
+    /// Payloads are written one at a time, so only the largest one is ever buffered.
+    ///
+    /// Chunks are compressed when pushed, writing itself only copies bytes around.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// let mut file = tokio::fs::File::create("r.0.0.mca").await?;
+    /// writer.write_async(&mut file).await?;
+    /// ```
+    pub async fn write_async<W>(&self, w: &mut W) -> Result<WriteSummary, McaError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut buf = vec![];
+
+        if self.base.is_some() {
+            // a preserved layout is patched in memory anyway
+            let summary = self.write(&mut buf)?;
+            w.write_all(&buf).await?;
+            w.flush().await?;
+            return Ok(summary);
+        }
+
+        let compressed = self.compress_deferred()?;
+        let header = self.plan_payloads(&compressed, None)?;
+        header.write(&mut buf)?;
+        w.write_all(&buf).await?;
+
+        for (compression, data, sectors) in header.payloads.iter() {
+            buf.clear();
+            write_reserved(&mut buf, *compression, data, *sectors)?;
+            w.write_all(&buf).await?;
+        }
+        w.flush().await?;
+
+        Ok(header.summary)
+    }
+
+    /// The async counterpart of [`write_to_path`](RegionWriter::write_to_path),
+    /// writing to a temporary file next to `path` and renaming it over `path` once complete  
+    /// With the `fs-lock` feature the [`RegionLock`](crate::RegionLock) is waited for on a blocking task.
+    pub async fn write_to_path_async(&self, path: &Path) -> Result<(), McaError> {
+        #[cfg(feature = "fs-lock")]
+        let _lock = {
+            let path = path.to_path_buf();
+            tokio::task::spawn_blocking(move || crate::RegionLock::lock(&path))
+                .await
+                .map_err(std::io::Error::other)??
+        };
+
+        let temp = temp_path_for(path);
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .await?;
+
+        let result = async {
+            self.write_async(&mut tokio::io::BufWriter::new(&mut file))
+                .await?;
+
+            if let Ok(meta) = tokio::fs::metadata(path).await {
+                file.set_permissions(meta.permissions()).await?;
+            }
+            file.sync_all().await?;
+
+            tokio::fs::rename(&temp, path).await?;
+            Ok(())
+        }
+        .await;
+
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&temp).await;
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CompressionType, PendingChunk, RegionReader, RegionWriter};
+
+    const REGION: &[u8] = include_bytes!("../../benches/r.0.0.mca");
+
+    #[tokio::test]
+    async fn matches_sync_write() {
+        let region = RegionReader::new(REGION).unwrap();
+        let mut writer = RegionWriter::from_region(&region).unwrap();
+        for i in 0..40u8 {
+            writer
+                .push_precompressed_chunk(
+                    vec![1; 300],
+                    CompressionType::Uncompressed,
+                    (i % 32, 31 - i / 32),
+                    0,
+                )
+                .unwrap();
+        }
+
+        for deduplicate in [false, true] {
+            writer.set_deduplicate(deduplicate);
+
+            let mut sync = vec![];
+            let summary = writer.write(&mut sync).unwrap();
+            let mut r#async = vec![];
+            assert_eq!(writer.write_async(&mut r#async).await.unwrap(), summary);
+            assert_eq!(sync, r#async);
+        }
+
+        writer.preserve_layout(&region);
+        writer
+            .push_pending_chunk(PendingChunk::from_compressed(
+                vec![10],
+                CompressionType::Uncompressed,
+                0,
+                (8, 3),
+            ))
+            .unwrap();
+        let mut sync = vec![];
+        writer.write(&mut sync).unwrap();
+        let mut r#async = vec![];
+        writer.write_async(&mut r#async).await.unwrap();
+        assert_eq!(sync, r#async);
+    }
+
+    #[tokio::test]
+    async fn write_to_path_async() {
+        let path = std::env::temp_dir().join(format!("mca-async-{}.mca", std::process::id()));
+
+        let mut writer = RegionWriter::new();
+        writer.push_chunk(&[10, 1, 2], (3, 4)).unwrap();
+        writer.write_to_path_async(&path).await.unwrap();
+
+        let mut sync = vec![];
+        writer.write(&mut sync).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), sync);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "fs-lock")]
+    #[tokio::test]
+    async fn write_to_path_async_waits_for_lock() {
+        let path = std::env::temp_dir().join(format!("mca-async-lock-{}.mca", std::process::id()));
+        let lock = crate::RegionLock::lock(&path).unwrap();
+
+        let mut writer = RegionWriter::new();
+        writer.push_chunk(&[10, 1, 2], (3, 4)).unwrap();
+        let write = tokio::spawn({
+            let (writer, path) = (writer.clone(), path.clone());
+            async move { writer.write_to_path_async(&path).await }
+        });
+
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert!(!path.exists());
+
+        drop(lock);
+        write.await.unwrap().unwrap();
+        let mut sync = vec![];
+        writer.write(&mut sync).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), sync);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(crate::RegionLock::lock_path(&path)).unwrap();
+    }
+}
@@ -0,0 +1,107 @@
+//! Allocation counts of the writer, in their own binary since the counting allocator is global.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use mca::{CompressionType, RegionReader, RegionWriter};
+
+const REGION: &[u8] = include_bytes!("../benches/r.0.0.mca");
+
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+// counts allocations & reallocations made on the current thread only
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn allocations<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATIONS.with(|a| a.get());
+    f();
+    ALLOCATIONS.with(|a| a.get()) - before
+}
+
+#[test]
+fn presized_allocations() {
+    let payloads = (0..1024)
+        .map(|i| vec![(i % 251) as u8; 3000])
+        .collect::<Vec<_>>();
+
+    let fill = |writer: &mut RegionWriter, payloads: Vec<Vec<u8>>| {
+        for (i, data) in payloads.into_iter().enumerate() {
+            let coordinate = ((i % 32) as u8, (i / 32) as u8);
+            writer
+                .push_precompressed_chunk(data, CompressionType::Uncompressed, coordinate, 0)
+                .unwrap();
+        }
+    };
+
+    let mut grown = RegionWriter::new();
+    let copy = payloads.clone();
+    let grown_pushes = allocations(|| fill(&mut grown, copy));
+    let mut presized = RegionWriter::with_capacity(1024);
+    let presized_pushes = allocations(|| fill(&mut presized, payloads));
+    assert_eq!(presized_pushes, 0);
+    assert!(grown_pushes > presized_pushes);
+
+    // ordering the chunks & its sort buffer, and the planned payload list
+    let mut buf = Vec::with_capacity(presized.estimated_len());
+    let writes = allocations(|| {
+        presized.write(&mut buf).unwrap();
+    });
+    assert!(writes <= 3, "{writes} allocations");
+    assert_eq!(buf.len(), presized.estimated_len());
+
+    let mut slice = vec![0; buf.len()];
+    let writes = allocations(|| {
+        presized.write_into(&mut slice).unwrap();
+    });
+    assert_eq!(writes, 0);
+    assert_eq!(slice, buf);
+}
+
+#[test]
+fn scratch_allocations() {
+    let region = RegionReader::new(REGION).unwrap();
+    let raw = region
+        .iter()
+        .flatten()
+        .flatten()
+        .take(64)
+        .map(|c| c.decompress().unwrap())
+        .collect::<Vec<_>>();
+
+    let mut writer = RegionWriter::with_capacity(1024);
+    let push_all = |writer: &mut RegionWriter, z: u8| {
+        for (i, data) in raw.iter().enumerate() {
+            writer
+                .push_chunk_with_timestamp(data, ((i % 32) as u8, z), CompressionType::Zlib, 0)
+                .unwrap();
+        }
+    };
+
+    // after warming up, a zlib push only allocates its compressed bytes
+    push_all(&mut writer, 1);
+    let pushes = allocations(|| push_all(&mut writer, 0));
+    assert!(pushes <= raw.len() + 1, "{pushes} allocations");
+}